@@ -1,6 +1,89 @@
 //! MIDI Capability Inquiry defines system exclusive messages for discovering other devices
 //! and managing their connections.
+use core::fmt;
+
 use crate::muid::MUID;
+use crate::universal::DeviceIdentity;
+
+use self::sysex::{Header, Reader, Writer, CI_VERSION, FUNCTION_BLOCK};
+
+pub mod ack;
+pub mod discovery;
+pub mod process;
+pub mod profile;
+pub mod property;
+pub mod protocol;
+pub mod sysex;
+#[cfg(not(feature = "no-std"))]
+mod transaction;
+
+pub use self::ack::{AckStatus, Acknowledged, NotAcknowledged, StatusCode};
+pub use self::discovery::{DiscoveryReply, EndpointInfoInquiry, EndpointInfoReply};
+pub use self::process::{
+    EndOfMessageReport, MessageReportInquiry, MessageReportReply, ProcessCapabilitiesInquiry,
+    ProcessCapabilitiesReply,
+};
+pub use self::profile::{
+    ProfileAdded, ProfileDetailsInquiry, ProfileDetailsReply, ProfileDisabled, ProfileEnabled,
+    ProfileInquiry, ProfileInquiryReply, ProfileList, ProfileRemoved, ProfileSpecificData,
+    SetProfileOff, SetProfileOn,
+};
+pub use self::property::{PropertyCapabilitiesInquiry, PropertyCapabilitiesReply, PropertyData};
+pub use self::protocol::{
+    NewProtocolEstablished, ProtocolList, ProtocolNegotiationReply, TestNewProtocol,
+    TestNewProtocolReply,
+};
+#[cfg(not(feature = "no-std"))]
+pub use self::transaction::{
+    TransactionEvent, TransactionId, TransactionManager, RETRY_DELAY, TRANSACTION_TIMEOUT,
+};
+
+/// Errors encoding or decoding MIDI-CI messages.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Error {
+    /// The message is not a Universal SysEx MIDI-CI message.
+    NotCapabilityInquiry,
+
+    /// The message is a MIDI-CI message of another type, with this sub-ID #2.
+    UnexpectedSubId(u8),
+
+    /// The message ended before all of its fields were read.
+    Truncated,
+
+    /// A data byte had its high bit set.
+    InvalidByte(u8),
+
+    /// A field had a value that is not allowed.
+    InvalidValue,
+
+    /// The buffer was too small to hold the encoded message.
+    BufferTooSmall,
+
+    /// Data uses an encoding that is not supported, eg zlib without the `zlib` feature.
+    UnsupportedEncoding,
+
+    /// Decoded data is larger than allowed, see [MAX_DECODED_LEN](property::MAX_DECODED_LEN).
+    TooLarge,
+
+    /// No unused MUID could be found to replace the local one after a collision.
+    MuidUnavailable,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotCapabilityInquiry => f.write_str("not a MIDI-CI message"),
+            Self::UnexpectedSubId(id) => write!(f, "unexpected MIDI-CI message type {id:#04x}"),
+            Self::Truncated => f.write_str("truncated MIDI-CI message"),
+            Self::InvalidByte(byte) => write!(f, "invalid SysEx data byte {byte:#04x}"),
+            Self::InvalidValue => f.write_str("invalid MIDI-CI field value"),
+            Self::BufferTooSmall => f.write_str("buffer too small for MIDI-CI message"),
+            Self::UnsupportedEncoding => f.write_str("unsupported data encoding"),
+            Self::TooLarge => f.write_str("decoded data too large"),
+            Self::MuidUnavailable => f.write_str("no unused MUID available"),
+        }
+    }
+}
 
 /// Metadata required for MIDI CI Discovery
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -19,24 +102,26 @@ pub struct DeviceDiscovery {
     pub ci_support: u8,
     /// Maximum size of a sysex message the device can receive. At least 128 bytes.
     pub max_sysex_size: u32,
+    /// Identifies the output the Discovery message was sent from, echoed in the reply.
+    pub output_path_id: u8,
 }
 
 /// Used to handle MUID collisions.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct InvalidateMUID {
+    /// The device sending the message.
+    pub source: MUID,
+
     /// The target MUID to invalidate.
     pub target: MUID,
 }
 
-/// Default response when receiving a message not understood
-///
-/// Used if:
-/// - Received a CI message the device does not understand
-/// - Received a CI message with unsupported MIDI CI version
-/// - Received a malformed CI message
-/// - Received a profile enable/disable message for a profile that the device does not support
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-pub struct NotAcknowledged;
+impl InvalidateMUID {
+    /// Construct a new InvalidateMUID message, broadcast to all devices.
+    pub fn new(source: MUID, target: MUID) -> Self {
+        Self { source, target }
+    }
+}
 
 /// Single byte representing the MIDI version used by a protocol
 #[repr(u8)]
@@ -108,24 +193,37 @@ impl Default for Protocol {
     }
 }
 
-/// Message to begin initializing protocol negotation. The spec allows for multiple protocls
-/// to be supported, this crate only supports a single preferred protocol until const generics
-/// are stabilized, as Rust does not support VLA members in structs.
+const DEFAULT_PROTOCOLS: [Protocol; 1] = [Protocol {
+    midi_version: MidiVersion::Midi2,
+    version: 0,
+    extensions: 0,
+}];
+
+/// Message to begin initializing protocol negotation, listing the protocols supported by the
+/// initiator in order of preference.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-pub struct InitiateProtocolNegotiation {
+pub struct InitiateProtocolNegotiation<'a> {
+    /// The device initiating the negotiation.
+    pub source: MUID,
+
+    /// The device to negotiate with.
+    pub destination: MUID,
+
     /// The authority to use.
     pub authority: u8,
 
-    /// Which version of the protocol to request.
-    pub preferred: Protocol,
+    /// The supported protocols, preferred first.
+    pub protocols: ProtocolList<'a>,
 }
 
-impl InitiateProtocolNegotiation {
-    /// Construct a new protocol negotation message
-    pub fn new(auth: AuthorityLevel) -> Self {
+impl<'a> InitiateProtocolNegotiation<'a> {
+    /// Construct a new protocol negotation message, supporting only the MIDI 2 protocol.
+    pub fn new(source: MUID, destination: MUID, auth: AuthorityLevel) -> Self {
         Self {
+            source,
+            destination,
             authority: auth as u8,
-            preferred: Protocol::default(),
+            protocols: ProtocolList::new(&DEFAULT_PROTOCOLS),
         }
     }
 
@@ -136,11 +234,16 @@ impl InitiateProtocolNegotiation {
         self
     }
 
-    /// annotate a different preferred protocol
-    pub fn with_preferred_protocol(mut self, p: Protocol) -> Self {
-        self.preferred = p;
+    /// annotate the supported protocols, preferred first
+    pub fn with_protocols(mut self, protocols: &'a [Protocol]) -> Self {
+        self.protocols = ProtocolList::new(protocols);
         self
     }
+
+    /// The preferred protocol.
+    pub fn preferred(&self) -> Option<Protocol> {
+        self.protocols.iter().next()
+    }
 }
 
 impl DeviceDiscovery {
@@ -155,6 +258,42 @@ impl DeviceDiscovery {
             revision: 0,
             ci_support: 0,
             max_sysex_size: 128,
+            output_path_id: 0,
+        }
+    }
+
+    /// Add the identifier of the output the Discovery message is sent from.
+    pub fn with_output_path_id(mut self, id: u8) -> Self {
+        debug_assert!(id < 0x80, "Wrong integer size: output_path_id is u7");
+        self.output_path_id = id;
+        self
+    }
+
+    /// Add the manufacturer, family, model and revision of the device.
+    pub fn with_identity(mut self, identity: DeviceIdentity) -> Self {
+        self.manufacturer = identity.manufacturer;
+        self.family = identity.family;
+        self.model = identity.model;
+        self.revision = identity
+            .version
+            .iter()
+            .rev()
+            .fold(0, |revision, byte| (revision << 7) | (*byte & 0x7f) as u32);
+        self
+    }
+
+    /// The manufacturer, family, model and revision of the device, as reported in an Identity
+    /// Reply.
+    pub fn identity(&self) -> DeviceIdentity {
+        let mut version = [0; 4];
+        for (i, byte) in version.iter_mut().enumerate() {
+            *byte = ((self.revision >> (7 * i)) & 0x7f) as u8;
+        }
+        DeviceIdentity {
+            manufacturer: self.manufacturer,
+            family: self.family,
+            model: self.model,
+            version,
         }
     }
 
@@ -207,11 +346,23 @@ impl DeviceDiscovery {
         self.ci_support |= 0b0000_1000;
         self
     }
+
+    /// Notate the device supports process inquiry.
+    pub fn with_process_inquiry(mut self) -> Self {
+        self.ci_support |= 0b0001_0000;
+        self
+    }
 }
 
 /// Sent when a device requests a new protocol for communication
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct SetNewProtocol {
+    /// The device requesting the protocol.
+    pub source: MUID,
+
+    /// The device to switch protocol.
+    pub destination: MUID,
+
     /// The authority to use.
     pub authority: u8,
 
@@ -221,8 +372,10 @@ pub struct SetNewProtocol {
 
 impl SetNewProtocol {
     /// Construct a new SetNewProtocol message
-    pub fn new(p: Protocol, a: AuthorityLevel) -> Self {
+    pub fn new(source: MUID, destination: MUID, p: Protocol, a: AuthorityLevel) -> Self {
         Self {
+            source,
+            destination,
             protocol: p,
             authority: a as u8,
         }
@@ -236,17 +389,25 @@ impl SetNewProtocol {
 }
 
 /// Helper trait for capability inquiry messages.
-pub trait CapabilityInquiryMessage {
+///
+/// Messages are encoded as Universal SysEx, without the `F0`/`F7` delimiters. Data borrowed from
+/// the SysEx message, like text, has the lifetime `'a`.
+pub trait CapabilityInquiryMessage<'a>: Sized {
+    /// The subcategory (sub-ID #2) of messages of this type.
+    const SUB_ID: u8;
+
     /// The authority level of the message
     fn authority(&self) -> AuthorityLevel {
         AuthorityLevel::ReservedLower
     }
 
-    /// The category of a CI message
+    // The category of a CI message
     // fn category(&self) -> Category;
 
     /// The subcategory (type) of CI message
-    fn subcategory(&self) -> u8;
+    fn subcategory(&self) -> u8 {
+        Self::SUB_ID
+    }
 
     /// Source of the message
     fn source(&self) -> MUID;
@@ -257,11 +418,309 @@ pub trait CapabilityInquiryMessage {
     /// Used for targeting a specific MIDI channel on a device, value 0x7f corresponds
     /// to the entire MIDI port.
     fn device_id(&self) -> u8 {
-        0x7f
+        FUNCTION_BLOCK
+    }
+
+    /// Returns true if messages with a subcategory can be decoded as this type.
+    fn accepts(sub_id: u8) -> bool {
+        sub_id == Self::SUB_ID
+    }
+
+    /// Write the data contents of the message, following the header.
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error>;
+
+    /// Read the data contents of a message with a header of this type.
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error>;
+
+    /// The header of the message.
+    fn header(&self) -> Header {
+        Header {
+            device_id: self.device_id(),
+            sub_id: self.subcategory(),
+            version: CI_VERSION,
+            source: self.source(),
+            destination: self.dest(),
+        }
+    }
+
+    /// Encode the message into a buffer, returning the number of bytes written.
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut writer = Writer::new(buffer);
+        self.header().write(&mut writer)?;
+        self.write_data(&mut writer)?;
+        Ok(writer.len())
     }
 
-    /// The data contents of the message.
-    fn data(&self) -> &'_ [u8];
+    /// Encode the message into a new buffer. Fails if a field does not fit its encoding, eg
+    /// data that is not 7 bit.
+    #[cfg(not(feature = "no-std"))]
+    fn to_sysex(&self) -> Result<Vec<u8>, Error> {
+        let mut buffer = vec![0; 256];
+        loop {
+            match self.encode(&mut buffer) {
+                Ok(len) => {
+                    buffer.truncate(len);
+                    return Ok(buffer);
+                }
+                Err(Error::BufferTooSmall) => buffer.resize(buffer.len() * 2, 0),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Decode a message of this type. Data following the fields known to this crate, which
+    /// later MIDI-CI versions may add, is ignored.
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (header, mut reader) = Header::parse(sysex)?;
+        if !Self::accepts(header.sub_id) {
+            return Err(Error::UnexpectedSubId(header.sub_id));
+        }
+        Self::read_data(&header, &mut reader)
+    }
+}
+
+/// Any MIDI-CI message supported by this crate.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum CiMessage<'a> {
+    /// Discovery, sent to find other MIDI-CI devices.
+    Discovery(DeviceDiscovery),
+
+    /// Reply to Discovery.
+    DiscoveryReply(DiscoveryReply),
+
+    /// Inquiry: Endpoint Information.
+    EndpointInfoInquiry(EndpointInfoInquiry),
+
+    /// Reply to Endpoint Information.
+    EndpointInfoReply(EndpointInfoReply<'a>),
+
+    /// Profile Inquiry.
+    ProfileInquiry(ProfileInquiry),
+
+    /// Reply to Profile Inquiry.
+    ProfileInquiryReply(ProfileInquiryReply<'a>),
+
+    /// Set Profile On.
+    SetProfileOn(SetProfileOn),
+
+    /// Set Profile Off.
+    SetProfileOff(SetProfileOff),
+
+    /// Profile Enabled Report.
+    ProfileEnabled(ProfileEnabled),
+
+    /// Profile Disabled Report.
+    ProfileDisabled(ProfileDisabled),
+
+    /// Profile Added Report.
+    ProfileAdded(ProfileAdded),
+
+    /// Profile Removed Report.
+    ProfileRemoved(ProfileRemoved),
+
+    /// Profile Details Inquiry.
+    ProfileDetailsInquiry(ProfileDetailsInquiry),
+
+    /// Reply to Profile Details Inquiry.
+    ProfileDetailsReply(ProfileDetailsReply<'a>),
+
+    /// Profile Specific Data.
+    ProfileSpecificData(ProfileSpecificData<'a>),
+
+    /// Inquiry: Property Exchange Capabilities.
+    PropertyCapabilitiesInquiry(PropertyCapabilitiesInquiry),
+
+    /// Reply to Property Exchange Capabilities.
+    PropertyCapabilitiesReply(PropertyCapabilitiesReply),
+
+    /// A chunk of a Get, Set, Subscription or Notify property message, or a reply to one.
+    PropertyData(PropertyData<'a>),
+
+    /// Initiate Protocol Negotiation.
+    InitiateProtocolNegotiation(InitiateProtocolNegotiation<'a>),
+
+    /// Reply to Initiate Protocol Negotiation.
+    ProtocolNegotiationReply(ProtocolNegotiationReply<'a>),
+
+    /// Set New Selected Protocol.
+    SetNewProtocol(SetNewProtocol),
+
+    /// Test New Protocol, Initiator to Recipient.
+    TestNewProtocol(TestNewProtocol),
+
+    /// Test New Protocol, Recipient to Initiator.
+    TestNewProtocolReply(TestNewProtocolReply),
+
+    /// Confirmation New Protocol Established.
+    NewProtocolEstablished(NewProtocolEstablished),
+
+    /// Inquiry: Process Inquiry Capabilities.
+    ProcessCapabilitiesInquiry(ProcessCapabilitiesInquiry),
+
+    /// Reply to Process Inquiry Capabilities.
+    ProcessCapabilitiesReply(ProcessCapabilitiesReply),
+
+    /// Inquiry: MIDI Message Report.
+    MessageReportInquiry(MessageReportInquiry),
+
+    /// Reply to MIDI Message Report.
+    MessageReportReply(MessageReportReply),
+
+    /// End of MIDI Message Report.
+    EndOfMessageReport(EndOfMessageReport),
+
+    /// Invalidate MUID.
+    InvalidateMUID(InvalidateMUID),
+
+    /// ACK.
+    Acknowledged(Acknowledged<'a>),
+
+    /// NAK.
+    NotAcknowledged(NotAcknowledged<'a>),
+
+    /// A MIDI-CI message this crate does not decode.
+    Unknown(Header),
+}
+
+impl<'a> CiMessage<'a> {
+    /// Decode any MIDI-CI message.
+    pub fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (header, mut reader) = Header::parse(sysex)?;
+        let reader = &mut reader;
+        let message = match header.sub_id {
+            DeviceDiscovery::SUB_ID => {
+                Self::Discovery(DeviceDiscovery::read_data(&header, reader)?)
+            }
+            DiscoveryReply::SUB_ID => {
+                Self::DiscoveryReply(DiscoveryReply::read_data(&header, reader)?)
+            }
+            EndpointInfoInquiry::SUB_ID => {
+                Self::EndpointInfoInquiry(EndpointInfoInquiry::read_data(&header, reader)?)
+            }
+            EndpointInfoReply::SUB_ID => {
+                Self::EndpointInfoReply(EndpointInfoReply::read_data(&header, reader)?)
+            }
+            InitiateProtocolNegotiation::SUB_ID => Self::InitiateProtocolNegotiation(
+                InitiateProtocolNegotiation::read_data(&header, reader)?,
+            ),
+            ProtocolNegotiationReply::SUB_ID => Self::ProtocolNegotiationReply(
+                ProtocolNegotiationReply::read_data(&header, reader)?,
+            ),
+            SetNewProtocol::SUB_ID => {
+                Self::SetNewProtocol(SetNewProtocol::read_data(&header, reader)?)
+            }
+            TestNewProtocol::SUB_ID => {
+                Self::TestNewProtocol(TestNewProtocol::read_data(&header, reader)?)
+            }
+            TestNewProtocolReply::SUB_ID => {
+                Self::TestNewProtocolReply(TestNewProtocolReply::read_data(&header, reader)?)
+            }
+            NewProtocolEstablished::SUB_ID => {
+                Self::NewProtocolEstablished(NewProtocolEstablished::read_data(&header, reader)?)
+            }
+            ProfileInquiry::SUB_ID => {
+                Self::ProfileInquiry(ProfileInquiry::read_data(&header, reader)?)
+            }
+            ProfileInquiryReply::SUB_ID => {
+                Self::ProfileInquiryReply(ProfileInquiryReply::read_data(&header, reader)?)
+            }
+            SetProfileOn::SUB_ID => Self::SetProfileOn(SetProfileOn::read_data(&header, reader)?),
+            SetProfileOff::SUB_ID => {
+                Self::SetProfileOff(SetProfileOff::read_data(&header, reader)?)
+            }
+            ProfileEnabled::SUB_ID => {
+                Self::ProfileEnabled(ProfileEnabled::read_data(&header, reader)?)
+            }
+            ProfileDisabled::SUB_ID => {
+                Self::ProfileDisabled(ProfileDisabled::read_data(&header, reader)?)
+            }
+            ProfileAdded::SUB_ID => Self::ProfileAdded(ProfileAdded::read_data(&header, reader)?),
+            ProfileRemoved::SUB_ID => {
+                Self::ProfileRemoved(ProfileRemoved::read_data(&header, reader)?)
+            }
+            ProfileDetailsInquiry::SUB_ID => {
+                Self::ProfileDetailsInquiry(ProfileDetailsInquiry::read_data(&header, reader)?)
+            }
+            ProfileDetailsReply::SUB_ID => {
+                Self::ProfileDetailsReply(ProfileDetailsReply::read_data(&header, reader)?)
+            }
+            ProfileSpecificData::SUB_ID => {
+                Self::ProfileSpecificData(ProfileSpecificData::read_data(&header, reader)?)
+            }
+            PropertyCapabilitiesInquiry::SUB_ID => Self::PropertyCapabilitiesInquiry(
+                PropertyCapabilitiesInquiry::read_data(&header, reader)?,
+            ),
+            PropertyCapabilitiesReply::SUB_ID => Self::PropertyCapabilitiesReply(
+                PropertyCapabilitiesReply::read_data(&header, reader)?,
+            ),
+            sub_id if PropertyData::accepts(sub_id) => {
+                Self::PropertyData(PropertyData::read_data(&header, reader)?)
+            }
+            ProcessCapabilitiesInquiry::SUB_ID => Self::ProcessCapabilitiesInquiry(
+                ProcessCapabilitiesInquiry::read_data(&header, reader)?,
+            ),
+            ProcessCapabilitiesReply::SUB_ID => Self::ProcessCapabilitiesReply(
+                ProcessCapabilitiesReply::read_data(&header, reader)?,
+            ),
+            MessageReportInquiry::SUB_ID => {
+                Self::MessageReportInquiry(MessageReportInquiry::read_data(&header, reader)?)
+            }
+            MessageReportReply::SUB_ID => {
+                Self::MessageReportReply(MessageReportReply::read_data(&header, reader)?)
+            }
+            EndOfMessageReport::SUB_ID => {
+                Self::EndOfMessageReport(EndOfMessageReport::read_data(&header, reader)?)
+            }
+            InvalidateMUID::SUB_ID => {
+                Self::InvalidateMUID(InvalidateMUID::read_data(&header, reader)?)
+            }
+            Acknowledged::SUB_ID => Self::Acknowledged(Acknowledged::read_data(&header, reader)?),
+            NotAcknowledged::SUB_ID => {
+                Self::NotAcknowledged(NotAcknowledged::read_data(&header, reader)?)
+            }
+            _ => Self::Unknown(header),
+        };
+        Ok(message)
+    }
+
+    /// The header fields of the message.
+    pub fn header(&self) -> Header {
+        match self {
+            Self::Discovery(m) => m.header(),
+            Self::DiscoveryReply(m) => m.header(),
+            Self::EndpointInfoInquiry(m) => m.header(),
+            Self::EndpointInfoReply(m) => m.header(),
+            Self::InitiateProtocolNegotiation(m) => m.header(),
+            Self::ProtocolNegotiationReply(m) => m.header(),
+            Self::SetNewProtocol(m) => m.header(),
+            Self::TestNewProtocol(m) => m.header(),
+            Self::TestNewProtocolReply(m) => m.header(),
+            Self::NewProtocolEstablished(m) => m.header(),
+            Self::ProfileInquiry(m) => m.header(),
+            Self::ProfileInquiryReply(m) => m.header(),
+            Self::SetProfileOn(m) => m.header(),
+            Self::SetProfileOff(m) => m.header(),
+            Self::ProfileEnabled(m) => m.header(),
+            Self::ProfileDisabled(m) => m.header(),
+            Self::ProfileAdded(m) => m.header(),
+            Self::ProfileRemoved(m) => m.header(),
+            Self::ProfileDetailsInquiry(m) => m.header(),
+            Self::ProfileDetailsReply(m) => m.header(),
+            Self::ProfileSpecificData(m) => m.header(),
+            Self::PropertyCapabilitiesInquiry(m) => m.header(),
+            Self::PropertyCapabilitiesReply(m) => m.header(),
+            Self::PropertyData(m) => m.header(),
+            Self::ProcessCapabilitiesInquiry(m) => m.header(),
+            Self::ProcessCapabilitiesReply(m) => m.header(),
+            Self::MessageReportInquiry(m) => m.header(),
+            Self::MessageReportReply(m) => m.header(),
+            Self::EndOfMessageReport(m) => m.header(),
+            Self::InvalidateMUID(m) => m.header(),
+            Self::Acknowledged(m) => m.header(),
+            Self::NotAcknowledged(m) => m.header(),
+            Self::Unknown(header) => *header,
+        }
+    }
 }
 
 /// Represents the various management authority levels required by some MIDI specifications.
@@ -0,0 +1,440 @@
+//! ACK and NAK, the replies to MIDI-CI messages that have no reply of their own.
+use core::fmt;
+
+use super::profile::ProfileId;
+use super::sysex::{Header, Reader, Writer};
+use super::{CapabilityInquiryMessage, Error};
+use crate::muid::MUID;
+
+/// Why a message was acknowledged or rejected.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum StatusCode {
+    /// ACK or NAK without a more specific reason.
+    #[default]
+    General,
+
+    /// ACK: the message was received, but the device is busy and will reply later. The status
+    /// data is the time to wait before timing out, in units of 100 ms.
+    Busy,
+
+    /// NAK: the message type is not supported.
+    MessageNotSupported,
+
+    /// NAK: the MIDI-CI version of the message is not supported.
+    CiVersionNotSupported,
+
+    /// NAK: the channel, group or function block of the message is not in use.
+    TargetNotInUse,
+
+    /// NAK: the profile is not supported on the channel, group or function block. The details
+    /// hold the profile ID.
+    ProfileNotSupported,
+
+    /// NAK: the device stops answering an inquiry, eg a property exchange transaction.
+    TerminateInquiry,
+
+    /// NAK: property exchange chunks were received out of sequence.
+    ChunksOutOfSequence,
+
+    /// NAK: the device is busy, eg with too many requests. The status data is the number of
+    /// seconds to wait before retrying, or 0 if unknown.
+    Retry,
+
+    /// NAK: the message is malformed.
+    Malformed,
+
+    /// NAK: the device timed out waiting for a message.
+    Timeout,
+
+    /// NAK: the device timed out waiting for a message and the request may be sent again.
+    /// The status data is the number of seconds to wait before retrying, or 0 if unknown.
+    TimeoutRetry,
+
+    /// A status code this crate does not know.
+    Other(u8),
+}
+
+impl StatusCode {
+    /// The status code on the wire.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::General => 0x00,
+            Self::Busy => 0x10,
+            Self::MessageNotSupported => 0x01,
+            Self::CiVersionNotSupported => 0x02,
+            Self::TargetNotInUse => 0x03,
+            Self::ProfileNotSupported => 0x04,
+            Self::TerminateInquiry => 0x20,
+            Self::ChunksOutOfSequence => 0x21,
+            Self::Retry => 0x40,
+            Self::Malformed => 0x41,
+            Self::Timeout => 0x42,
+            Self::TimeoutRetry => 0x43,
+            Self::Other(code) => *code,
+        }
+    }
+}
+
+impl From<u8> for StatusCode {
+    fn from(code: u8) -> Self {
+        match code {
+            0x00 => Self::General,
+            0x10 => Self::Busy,
+            0x01 => Self::MessageNotSupported,
+            0x02 => Self::CiVersionNotSupported,
+            0x03 => Self::TargetNotInUse,
+            0x04 => Self::ProfileNotSupported,
+            0x20 => Self::TerminateInquiry,
+            0x21 => Self::ChunksOutOfSequence,
+            0x40 => Self::Retry,
+            0x41 => Self::Malformed,
+            0x42 => Self::Timeout,
+            0x43 => Self::TimeoutRetry,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl From<StatusCode> for u8 {
+    fn from(code: StatusCode) -> u8 {
+        code.code()
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::General => f.write_str("no reason given"),
+            Self::Busy => f.write_str("busy, reply will follow"),
+            Self::MessageNotSupported => f.write_str("message not supported"),
+            Self::CiVersionNotSupported => f.write_str("MIDI-CI version not supported"),
+            Self::TargetNotInUse => f.write_str("channel, group or function block not in use"),
+            Self::ProfileNotSupported => f.write_str("profile not supported"),
+            Self::TerminateInquiry => f.write_str("inquiry terminated"),
+            Self::ChunksOutOfSequence => f.write_str("chunks out of sequence"),
+            Self::Retry => f.write_str("error, retry"),
+            Self::Malformed => f.write_str("malformed message"),
+            Self::Timeout => f.write_str("timeout"),
+            Self::TimeoutRetry => f.write_str("timeout, retry"),
+            Self::Other(code) => write!(f, "status {code:#04x}"),
+        }
+    }
+}
+
+/// The body of an ACK or NAK message.
+///
+/// MIDI-CI 1.1 NAK messages have no body, and decode with every field zero.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct AckStatus<'a> {
+    /// The sub-ID #2 of the message being replied to.
+    pub original_sub_id: u8,
+
+    /// The status code.
+    pub code: StatusCode,
+
+    /// Additional data for the status code.
+    pub data: u8,
+
+    /// Details specific to the message being replied to.
+    pub details: [u8; 5],
+
+    /// Optional human readable text.
+    pub message: &'a [u8],
+}
+
+/// Acknowledges a message that has no reply of its own.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Acknowledged<'a> {
+    /// The channel, group or function block of the message being acknowledged.
+    pub device_id: u8,
+
+    /// The device acknowledging the message.
+    pub source: MUID,
+
+    /// The sender of the message being acknowledged.
+    pub destination: MUID,
+
+    /// Why the message is acknowledged.
+    pub status: AckStatus<'a>,
+}
+
+/// Default response when receiving a message not understood
+///
+/// Used if:
+/// - Received a CI message the device does not understand
+/// - Received a CI message with unsupported MIDI CI version
+/// - Received a malformed CI message
+/// - Received a profile enable/disable message for a profile that the device does not support
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct NotAcknowledged<'a> {
+    /// The channel, group or function block of the message being rejected.
+    pub device_id: u8,
+
+    /// The device rejecting the message.
+    pub source: MUID,
+
+    /// The sender of the message being rejected.
+    pub destination: MUID,
+
+    /// Why the message is rejected.
+    pub status: AckStatus<'a>,
+}
+
+impl<'a> AckStatus<'a> {
+    /// The status of a reply to a message with a sub-ID #2.
+    pub fn new(original_sub_id: u8, code: StatusCode) -> Self {
+        Self {
+            original_sub_id,
+            code,
+            ..Self::default()
+        }
+    }
+
+    /// Add data for the status code.
+    pub fn with_data(mut self, data: u8) -> Self {
+        debug_assert!(data < 0x80, "Wrong integer size: status data is u7");
+        self.data = data;
+        self
+    }
+
+    /// Add details specific to the message being replied to.
+    pub fn with_details(mut self, details: [u8; 5]) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// Add human readable ASCII text.
+    pub fn with_message(mut self, message: &'a [u8]) -> Self {
+        self.message = message;
+        self
+    }
+
+    fn write(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        if self.message.len() >= 0x4000 {
+            return Err(Error::InvalidValue);
+        }
+        writer.bytes(&[self.original_sub_id, self.code.code(), self.data])?;
+        writer.bytes(&self.details)?;
+        writer.u14(self.message.len() as u16)?;
+        writer.bytes(self.message)
+    }
+
+    fn read(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        if reader.is_empty() {
+            return Ok(Self::default());
+        }
+        let original_sub_id = reader.u8()?;
+        let code = reader.u8()?.into();
+        let data = reader.u8()?;
+        let details = reader.array()?;
+        let len = reader.u14()?;
+        Ok(Self {
+            original_sub_id,
+            code,
+            data,
+            details,
+            message: reader.bytes(len as usize)?,
+        })
+    }
+}
+
+impl<'a> Acknowledged<'a> {
+    /// Acknowledge a received message.
+    pub fn reply_to(source: MUID, original: &Header, code: StatusCode) -> Self {
+        Self {
+            device_id: original.device_id,
+            source,
+            destination: original.source,
+            status: AckStatus::new(original.sub_id, code),
+        }
+    }
+
+    /// Acknowledge a message that will be answered later, within `wait` units of 100 ms.
+    pub fn busy(source: MUID, original: &Header, wait: u8) -> Self {
+        let mut ack = Self::reply_to(source, original, StatusCode::Busy);
+        ack.status = ack.status.with_data(wait);
+        ack
+    }
+}
+
+impl<'a> NotAcknowledged<'a> {
+    /// Reject a received message.
+    pub fn reply_to(source: MUID, original: &Header, code: StatusCode) -> Self {
+        Self {
+            device_id: original.device_id,
+            source,
+            destination: original.source,
+            status: AckStatus::new(original.sub_id, code),
+        }
+    }
+
+    /// Reject a message type the device does not support.
+    pub fn message_not_supported(source: MUID, original: &Header) -> Self {
+        Self::reply_to(source, original, StatusCode::MessageNotSupported)
+    }
+
+    /// Reject a message with a MIDI-CI version the device does not support.
+    pub fn version_not_supported(source: MUID, original: &Header) -> Self {
+        Self::reply_to(source, original, StatusCode::CiVersionNotSupported)
+    }
+
+    /// Reject a message to a channel, group or function block that is not in use.
+    pub fn target_not_in_use(source: MUID, original: &Header) -> Self {
+        Self::reply_to(source, original, StatusCode::TargetNotInUse)
+    }
+
+    /// Reject a request for a profile not supported on the channel, group or function block.
+    pub fn profile_not_supported(source: MUID, original: &Header, profile: ProfileId) -> Self {
+        let mut nak = Self::reply_to(source, original, StatusCode::ProfileNotSupported);
+        nak.status = nak.status.with_details(profile.0);
+        nak
+    }
+
+    /// Reject a malformed message.
+    pub fn malformed(source: MUID, original: &Header) -> Self {
+        Self::reply_to(source, original, StatusCode::Malformed)
+    }
+
+    /// Reject a message the device is too busy to handle, asking to retry after some seconds,
+    /// or 0 if unknown.
+    pub fn retry(source: MUID, original: &Header, seconds: u8) -> Self {
+        let mut nak = Self::reply_to(source, original, StatusCode::Retry);
+        nak.status = nak.status.with_data(seconds);
+        nak
+    }
+
+    /// Add human readable ASCII text explaining the rejection.
+    pub fn with_message(mut self, message: &'a [u8]) -> Self {
+        self.status = self.status.with_message(message);
+        self
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for Acknowledged<'a> {
+    const SUB_ID: u8 = 0x7d;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        self.status.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            status: AckStatus::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for NotAcknowledged<'a> {
+    const SUB_ID: u8 = 0x7f;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        self.status.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            status: AckStatus::read(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::CiMessage;
+    use crate::muid::BROADCAST;
+
+    #[test]
+    fn round_trip() {
+        let nak = NotAcknowledged {
+            device_id: 3,
+            source: BROADCAST,
+            destination: MUID::from_wire([1, 2, 3, 4]),
+            status: AckStatus {
+                original_sub_id: 0x22,
+                code: StatusCode::MessageNotSupported,
+                data: 0,
+                details: [1, 2, 3, 4, 5],
+                message: b"unsupported",
+            },
+        };
+        let sysex = nak.to_sysex().unwrap();
+        assert_eq!(sysex.len(), 13 + 10 + 11);
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::NotAcknowledged(nak))
+        );
+        assert_eq!(
+            Acknowledged::decode(&sysex),
+            Err(Error::UnexpectedSubId(0x7f))
+        );
+    }
+
+    #[test]
+    fn decodes_version_1_nak() {
+        let sysex = [
+            0xf0, 0x7e, 0x7f, 0x0d, 0x7f, 0x01, 1, 0, 0, 0, 2, 0, 0, 0, 0xf7,
+        ];
+        let nak = NotAcknowledged::decode(&sysex).unwrap();
+        assert_eq!(nak.status, AckStatus::default());
+        assert_eq!(nak.destination, MUID::from_wire([2, 0, 0, 0]));
+    }
+
+    #[test]
+    fn status_codes() {
+        for code in 0..0x80 {
+            assert_eq!(StatusCode::from(code).code(), code);
+        }
+        assert_eq!(StatusCode::from(0x02), StatusCode::CiVersionNotSupported);
+        assert_eq!(StatusCode::from(0x7f), StatusCode::Other(0x7f));
+
+        let inquiry = crate::ci::ProfileInquiry {
+            device_id: 5,
+            source: MUID::from_wire([1, 0, 0, 0]),
+            destination: MUID::from_wire([2, 0, 0, 0]),
+        };
+        let header = inquiry.header();
+        let nak = NotAcknowledged::retry(inquiry.destination, &header, 2).with_message(b"busy");
+        let sysex = nak.to_sysex().unwrap();
+        let nak = NotAcknowledged::decode(&sysex).unwrap();
+        assert_eq!(nak.device_id, 5);
+        assert_eq!(nak.destination, inquiry.source);
+        assert_eq!(nak.status.original_sub_id, 0x20);
+        assert_eq!(nak.status.code, StatusCode::Retry);
+        assert_eq!(nak.status.data, 2);
+        assert_eq!(nak.status.message, b"busy");
+
+        let ack = Acknowledged::busy(inquiry.destination, &header, 10);
+        assert_eq!(ack.status.code.code(), 0x10);
+    }
+}
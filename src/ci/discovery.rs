@@ -0,0 +1,329 @@
+//! Management messages: Discovery, Endpoint Information and Invalidate MUID.
+use super::sysex::{Header, Reader, Writer};
+use super::{CapabilityInquiryMessage, DeviceDiscovery, Error, InvalidateMUID};
+use crate::muid::{BROADCAST, MUID};
+
+#[cfg(not(feature = "no-std"))]
+mod engine;
+#[cfg(not(feature = "no-std"))]
+pub use self::engine::{CiDiscovery, DiscoveryEvent, RemoteDevice};
+
+/// Sent in response to Discovery, describing the replying device.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct DiscoveryReply {
+    /// The device that sent the Discovery message.
+    pub destination: MUID,
+
+    /// The replying device. Its output path ID echoes the one from the Discovery message.
+    pub device: DeviceDiscovery,
+
+    /// The function block of the replying device, or 0x7f if it does not use function blocks.
+    pub function_block: u8,
+}
+
+impl DiscoveryReply {
+    /// Reply to a Discovery message.
+    pub fn new(discovery: &DeviceDiscovery, device: DeviceDiscovery) -> Self {
+        Self {
+            destination: discovery.muid,
+            device: device.with_output_path_id(discovery.output_path_id),
+            function_block: 0x7f,
+        }
+    }
+
+    /// Notate the function block of the replying device.
+    pub fn with_function_block(mut self, function_block: u8) -> Self {
+        debug_assert!(
+            function_block < 0x80,
+            "Wrong integer size: function_block is u7"
+        );
+        self.function_block = function_block;
+        self
+    }
+}
+
+/// Asks a device for information about its UMP endpoint.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct EndpointInfoInquiry {
+    /// The device sending the inquiry.
+    pub source: MUID,
+
+    /// The device being asked.
+    pub destination: MUID,
+
+    /// Which information to return, 0x00 for the product instance ID.
+    pub status: u8,
+}
+
+/// Sent in response to an Endpoint Information inquiry.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct EndpointInfoReply<'a> {
+    /// The device replying.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+
+    /// Which information is returned, echoed from the inquiry.
+    pub status: u8,
+
+    /// The information, eg up to 16 ASCII bytes of product instance ID.
+    pub information: &'a [u8],
+}
+
+fn write_device(writer: &mut Writer<'_>, device: &DeviceDiscovery) -> Result<(), Error> {
+    writer.bytes(&device.manufacturer)?;
+    writer.bytes(&device.family)?;
+    writer.bytes(&device.model)?;
+    writer.u28(device.revision)?;
+    writer.u8(device.ci_support)?;
+    writer.u28(device.max_sysex_size)?;
+    writer.u8(device.output_path_id)
+}
+
+fn read_device(muid: MUID, reader: &mut Reader<'_>) -> Result<DeviceDiscovery, Error> {
+    Ok(DeviceDiscovery {
+        muid,
+        manufacturer: reader.array()?,
+        family: reader.array()?,
+        model: reader.array()?,
+        revision: reader.u28()?,
+        ci_support: reader.u8()?,
+        max_sysex_size: reader.u28()?,
+        output_path_id: reader.u8_or_default()?,
+    })
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for DeviceDiscovery {
+    const SUB_ID: u8 = 0x70;
+
+    fn source(&self) -> MUID {
+        self.muid
+    }
+
+    fn dest(&self) -> MUID {
+        BROADCAST
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        write_device(writer, self)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        read_device(header.source, reader)
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for DiscoveryReply {
+    const SUB_ID: u8 = 0x71;
+
+    fn source(&self) -> MUID {
+        self.device.muid
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        write_device(writer, &self.device)?;
+        writer.u8(self.function_block)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let device = read_device(header.source, reader)?;
+        let function_block = if reader.is_empty() {
+            0x7f
+        } else {
+            reader.u8()?
+        };
+        Ok(Self {
+            destination: header.destination,
+            device,
+            function_block,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for EndpointInfoInquiry {
+    const SUB_ID: u8 = 0x72;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.status)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            status: reader.u8()?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for EndpointInfoReply<'a> {
+    const SUB_ID: u8 = 0x73;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        if self.information.len() >= 0x4000 {
+            return Err(Error::InvalidValue);
+        }
+        writer.u8(self.status)?;
+        writer.u14(self.information.len() as u16)?;
+        writer.bytes(self.information)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let status = reader.u8()?;
+        let len = reader.u14()?;
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            status,
+            information: reader.bytes(len as usize)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for InvalidateMUID {
+    const SUB_ID: u8 = 0x7e;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        BROADCAST
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.muid(self.target)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            target: reader.muid()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::CiMessage;
+    use crate::muid::muid;
+
+    #[test]
+    fn discovery_layout() {
+        let discovery = DeviceDiscovery::new(muid(0x0123_4567))
+            .with_manufacturer_code([0x00, 0x21, 0x09])
+            .with_family_code([0x01, 0x02])
+            .with_model_code([0x03, 0x04])
+            .with_revision(0x0000_0081)
+            .with_max_sysex_length(512)
+            .with_property_exchange()
+            .with_output_path_id(5);
+        let sysex = discovery.to_sysex().unwrap();
+        assert_eq!(
+            sysex,
+            [
+                0x7e, 0x7f, 0x0d, 0x70, 0x02, // header
+                0x67, 0x0a, 0x0d, 0x09, // source MUID
+                0x7f, 0x7f, 0x7f, 0x7f, // broadcast
+                0x00, 0x21, 0x09, 0x01, 0x02, 0x03, 0x04, // identity
+                0x01, 0x01, 0x00, 0x00, // revision
+                0x08, // CI categories
+                0x00, 0x04, 0x00, 0x00, // max sysex size
+                0x05, // output path id
+            ]
+        );
+        assert_eq!(DeviceDiscovery::decode(&sysex), Ok(discovery));
+    }
+
+    #[test]
+    fn decodes_delimited_version_1_messages() {
+        let discovery = DeviceDiscovery::new(muid(42));
+        let mut sysex = vec![0xf0];
+        sysex.extend(discovery.to_sysex().unwrap());
+        sysex[5] = 0x01;
+        sysex.pop();
+        sysex.push(0xf7);
+        assert_eq!(DeviceDiscovery::decode(&sysex), Ok(discovery));
+    }
+
+    #[test]
+    fn round_trips() {
+        let discovery = DeviceDiscovery::new(muid(1)).with_output_path_id(3);
+        let reply =
+            DiscoveryReply::new(&discovery, DeviceDiscovery::new(muid(2))).with_function_block(1);
+        assert_eq!(reply.device.output_path_id, 3);
+        let sysex = reply.to_sysex().unwrap();
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::DiscoveryReply(reply))
+        );
+
+        let inquiry = EndpointInfoInquiry {
+            source: muid(1),
+            destination: muid(2),
+            status: 0,
+        };
+        let sysex = inquiry.to_sysex().unwrap();
+        assert_eq!(EndpointInfoInquiry::decode(&sysex), Ok(inquiry));
+
+        let reply = EndpointInfoReply {
+            source: muid(2),
+            destination: muid(1),
+            status: 0,
+            information: b"SN-0001",
+        };
+        let sysex = reply.to_sysex().unwrap();
+        assert_eq!(EndpointInfoReply::decode(&sysex), Ok(reply));
+
+        let invalidate = InvalidateMUID::new(muid(1), muid(2));
+        let sysex = invalidate.to_sysex().unwrap();
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::InvalidateMUID(invalidate))
+        );
+        assert_eq!(
+            DeviceDiscovery::decode(&sysex),
+            Err(Error::UnexpectedSubId(0x7e))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let sysex = DeviceDiscovery::new(muid(1)).to_sysex().unwrap();
+        assert_eq!(DeviceDiscovery::decode(&sysex[..20]), Err(Error::Truncated));
+        let mut bad = sysex.clone();
+        bad[14] = 0x80;
+        assert_eq!(DeviceDiscovery::decode(&bad), Err(Error::InvalidByte(0x80)));
+        assert_eq!(
+            DeviceDiscovery::decode(&[0x7e, 0x7f, 0x06, 0x01]),
+            Err(Error::NotCapabilityInquiry)
+        );
+        let mut buffer = [0; 16];
+        assert_eq!(
+            DeviceDiscovery::new(muid(1)).encode(&mut buffer),
+            Err(Error::BufferTooSmall)
+        );
+    }
+}
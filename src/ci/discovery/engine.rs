@@ -0,0 +1,372 @@
+//! A sans-IO MIDI-CI discovery engine.
+//!
+//! The engine never sends or receives anything itself: the caller passes it received SysEx
+//! messages and the current time, and polls it for messages to send and events to handle.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+use super::DiscoveryReply;
+use crate::ci::{CapabilityInquiryMessage, CiMessage, DeviceDiscovery, Error, InvalidateMUID};
+use crate::muid::{self, MUID};
+
+/// Number of MUIDs taken from the generator after a collision before giving up.
+const MAX_MUID_ATTEMPTS: usize = 64;
+
+/// How long to wait for replies to Discovery before devices that did not reply are lost.
+pub const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A device found by discovery.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct RemoteDevice {
+    /// The identity and capabilities of the device.
+    pub device: DeviceDiscovery,
+
+    /// The function block of the device, or 0x7f if unknown.
+    pub function_block: u8,
+
+    /// When a message was last received from the device.
+    pub last_seen: Duration,
+}
+
+/// Something the caller of [CiDiscovery] may need to react to.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum DiscoveryEvent {
+    /// A new device was discovered.
+    DeviceFound(RemoteDevice),
+
+    /// A device invalidated its MUID or did not reply to Discovery.
+    DeviceLost(MUID),
+
+    /// The local MUID collided with another device and was replaced.
+    MuidChanged {
+        /// The MUID that collided.
+        old: MUID,
+
+        /// The new MUID of the local device.
+        new: MUID,
+    },
+}
+
+/// Runs MIDI-CI discovery for a local device.
+///
+/// Discovery is broadcast by [CiDiscovery::start], and remote devices are tracked from their
+/// replies and their own Discovery messages, which are answered. When a message from another
+/// device uses the local MUID, the engine invalidates it, picks a new MUID and starts again.
+pub struct CiDiscovery {
+    local: DeviceDiscovery,
+    function_block: u8,
+    generate_muid: Box<dyn FnMut() -> MUID>,
+    devices: HashMap<MUID, RemoteDevice>,
+    discovery_sent: Option<Duration>,
+    transmit: VecDeque<Vec<u8>>,
+    events: VecDeque<DiscoveryEvent>,
+}
+
+impl fmt::Debug for CiDiscovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CiDiscovery")
+            .field("local", &self.local)
+            .field("function_block", &self.function_block)
+            .field("devices", &self.devices)
+            .field("discovery_sent", &self.discovery_sent)
+            .finish()
+    }
+}
+
+impl CiDiscovery {
+    /// Create an engine for a local device. Nothing is sent until [CiDiscovery::start].
+    /// MUIDs replacing the local one after a collision are picked by [MUID::random] from
+    /// [muid::std_random], see [CiDiscovery::with_muid_generator].
+    pub fn new(local: DeviceDiscovery) -> Self {
+        let mut rng = muid::std_random();
+        Self {
+            local,
+            function_block: 0x7f,
+            generate_muid: Box::new(move || MUID::random(&mut rng)),
+            devices: HashMap::new(),
+            discovery_sent: None,
+            transmit: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Notate the function block of the local device, sent in replies to Discovery.
+    pub fn with_function_block(mut self, function_block: u8) -> Self {
+        debug_assert!(
+            function_block < 0x80,
+            "Wrong integer size: function_block is u7"
+        );
+        self.function_block = function_block;
+        self
+    }
+
+    /// Use a different generator for new MUIDs after a collision, eg a closure calling
+    /// [MUID::random] with the random number generator of the device. Reserved MUIDs and those
+    /// already in use are skipped, and [Error::MuidUnavailable] is returned if the generator
+    /// produces no other MUID within a few attempts.
+    pub fn with_muid_generator(mut self, generate_muid: impl FnMut() -> MUID + 'static) -> Self {
+        self.generate_muid = Box::new(generate_muid);
+        self
+    }
+
+    /// The local device.
+    pub fn local(&self) -> &DeviceDiscovery {
+        &self.local
+    }
+
+    /// The current MUID of the local device.
+    pub fn muid(&self) -> MUID {
+        self.local.muid
+    }
+
+    /// The remote device with a MUID, if it has been discovered.
+    pub fn device(&self, muid: MUID) -> Option<&RemoteDevice> {
+        self.devices.get(&muid)
+    }
+
+    /// Iterate over the discovered devices, in no particular order.
+    pub fn devices(&self) -> impl Iterator<Item = &RemoteDevice> + '_ {
+        self.devices.values()
+    }
+
+    /// Broadcast Discovery. Known devices that do not reply within [DISCOVERY_TIMEOUT] are
+    /// lost. Fails if the local device cannot be encoded.
+    pub fn start(&mut self, now: Duration) -> Result<(), Error> {
+        let local = self.local;
+        self.send(&local)?;
+        self.discovery_sent = Some(now);
+        Ok(())
+    }
+
+    /// Advance the clock, losing devices that did not reply to Discovery in time.
+    pub fn tick(&mut self, now: Duration) {
+        let Some(sent) = self.discovery_sent else {
+            return;
+        };
+        if now < sent + DISCOVERY_TIMEOUT {
+            return;
+        }
+        self.discovery_sent = None;
+        let mut lost: Vec<_> = self
+            .devices
+            .values()
+            .filter(|d| d.last_seen < sent)
+            .map(|d| d.device.muid)
+            .collect();
+        lost.sort_by_key(|muid| muid.to_wire());
+        for muid in lost {
+            self.lose(muid);
+        }
+    }
+
+    /// Handle a received SysEx message. Messages other than MIDI-CI are rejected, and MIDI-CI
+    /// messages not concerned with discovery only refresh the device that sent them.
+    pub fn receive(&mut self, now: Duration, sysex: &[u8]) -> Result<(), Error> {
+        let message = CiMessage::decode(sysex)?;
+        let header = message.header();
+        if header.source == self.local.muid {
+            return self.collision(now);
+        }
+        if let Some(device) = self.devices.get_mut(&header.source) {
+            device.last_seen = now;
+        }
+        match message {
+            CiMessage::Discovery(discovery) => {
+                self.found(now, discovery, 0x7f);
+                let reply = DiscoveryReply::new(&discovery, self.local)
+                    .with_function_block(self.function_block);
+                self.send(&reply)?;
+            }
+            CiMessage::DiscoveryReply(reply) if reply.destination == self.local.muid => {
+                self.found(now, reply.device, reply.function_block);
+            }
+            CiMessage::InvalidateMUID(invalidate) => {
+                if invalidate.target == self.local.muid {
+                    self.collision(now)?;
+                } else if self.devices.contains_key(&invalidate.target) {
+                    self.lose(invalidate.target);
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// The next SysEx message to send, without `F0`/`F7` delimiters.
+    pub fn poll_transmit(&mut self) -> Option<Vec<u8>> {
+        self.transmit.pop_front()
+    }
+
+    /// The next event to handle.
+    pub fn poll_event(&mut self) -> Option<DiscoveryEvent> {
+        self.events.pop_front()
+    }
+
+    fn send<'a>(&mut self, message: &impl CapabilityInquiryMessage<'a>) -> Result<(), Error> {
+        self.transmit.push_back(message.to_sysex()?);
+        Ok(())
+    }
+
+    fn found(&mut self, now: Duration, device: DeviceDiscovery, function_block: u8) {
+        let remote = RemoteDevice {
+            device,
+            function_block,
+            last_seen: now,
+        };
+        if self.devices.insert(device.muid, remote).is_none() {
+            self.events.push_back(DiscoveryEvent::DeviceFound(remote));
+        }
+    }
+
+    fn lose(&mut self, muid: MUID) {
+        if self.devices.remove(&muid).is_some() {
+            self.events.push_back(DiscoveryEvent::DeviceLost(muid));
+        }
+    }
+
+    /// Invalidate the local MUID, replace it and start discovery again.
+    fn collision(&mut self, now: Duration) -> Result<(), Error> {
+        let old = self.local.muid;
+        let mut attempts = 0;
+        let new = loop {
+            if attempts == MAX_MUID_ATTEMPTS {
+                return Err(Error::MuidUnavailable);
+            }
+            attempts += 1;
+            let new = (self.generate_muid)();
+            if new != old && new.value() < muid::RESERVED && !self.devices.contains_key(&new) {
+                break new;
+            }
+        };
+        self.send(&InvalidateMUID::new(old, old))?;
+        self.local.muid = new;
+        self.events
+            .push_back(DiscoveryEvent::MuidChanged { old, new });
+        self.start(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::muid::muid;
+    use crate::muid::BROADCAST;
+
+    fn drain(engine: &mut CiDiscovery) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| engine.poll_transmit()).collect()
+    }
+
+    #[test]
+    fn discovers_and_loses_devices() {
+        let mut engine = CiDiscovery::new(DeviceDiscovery::new(muid(1)));
+        engine.start(Duration::ZERO).unwrap();
+        let sent = drain(&mut engine);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(DeviceDiscovery::decode(&sent[0]), Ok(*engine.local()));
+
+        let remote = DeviceDiscovery::new(muid(2)).with_property_exchange();
+        let reply = DiscoveryReply::new(engine.local(), remote).with_function_block(0);
+        engine
+            .receive(Duration::from_millis(10), &reply.to_sysex().unwrap())
+            .unwrap();
+        let Some(DiscoveryEvent::DeviceFound(found)) = engine.poll_event() else {
+            panic!("expected a device");
+        };
+        assert_eq!(found.device, remote);
+        assert_eq!(found.function_block, 0);
+
+        // The device does not reply to a second discovery.
+        engine.start(Duration::from_secs(10)).unwrap();
+        engine.tick(Duration::from_secs(12));
+        assert_eq!(engine.poll_event(), None);
+        engine.tick(Duration::from_secs(13));
+        assert_eq!(
+            engine.poll_event(),
+            Some(DiscoveryEvent::DeviceLost(muid(2)))
+        );
+        assert_eq!(engine.devices().count(), 0);
+    }
+
+    #[test]
+    fn replies_to_discovery() {
+        let mut engine = CiDiscovery::new(DeviceDiscovery::new(muid(1))).with_function_block(2);
+        let remote = DeviceDiscovery::new(muid(3)).with_output_path_id(4);
+        engine
+            .receive(Duration::ZERO, &remote.to_sysex().unwrap())
+            .unwrap();
+        let sent = drain(&mut engine);
+        let reply = DiscoveryReply::decode(&sent[0]).unwrap();
+        assert_eq!(reply.destination, muid(3));
+        assert_eq!(reply.device.output_path_id, 4);
+        assert_eq!(reply.function_block, 2);
+        assert!(engine.device(muid(3)).is_some());
+
+        let invalidate = InvalidateMUID::new(muid(3), muid(3));
+        engine
+            .receive(Duration::ZERO, &invalidate.to_sysex().unwrap())
+            .unwrap();
+        assert_eq!(engine.poll_event().map(|_| ()), Some(()));
+        assert_eq!(
+            engine.poll_event(),
+            Some(DiscoveryEvent::DeviceLost(muid(3)))
+        );
+    }
+
+    #[test]
+    fn resolves_collisions() {
+        let mut next = 100;
+        let mut engine =
+            CiDiscovery::new(DeviceDiscovery::new(muid(1))).with_muid_generator(move || {
+                next += 1;
+                muid(next)
+            });
+        let imposter = DeviceDiscovery::new(muid(1));
+        engine
+            .receive(Duration::ZERO, &imposter.to_sysex().unwrap())
+            .unwrap();
+        let Some(DiscoveryEvent::MuidChanged { old, new }) = engine.poll_event() else {
+            panic!("expected a new MUID");
+        };
+        assert_eq!(old, muid(1));
+        assert_eq!(new, muid(101));
+        assert_eq!(engine.muid(), new);
+
+        let sent = drain(&mut engine);
+        assert_eq!(
+            InvalidateMUID::decode(&sent[0]),
+            Ok(InvalidateMUID::new(old, old))
+        );
+        assert_eq!(DeviceDiscovery::decode(&sent[1]).unwrap().muid, new);
+    }
+
+    #[test]
+    fn skips_reserved_muids() {
+        let mut candidates = vec![
+            muid(2),
+            BROADCAST,
+            MUID::from_wire([0x00, 0x7e, 0x7f, 0x7f]),
+            muid(1),
+        ];
+        let mut engine = CiDiscovery::new(DeviceDiscovery::new(muid(1)))
+            .with_muid_generator(move || candidates.pop().unwrap());
+        let imposter = DeviceDiscovery::new(muid(1));
+        engine
+            .receive(Duration::ZERO, &imposter.to_sysex().unwrap())
+            .unwrap();
+        assert_eq!(engine.muid(), muid(2));
+    }
+
+    #[test]
+    fn gives_up_without_a_new_muid() {
+        let mut engine =
+            CiDiscovery::new(DeviceDiscovery::new(muid(1))).with_muid_generator(|| muid(1));
+        let imposter = DeviceDiscovery::new(muid(1));
+        assert_eq!(
+            engine.receive(Duration::ZERO, &imposter.to_sysex().unwrap()),
+            Err(Error::MuidUnavailable)
+        );
+        assert_eq!(engine.muid(), muid(1));
+        assert_eq!(engine.poll_transmit(), None);
+    }
+}
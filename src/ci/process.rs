@@ -0,0 +1,396 @@
+//! Process Inquiry: messages to discover the current state of a device, eg its controller values
+//! and held notes, reported as MIDI messages.
+use core::convert::TryFrom;
+
+use super::sysex::{Header, Reader, Writer};
+use super::{CapabilityInquiryMessage, Error};
+use crate::muid::MUID;
+
+#[cfg(not(feature = "no-std"))]
+mod report;
+#[cfg(not(feature = "no-std"))]
+pub use self::report::{
+    ChannelState, DeviceState, MessageReporter, NoteState, Program, ReportItem, SystemState,
+};
+
+/// Inquiry: Process Inquiry Capabilities.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProcessCapabilitiesInquiry {
+    /// The device sending the inquiry.
+    pub source: MUID,
+
+    /// The device being queried.
+    pub destination: MUID,
+}
+
+/// Reply to Process Inquiry Capabilities.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProcessCapabilitiesReply {
+    /// The device sending the reply.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+
+    /// Bitflags of the supported Process Inquiry features.
+    pub features: u8,
+}
+
+impl ProcessCapabilitiesReply {
+    /// The device supports the MIDI Message Report.
+    pub const MIDI_MESSAGE_REPORT: u8 = 0b0000_0001;
+
+    /// Returns true if the device supports the MIDI Message Report.
+    pub fn supports_message_report(&self) -> bool {
+        self.features & Self::MIDI_MESSAGE_REPORT != 0
+    }
+}
+
+/// Which values a MIDI Message Report should include.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum MessageDataControl {
+    /// No values, only the categories the device would report.
+    NoData = 0x00,
+
+    /// Only values that differ from their defaults.
+    NonDefault = 0x01,
+
+    /// All values.
+    Full = 0x7f,
+}
+
+impl TryFrom<u8> for MessageDataControl {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Self::NoData),
+            0x01 => Ok(Self::NonDefault),
+            0x7f => Ok(Self::Full),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// Bitmaps of the kinds of MIDI messages in a MIDI Message Report.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ReportCategories {
+    /// System messages, eg [ReportCategories::SONG_POSITION].
+    pub system: u8,
+
+    /// Channel controller messages, eg [ReportCategories::CONTROL_CHANGE].
+    pub channel_controllers: u8,
+
+    /// Note data messages, eg [ReportCategories::NOTES].
+    pub notes: u8,
+}
+
+impl ReportCategories {
+    /// MIDI Time Code quarter frame messages.
+    pub const MTC_QUARTER_FRAME: u8 = 0b0000_0001;
+    /// Song Position Pointer.
+    pub const SONG_POSITION: u8 = 0b0000_0010;
+    /// Song Select.
+    pub const SONG_SELECT: u8 = 0b0000_0100;
+
+    /// Pitch bend.
+    pub const PITCH_BEND: u8 = 0b0000_0001;
+    /// Control changes.
+    pub const CONTROL_CHANGE: u8 = 0b0000_0010;
+    /// Registered controllers (RPN).
+    pub const RPN: u8 = 0b0000_0100;
+    /// Assignable controllers (NRPN).
+    pub const NRPN: u8 = 0b0000_1000;
+    /// Program change.
+    pub const PROGRAM_CHANGE: u8 = 0b0001_0000;
+    /// Channel pressure.
+    pub const CHANNEL_PRESSURE: u8 = 0b0010_0000;
+
+    /// Note on messages for held notes.
+    pub const NOTES: u8 = 0b0000_0001;
+    /// Polyphonic pressure.
+    pub const POLY_PRESSURE: u8 = 0b0000_0010;
+    /// Per-note pitch bend.
+    pub const PER_NOTE_PITCH_BEND: u8 = 0b0000_0100;
+    /// Registered per-note controllers.
+    pub const REGISTERED_PER_NOTE_CONTROLLER: u8 = 0b0000_1000;
+    /// Assignable per-note controllers.
+    pub const ASSIGNABLE_PER_NOTE_CONTROLLER: u8 = 0b0001_0000;
+
+    /// Every category.
+    pub fn all() -> Self {
+        Self {
+            system: 0b0000_0111,
+            channel_controllers: 0b0011_1111,
+            notes: 0b0001_1111,
+        }
+    }
+
+    /// The categories in both `self` and `other`.
+    pub fn intersection(&self, other: Self) -> Self {
+        Self {
+            system: self.system & other.system,
+            channel_controllers: self.channel_controllers & other.channel_controllers,
+            notes: self.notes & other.notes,
+        }
+    }
+
+    fn write(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.system)?;
+        writer.u8(0)?;
+        writer.u8(self.channel_controllers)?;
+        writer.u8(self.notes)
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, Error> {
+        let system = reader.u8()?;
+        reader.u8()?;
+        Ok(Self {
+            system,
+            channel_controllers: reader.u8()?,
+            notes: reader.u8()?,
+        })
+    }
+}
+
+/// Inquiry: MIDI Message Report. Asks a device to send the current state of a channel, group
+/// or function block as MIDI messages.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct MessageReportInquiry {
+    /// The channel, group (0x7e) or function block (0x7f) to report.
+    pub device_id: u8,
+
+    /// The device sending the inquiry.
+    pub source: MUID,
+
+    /// The device being queried.
+    pub destination: MUID,
+
+    /// Which values to report.
+    pub data_control: MessageDataControl,
+
+    /// The kinds of messages requested.
+    pub categories: ReportCategories,
+}
+
+/// Reply to MIDI Message Report, sent before the report with the kinds of messages it holds.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct MessageReportReply {
+    /// The channel, group (0x7e) or function block (0x7f) reported.
+    pub device_id: u8,
+
+    /// The device sending the report.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+
+    /// The kinds of messages that will be sent.
+    pub categories: ReportCategories,
+}
+
+/// Sent after the last message of a MIDI Message Report.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct EndOfMessageReport {
+    /// The channel, group (0x7e) or function block (0x7f) reported.
+    pub device_id: u8,
+
+    /// The device sending the report.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProcessCapabilitiesInquiry {
+    const SUB_ID: u8 = 0x40;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, _writer: &mut Writer<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_data(header: &Header, _reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProcessCapabilitiesReply {
+    const SUB_ID: u8 = 0x41;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.features)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            features: reader.u8()?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for MessageReportInquiry {
+    const SUB_ID: u8 = 0x42;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.data_control as u8)?;
+        self.categories.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            data_control: MessageDataControl::try_from(reader.u8()?)?,
+            categories: ReportCategories::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for MessageReportReply {
+    const SUB_ID: u8 = 0x43;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        self.categories.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            categories: ReportCategories::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for EndOfMessageReport {
+    const SUB_ID: u8 = 0x44;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, _writer: &mut Writer<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_data(header: &Header, _reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::CiMessage;
+
+    #[test]
+    fn message_report_round_trip() {
+        let inquiry = MessageReportInquiry {
+            device_id: 2,
+            source: MUID::from_wire([1, 0, 0, 0]),
+            destination: MUID::from_wire([2, 0, 0, 0]),
+            data_control: MessageDataControl::NonDefault,
+            categories: ReportCategories {
+                system: ReportCategories::SONG_POSITION,
+                channel_controllers: ReportCategories::CONTROL_CHANGE
+                    | ReportCategories::PROGRAM_CHANGE,
+                notes: ReportCategories::NOTES,
+            },
+        };
+        let sysex = inquiry.to_sysex().unwrap();
+        assert_eq!(
+            &sysex[..14],
+            [0x7e, 0x02, 0x0d, 0x42, 0x02, 1, 0, 0, 0, 2, 0, 0, 0, 0x01]
+        );
+        assert_eq!(&sysex[14..], [0x02, 0x00, 0x12, 0x01]);
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::MessageReportInquiry(inquiry))
+        );
+
+        let mut sysex = sysex;
+        sysex[13] = 0x02;
+        assert_eq!(
+            MessageReportInquiry::decode(&sysex),
+            Err(Error::InvalidValue)
+        );
+
+        let reply = ProcessCapabilitiesReply {
+            source: inquiry.destination,
+            destination: inquiry.source,
+            features: ProcessCapabilitiesReply::MIDI_MESSAGE_REPORT,
+        };
+        let decoded = ProcessCapabilitiesReply::decode(&reply.to_sysex().unwrap()).unwrap();
+        assert!(decoded.supports_message_report());
+
+        let end = EndOfMessageReport {
+            device_id: 2,
+            source: inquiry.destination,
+            destination: inquiry.source,
+        };
+        assert_eq!(
+            CiMessage::decode(&end.to_sysex().unwrap()),
+            Ok(CiMessage::EndOfMessageReport(end))
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! Answering MIDI Message Report inquiries from a snapshot of the state of a device.
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::ci::CiMessage;
+use crate::message::channel2::ChannelVoice;
+use crate::message::system::System;
+use crate::message::Data;
+use crate::packet::Packet;
+
+/// The pitch bend value of a centred wheel.
+const PITCH_BEND_CENTER: u32 = 0x8000_0000;
+
+/// A program selected on a channel.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Program {
+    /// The program number.
+    pub program: u8,
+
+    /// The bank, as a 14-bit MSB/LSB value, if one was selected.
+    pub bank: Option<u16>,
+}
+
+/// The state of a held note.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NoteState {
+    /// The velocity the note was played with.
+    pub velocity: u16,
+
+    /// The polyphonic pressure.
+    pub pressure: Option<u32>,
+
+    /// The per-note pitch bend.
+    pub pitch_bend: Option<u32>,
+
+    /// Registered per-note controller values by index.
+    pub registered: BTreeMap<u8, u32>,
+
+    /// Assignable per-note controller values by index.
+    pub assignable: BTreeMap<u8, u32>,
+}
+
+/// The state of a channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelState {
+    /// The selected program.
+    pub program: Option<Program>,
+
+    /// Control change values by index.
+    pub controllers: BTreeMap<u8, u32>,
+
+    /// Registered controller (RPN) values by bank and index.
+    pub rpns: BTreeMap<(u8, u8), u32>,
+
+    /// Assignable controller (NRPN) values by bank and index.
+    pub nrpns: BTreeMap<(u8, u8), u32>,
+
+    /// The pitch bend.
+    pub pitch_bend: Option<u32>,
+
+    /// The channel pressure.
+    pub pressure: Option<u32>,
+
+    /// The held notes by note number.
+    pub notes: BTreeMap<u8, NoteState>,
+}
+
+/// The state of system messages.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct SystemState {
+    /// The song position, in MIDI beats.
+    pub song_position: Option<u16>,
+
+    /// The selected song.
+    pub song_select: Option<u8>,
+}
+
+/// A snapshot of the state of a group, reported by a [MessageReporter].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceState {
+    /// The state of system messages.
+    pub system: SystemState,
+
+    /// The state of each channel.
+    pub channels: [ChannelState; 16],
+}
+
+/// An item of a MIDI Message Report, in the order it should be sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReportItem {
+    /// A MIDI-CI message: the reply that starts the report, or the end of the report.
+    SysEx(Vec<u8>),
+
+    /// A MIDI message reporting a value.
+    Message(Data),
+}
+
+/// Answers Process Inquiry messages for the local device, reporting a [DeviceState] as
+/// MIDI 2.0 messages on one group.
+///
+/// Only the values present in the snapshot are reported. When only non-default values are
+/// requested, centred pitch bends and zero pressures and controller values, including RPNs,
+/// NRPNs and per-note controllers, are skipped. MIDI Time Code is not reported.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct MessageReporter {
+    muid: MUID,
+    group: u8,
+}
+
+impl MessageReporter {
+    /// Create a reporter for the device with a MUID, sending messages on group 0.
+    pub fn new(muid: MUID) -> Self {
+        Self { muid, group: 0 }
+    }
+
+    /// Send report messages on a group.
+    pub fn with_group(mut self, group: u8) -> Self {
+        debug_assert!(group < 16, "Wrong integer size: group is u4");
+        self.group = group;
+        self
+    }
+
+    /// Change the MUID of the local device, eg after a collision.
+    pub fn set_muid(&mut self, muid: MUID) {
+        self.muid = muid;
+    }
+
+    /// The categories of messages the reporter can send.
+    pub fn supported() -> ReportCategories {
+        let mut categories = ReportCategories::all();
+        categories.system &= !ReportCategories::MTC_QUARTER_FRAME;
+        categories
+    }
+
+    /// Handle a received message, returning what to send in reply.
+    pub fn respond(
+        &self,
+        message: &CiMessage<'_>,
+        state: &DeviceState,
+    ) -> Result<Vec<ReportItem>, Error> {
+        match message {
+            CiMessage::ProcessCapabilitiesInquiry(inquiry) if inquiry.destination == self.muid => {
+                let reply = ProcessCapabilitiesReply {
+                    source: self.muid,
+                    destination: inquiry.source,
+                    features: ProcessCapabilitiesReply::MIDI_MESSAGE_REPORT,
+                };
+                Ok(vec![ReportItem::SysEx(reply.to_sysex()?)])
+            }
+            CiMessage::MessageReportInquiry(inquiry) if inquiry.destination == self.muid => {
+                self.report(inquiry, state)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// The report answering an inquiry, starting with the reply and ending with the End of
+    /// MIDI Message Report.
+    pub fn report(
+        &self,
+        inquiry: &MessageReportInquiry,
+        state: &DeviceState,
+    ) -> Result<Vec<ReportItem>, Error> {
+        let channels = match inquiry.device_id {
+            channel @ 0x00..=0x0f => channel..=channel,
+            0x7e | 0x7f => 0..=15,
+            _ => return Ok(Vec::new()),
+        };
+        let categories = inquiry.categories.intersection(Self::supported());
+        let reply = MessageReportReply {
+            device_id: inquiry.device_id,
+            source: self.muid,
+            destination: inquiry.source,
+            categories,
+        };
+        let mut items = vec![ReportItem::SysEx(reply.to_sysex()?)];
+        if inquiry.data_control != MessageDataControl::NoData {
+            let mut report = Report {
+                group: self.group,
+                categories,
+                full: inquiry.data_control == MessageDataControl::Full,
+                items: &mut items,
+            };
+            report.system(&state.system);
+            for channel in channels {
+                report.channel(channel, &state.channels[channel as usize]);
+            }
+        }
+        let end = EndOfMessageReport {
+            device_id: inquiry.device_id,
+            source: self.muid,
+            destination: inquiry.source,
+        };
+        items.push(ReportItem::SysEx(end.to_sysex()?));
+        Ok(items)
+    }
+}
+
+struct Report<'a> {
+    group: u8,
+    categories: ReportCategories,
+    full: bool,
+    items: &'a mut Vec<ReportItem>,
+}
+
+impl Report<'_> {
+    fn reports(&self, value: u32, default: u32) -> bool {
+        self.full || value != default
+    }
+
+    fn system(&mut self, state: &SystemState) {
+        let group = (self.group as u32) << 24;
+        let system = self.categories.system;
+        let mut push = |word: u32| {
+            let message = System::from_packet_unchecked(Packet([0x1000_0000 | group | word]));
+            self.items.push(ReportItem::Message(Data::System(message)));
+        };
+        if let Some(position) = state.song_position {
+            if system & ReportCategories::SONG_POSITION != 0 {
+                let (lsb, msb) = (position & 0x7f, (position >> 7) & 0x7f);
+                push(0x00f2_0000 | (lsb as u32) << 8 | msb as u32);
+            }
+        }
+        if let Some(song) = state.song_select {
+            if system & ReportCategories::SONG_SELECT != 0 {
+                push(0x00f3_0000 | (song as u32) << 8);
+            }
+        }
+    }
+
+    fn push(&mut self, status: u8, channel: u8, index: [u8; 2], data: u32) {
+        let word = 0x4000_0000
+            | (self.group as u32) << 24
+            | (status as u32) << 20
+            | (channel as u32) << 16
+            | (index[0] as u32) << 8
+            | index[1] as u32;
+        let message = ChannelVoice::from_packet_unchecked(Packet([word, data]));
+        self.items
+            .push(ReportItem::Message(Data::ChannelVoice(message)));
+    }
+
+    fn channel(&mut self, channel: u8, state: &ChannelState) {
+        let controllers = self.categories.channel_controllers;
+        if controllers & ReportCategories::PROGRAM_CHANGE != 0 {
+            if let Some(program) = state.program {
+                let (options, [msb, lsb]) = match program.bank {
+                    Some(bank) => (1, [(bank >> 7) as u8 & 0x7f, bank as u8 & 0x7f]),
+                    None => (0, [0, 0]),
+                };
+                let data = (program.program as u32) << 24 | (msb as u32) << 8 | lsb as u32;
+                self.push(0xc, channel, [0, options], data);
+            }
+        }
+        if controllers & ReportCategories::CONTROL_CHANGE != 0 {
+            for (&index, &value) in &state.controllers {
+                if self.reports(value, 0) {
+                    self.push(0xb, channel, [index, 0], value);
+                }
+            }
+        }
+        if controllers & ReportCategories::RPN != 0 {
+            for (&(bank, index), &value) in &state.rpns {
+                if self.reports(value, 0) {
+                    self.push(0x2, channel, [bank, index], value);
+                }
+            }
+        }
+        if controllers & ReportCategories::NRPN != 0 {
+            for (&(bank, index), &value) in &state.nrpns {
+                if self.reports(value, 0) {
+                    self.push(0x3, channel, [bank, index], value);
+                }
+            }
+        }
+        if controllers & ReportCategories::PITCH_BEND != 0 {
+            if let Some(value) = state
+                .pitch_bend
+                .filter(|v| self.reports(*v, PITCH_BEND_CENTER))
+            {
+                self.push(0xe, channel, [0, 0], value);
+            }
+        }
+        if controllers & ReportCategories::CHANNEL_PRESSURE != 0 {
+            if let Some(value) = state.pressure.filter(|v| self.reports(*v, 0)) {
+                self.push(0xd, channel, [0, 0], value);
+            }
+        }
+        let notes = self.categories.notes;
+        for (&note, state) in &state.notes {
+            if notes & ReportCategories::NOTES != 0 {
+                self.push(0x9, channel, [note, 0], (state.velocity as u32) << 16);
+            }
+            if notes & ReportCategories::POLY_PRESSURE != 0 {
+                if let Some(value) = state.pressure.filter(|v| self.reports(*v, 0)) {
+                    self.push(0xa, channel, [note, 0], value);
+                }
+            }
+            if notes & ReportCategories::PER_NOTE_PITCH_BEND != 0 {
+                if let Some(value) = state
+                    .pitch_bend
+                    .filter(|v| self.reports(*v, PITCH_BEND_CENTER))
+                {
+                    self.push(0x6, channel, [note, 0], value);
+                }
+            }
+            if notes & ReportCategories::REGISTERED_PER_NOTE_CONTROLLER != 0 {
+                for (&index, &value) in &state.registered {
+                    if self.reports(value, 0) {
+                        self.push(0x0, channel, [note, index], value);
+                    }
+                }
+            }
+            if notes & ReportCategories::ASSIGNABLE_PER_NOTE_CONTROLLER != 0 {
+                for (&index, &value) in &state.assignable {
+                    if self.reports(value, 0) {
+                        self.push(0x1, channel, [note, index], value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(item: &ReportItem) -> Vec<u32> {
+        match item {
+            ReportItem::Message(Data::ChannelVoice(message)) => message.to_vec(),
+            ReportItem::Message(Data::System(message)) => message.to_vec(),
+            item => panic!("unexpected report item: {:?}", item),
+        }
+    }
+
+    #[test]
+    fn reports_channel_state() {
+        let host = MUID::from_wire([1, 0, 0, 0]);
+        let device = MUID::from_wire([2, 0, 0, 0]);
+        let reporter = MessageReporter::new(device).with_group(1);
+
+        let mut state = DeviceState::default();
+        state.system.song_select = Some(3);
+        let channel = &mut state.channels[2];
+        channel.program = Some(Program {
+            program: 5,
+            bank: Some(0x81),
+        });
+        channel.controllers.insert(7, 0x8000_0000);
+        channel.controllers.insert(64, 0);
+        channel.rpns.insert((0, 0), 0);
+        channel.nrpns.insert((1, 2), 0x1000);
+        channel.pitch_bend = Some(PITCH_BEND_CENTER);
+        channel.notes.insert(
+            60,
+            NoteState {
+                velocity: 0xffff,
+                pressure: Some(0x1234),
+                assignable: std::iter::once((5, 0)).collect(),
+                ..NoteState::default()
+            },
+        );
+
+        let inquiry = MessageReportInquiry {
+            device_id: 2,
+            source: host,
+            destination: device,
+            data_control: MessageDataControl::NonDefault,
+            categories: ReportCategories::all(),
+        };
+        let sysex = inquiry.to_sysex().unwrap();
+        let items = reporter
+            .respond(&CiMessage::decode(&sysex).unwrap(), &state)
+            .unwrap();
+        assert_eq!(items.len(), 8);
+
+        let ReportItem::SysEx(reply) = &items[0] else {
+            panic!("expected a reply")
+        };
+        let reply = MessageReportReply::decode(reply).unwrap();
+        assert_eq!(reply.destination, host);
+        assert_eq!(reply.categories, MessageReporter::supported());
+
+        assert_eq!(words(&items[1]), [0x11f3_0300]);
+        assert_eq!(words(&items[2]), [0x41c2_0001, 0x0500_0101]);
+        assert_eq!(words(&items[3]), [0x41b2_0700, 0x8000_0000]);
+        assert_eq!(words(&items[4]), [0x4132_0102, 0x0000_1000]);
+        assert_eq!(words(&items[5]), [0x4192_3c00, 0xffff_0000]);
+        assert_eq!(words(&items[6]), [0x41a2_3c00, 0x0000_1234]);
+
+        let ReportItem::SysEx(end) = &items[7] else {
+            panic!("expected the end of the report")
+        };
+        assert_eq!(
+            CiMessage::decode(end),
+            Ok(CiMessage::EndOfMessageReport(EndOfMessageReport {
+                device_id: 2,
+                source: device,
+                destination: host,
+            }))
+        );
+
+        // Without data, only the reply and the end are sent.
+        let inquiry = MessageReportInquiry {
+            data_control: MessageDataControl::NoData,
+            ..inquiry
+        };
+        assert_eq!(reporter.report(&inquiry, &state).unwrap().len(), 2);
+
+        // The full report includes default values.
+        let inquiry = MessageReportInquiry {
+            data_control: MessageDataControl::Full,
+            ..inquiry
+        };
+        assert_eq!(reporter.report(&inquiry, &state).unwrap().len(), 12);
+    }
+}
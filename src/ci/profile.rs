@@ -0,0 +1,851 @@
+//! Profile Configuration: messages to discover, enable and disable profiles, which define how a
+//! device responds to MIDI messages, eg as a drawbar organ or a mixer.
+use core::convert::TryInto;
+use core::hash::{Hash, Hasher};
+use core::{fmt, slice};
+
+use super::sysex::{Header, Reader, Writer};
+use super::{CapabilityInquiryMessage, Error};
+use crate::muid::{BROADCAST, MUID};
+
+#[cfg(not(feature = "no-std"))]
+mod registry;
+#[cfg(not(feature = "no-std"))]
+pub use self::registry::{Profile, ProfileRegistry};
+
+/// The first byte of standard defined profile IDs.
+pub const STANDARD_PROFILE: u8 = 0x7e;
+
+/// The length of a profile ID on the wire.
+const PROFILE_LEN: usize = 5;
+
+/// A 5 byte profile identifier, either defined by the MIDI Association or by a manufacturer.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProfileId(pub [u8; 5]);
+
+impl ProfileId {
+    /// A profile defined by the MIDI Association.
+    pub fn standard(bank: u8, number: u8, version: u8, level: u8) -> Self {
+        debug_assert!(
+            bank < 0x80 && number < 0x80 && version < 0x80 && level < 0x80,
+            "Wrong integer size: profile ID bytes are u7"
+        );
+        Self([STANDARD_PROFILE, bank, number, version, level])
+    }
+
+    /// A profile defined by a manufacturer, identified by its SysEx ID.
+    pub fn manufacturer(id: [u8; 3], info: [u8; 2]) -> Self {
+        debug_assert_ne!(id[0], STANDARD_PROFILE);
+        Self([id[0], id[1], id[2], info[0], info[1]])
+    }
+
+    /// Returns true if the profile is defined by the MIDI Association.
+    pub fn is_standard(&self) -> bool {
+        self.0[0] == STANDARD_PROFILE
+    }
+
+    /// The bank, number, version and level of a standard defined profile.
+    pub fn standard_fields(&self) -> Option<[u8; 4]> {
+        self.is_standard()
+            .then(|| [self.0[1], self.0[2], self.0[3], self.0[4]])
+    }
+
+    /// The SysEx ID of the manufacturer of a manufacturer specific profile.
+    pub fn manufacturer_id(&self) -> Option<[u8; 3]> {
+        (!self.is_standard()).then(|| [self.0[0], self.0[1], self.0[2]])
+    }
+}
+
+impl From<[u8; 5]> for ProfileId {
+    fn from(bytes: [u8; 5]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<ProfileId> for [u8; 5] {
+    fn from(id: ProfileId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for ProfileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e] = self.0;
+        write!(f, "{a:02X} {b:02X} {c:02X} {d:02X} {e:02X}")
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Profiles<'a> {
+    Decoded(&'a [ProfileId]),
+    Encoded(&'a [u8]),
+}
+
+/// A list of profile IDs, either borrowed from the caller or from a received message.
+#[derive(Clone, Copy)]
+pub struct ProfileList<'a>(Profiles<'a>);
+
+impl<'a> ProfileList<'a> {
+    /// A list of profiles.
+    pub fn new(profiles: &'a [ProfileId]) -> Self {
+        Self(Profiles::Decoded(profiles))
+    }
+
+    /// The number of profiles.
+    pub fn len(&self) -> usize {
+        match self.0 {
+            Profiles::Decoded(profiles) => profiles.len(),
+            Profiles::Encoded(bytes) => bytes.len() / PROFILE_LEN,
+        }
+    }
+
+    /// Returns true if the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The profiles.
+    pub fn iter(&self) -> ProfileIter<'a> {
+        match self.0 {
+            Profiles::Decoded(profiles) => ProfileIter(Iter::Decoded(profiles.iter())),
+            Profiles::Encoded(bytes) => ProfileIter(Iter::Encoded(bytes.chunks_exact(PROFILE_LEN))),
+        }
+    }
+
+    fn write(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        if self.len() >= 0x4000 {
+            return Err(Error::InvalidValue);
+        }
+        writer.u14(self.len() as u16)?;
+        for id in self.iter() {
+            writer.bytes(&id.0)?;
+        }
+        Ok(())
+    }
+
+    fn read(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let count = reader.u14()? as usize;
+        let bytes = reader.bytes(count * PROFILE_LEN)?;
+        Ok(Self(Profiles::Encoded(bytes)))
+    }
+}
+
+impl<'a> From<&'a [ProfileId]> for ProfileList<'a> {
+    fn from(profiles: &'a [ProfileId]) -> Self {
+        Self::new(profiles)
+    }
+}
+
+impl<'a> IntoIterator for ProfileList<'a> {
+    type Item = ProfileId;
+    type IntoIter = ProfileIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl PartialEq for ProfileList<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for ProfileList<'_> {}
+
+impl Hash for ProfileList<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for profile in self.iter() {
+            profile.hash(state);
+        }
+    }
+}
+
+impl fmt::Debug for ProfileList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Iter<'a> {
+    Decoded(slice::Iter<'a, ProfileId>),
+    Encoded(slice::ChunksExact<'a, u8>),
+}
+
+/// An iterator over a [ProfileList].
+#[derive(Clone, Debug)]
+pub struct ProfileIter<'a>(Iter<'a>);
+
+impl Iterator for ProfileIter<'_> {
+    type Item = ProfileId;
+
+    fn next(&mut self) -> Option<ProfileId> {
+        match &mut self.0 {
+            Iter::Decoded(profiles) => profiles.next().copied(),
+            Iter::Encoded(chunks) => chunks.next().map(|b| ProfileId(b.try_into().unwrap())),
+        }
+    }
+}
+
+fn read_id(reader: &mut Reader<'_>) -> Result<ProfileId, Error> {
+    reader.array().map(ProfileId)
+}
+
+/// Asks a device which profiles it supports.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileInquiry {
+    /// The channel, group (0x7e) or function block (0x7f) to ask about.
+    pub device_id: u8,
+
+    /// The device sending the inquiry.
+    pub source: MUID,
+
+    /// The device being asked.
+    pub destination: MUID,
+}
+
+/// The profiles supported on a channel, group or function block.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileInquiryReply<'a> {
+    /// The channel, group (0x7e) or function block (0x7f) of the profiles.
+    pub device_id: u8,
+
+    /// The device replying.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+
+    /// The profiles that are enabled.
+    pub enabled: ProfileList<'a>,
+
+    /// The profiles that are supported but disabled.
+    pub disabled: ProfileList<'a>,
+}
+
+/// Requests a profile be enabled.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct SetProfileOn {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device sending the request.
+    pub source: MUID,
+
+    /// The device to enable the profile on.
+    pub destination: MUID,
+
+    /// The profile to enable.
+    pub profile: ProfileId,
+
+    /// The number of channels to enable the profile on, 0 for a single channel.
+    pub channels: u16,
+}
+
+/// Requests a profile be disabled.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct SetProfileOff {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device sending the request.
+    pub source: MUID,
+
+    /// The device to disable the profile on.
+    pub destination: MUID,
+
+    /// The profile to disable.
+    pub profile: ProfileId,
+}
+
+/// Broadcast when a profile is enabled.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileEnabled {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device reporting the profile.
+    pub source: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+
+    /// The number of channels the profile is enabled on, 0 for a single channel.
+    pub channels: u16,
+}
+
+/// Broadcast when a profile is disabled.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileDisabled {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device reporting the profile.
+    pub source: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+
+    /// The number of channels the profile was enabled on, 0 for a single channel.
+    pub channels: u16,
+}
+
+/// Broadcast when a device starts supporting a profile.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileAdded {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device reporting the profile.
+    pub source: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+}
+
+/// Broadcast when a device stops supporting a profile.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileRemoved {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device reporting the profile.
+    pub source: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+}
+
+/// Asks for details of how a device implements a profile.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileDetailsInquiry {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device sending the inquiry.
+    pub source: MUID,
+
+    /// The device being asked.
+    pub destination: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+
+    /// Which details to return, as defined by the profile.
+    pub target: u8,
+}
+
+/// Details of how a device implements a profile.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileDetailsReply<'a> {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device replying.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+
+    /// Which details are returned, echoed from the inquiry.
+    pub target: u8,
+
+    /// The details, as defined by the profile.
+    pub data: &'a [u8],
+}
+
+/// Data defined by a profile, sent while it is enabled.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileSpecificData<'a> {
+    /// The channel, group (0x7e) or function block (0x7f) of the profile.
+    pub device_id: u8,
+
+    /// The device sending the data.
+    pub source: MUID,
+
+    /// The device receiving the data.
+    pub destination: MUID,
+
+    /// The profile.
+    pub profile: ProfileId,
+
+    /// The data, as defined by the profile.
+    pub data: &'a [u8],
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileInquiry {
+    const SUB_ID: u8 = 0x20;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, _writer: &mut Writer<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_data(header: &Header, _reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileInquiryReply<'a> {
+    const SUB_ID: u8 = 0x21;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        self.enabled.write(writer)?;
+        self.disabled.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            enabled: ProfileList::read(reader)?,
+            disabled: ProfileList::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for SetProfileOn {
+    const SUB_ID: u8 = 0x22;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)?;
+        writer.u14(self.channels)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let profile = read_id(reader)?;
+        let channels = if reader.is_empty() { 0 } else { reader.u14()? };
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            profile,
+            channels,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for SetProfileOff {
+    const SUB_ID: u8 = 0x23;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)?;
+        // Reserved.
+        writer.u14(0)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            profile: read_id(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileEnabled {
+    const SUB_ID: u8 = 0x24;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        BROADCAST
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)?;
+        writer.u14(self.channels)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let profile = read_id(reader)?;
+        let channels = if reader.is_empty() { 0 } else { reader.u14()? };
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            profile,
+            channels,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileDisabled {
+    const SUB_ID: u8 = 0x25;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        BROADCAST
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)?;
+        writer.u14(self.channels)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let profile = read_id(reader)?;
+        let channels = if reader.is_empty() { 0 } else { reader.u14()? };
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            profile,
+            channels,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileAdded {
+    const SUB_ID: u8 = 0x26;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        BROADCAST
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            profile: read_id(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileRemoved {
+    const SUB_ID: u8 = 0x27;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        BROADCAST
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            profile: read_id(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileDetailsInquiry {
+    const SUB_ID: u8 = 0x28;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&self.profile.0)?;
+        writer.u8(self.target)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            profile: read_id(reader)?,
+            target: reader.u8()?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileDetailsReply<'a> {
+    const SUB_ID: u8 = 0x29;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        if self.data.len() >= 0x4000 {
+            return Err(Error::InvalidValue);
+        }
+        writer.bytes(&self.profile.0)?;
+        writer.u8(self.target)?;
+        writer.u14(self.data.len() as u16)?;
+        writer.bytes(self.data)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let profile = read_id(reader)?;
+        let target = reader.u8()?;
+        let len = reader.u14()?;
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            profile,
+            target,
+            data: reader.bytes(len as usize)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProfileSpecificData<'a> {
+    const SUB_ID: u8 = 0x2f;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        if self.data.len() >= 0x1000_0000 {
+            return Err(Error::InvalidValue);
+        }
+        writer.bytes(&self.profile.0)?;
+        writer.u28(self.data.len() as u32)?;
+        writer.bytes(self.data)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let profile = read_id(reader)?;
+        let len = reader.u28()?;
+        Ok(Self {
+            device_id: header.device_id,
+            source: header.source,
+            destination: header.destination,
+            profile,
+            data: reader.bytes(len as usize)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::CiMessage;
+
+    #[test]
+    fn profile_ids() {
+        let id = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        assert!(id.is_standard());
+        assert_eq!(id.standard_fields(), Some([0x21, 0x01, 0x00, 0x01]));
+        assert_eq!(id.to_string(), "7E 21 01 00 01");
+        let id = ProfileId::manufacturer([0x00, 0x21, 0x09], [1, 2]);
+        assert_eq!(id.manufacturer_id(), Some([0x00, 0x21, 0x09]));
+        assert_eq!(id.standard_fields(), None);
+    }
+
+    #[test]
+    fn inquiry_reply_round_trip() {
+        let enabled = [ProfileId::standard(0x21, 0x01, 0x00, 0x01)];
+        let disabled = [
+            ProfileId::standard(0x22, 0x01, 0x00, 0x01),
+            ProfileId::manufacturer([0x00, 0x21, 0x09], [1, 2]),
+        ];
+        let reply = ProfileInquiryReply {
+            device_id: 3,
+            source: MUID::from_wire([1, 0, 0, 0]),
+            destination: MUID::from_wire([2, 0, 0, 0]),
+            enabled: ProfileList::new(&enabled),
+            disabled: ProfileList::new(&disabled),
+        };
+        let sysex = reply.to_sysex().unwrap();
+        assert_eq!(sysex.len(), 13 + 2 + 5 + 2 + 10);
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::ProfileInquiryReply(reply))
+        );
+        assert_eq!(
+            ProfileInquiryReply::decode(&sysex[..sysex.len() - 1]),
+            Err(Error::Truncated)
+        );
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let source = MUID::from_wire([1, 0, 0, 0]);
+        let destination = MUID::from_wire([2, 0, 0, 0]);
+        let profile = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        let on = SetProfileOn {
+            device_id: 0x7e,
+            source,
+            destination,
+            profile,
+            channels: 16,
+        };
+        assert_eq!(SetProfileOn::decode(&on.to_sysex().unwrap()), Ok(on));
+        let off = SetProfileOff {
+            device_id: 0,
+            source,
+            destination,
+            profile,
+        };
+        assert_eq!(SetProfileOff::decode(&off.to_sysex().unwrap()), Ok(off));
+        let enabled = ProfileEnabled {
+            device_id: 0,
+            source,
+            profile,
+            channels: 0,
+        };
+        assert_eq!(enabled.header().destination, BROADCAST);
+        assert_eq!(
+            ProfileEnabled::decode(&enabled.to_sysex().unwrap()),
+            Ok(enabled)
+        );
+        let details = ProfileDetailsReply {
+            device_id: 0,
+            source,
+            destination,
+            profile,
+            target: 0,
+            data: &[1, 2, 3],
+        };
+        assert_eq!(
+            ProfileDetailsReply::decode(&details.to_sysex().unwrap()),
+            Ok(details)
+        );
+        let data = ProfileSpecificData {
+            device_id: 0,
+            source,
+            destination,
+            profile,
+            data: &[0x7f; 300],
+        };
+        assert_eq!(
+            CiMessage::decode(&data.to_sysex().unwrap()),
+            Ok(CiMessage::ProfileSpecificData(data))
+        );
+    }
+
+    #[test]
+    fn invalid_fields_are_errors() {
+        let profile = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        let data = ProfileSpecificData {
+            device_id: 0,
+            source: MUID::from_wire([1, 0, 0, 0]),
+            destination: MUID::from_wire([2, 0, 0, 0]),
+            profile,
+            data: &[0x80],
+        };
+        assert_eq!(data.to_sysex(), Err(Error::InvalidByte(0x80)));
+
+        let profiles = vec![profile; 0x4000];
+        let reply = ProfileInquiryReply {
+            device_id: 0,
+            source: data.source,
+            destination: data.destination,
+            enabled: ProfileList::new(&profiles),
+            disabled: ProfileList::new(&[]),
+        };
+        assert_eq!(reply.to_sysex(), Err(Error::InvalidValue));
+    }
+}
@@ -0,0 +1,305 @@
+//! The profiles of a local device, answering Profile Configuration messages.
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::ci::{CiMessage, NotAcknowledged};
+
+/// A profile supported on a channel, group or function block.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Profile {
+    /// The profile.
+    pub id: ProfileId,
+
+    /// Whether the profile is enabled.
+    pub enabled: bool,
+
+    /// The number of channels the profile is enabled on, 0 for a single channel.
+    pub channels: u16,
+}
+
+/// The profiles supported by a local device on each of its channels (0-15), its group (0x7e)
+/// and its function block (0x7f).
+///
+/// Changes made locally return the report to broadcast, and [ProfileRegistry::respond] answers
+/// inquiries and requests from other devices.
+#[derive(Clone, Debug)]
+pub struct ProfileRegistry {
+    muid: MUID,
+    profiles: BTreeMap<u8, Vec<Profile>>,
+}
+
+impl ProfileRegistry {
+    /// Create an empty registry for the device with a MUID.
+    pub fn new(muid: MUID) -> Self {
+        Self {
+            muid,
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// Change the MUID of the local device, eg after a collision.
+    pub fn set_muid(&mut self, muid: MUID) {
+        self.muid = muid;
+    }
+
+    /// The profiles supported on a channel, group or function block.
+    pub fn profiles(&self, device_id: u8) -> &[Profile] {
+        self.profiles.get(&device_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The profile with an ID on a channel, group or function block, if it is supported.
+    pub fn profile(&self, device_id: u8, id: ProfileId) -> Option<&Profile> {
+        self.profiles(device_id).iter().find(|p| p.id == id)
+    }
+
+    /// Add a disabled profile, returning the Profile Added Report to broadcast. Returns `None`
+    /// if the profile was already supported, and fails if the profile ID cannot be encoded.
+    pub fn add(&mut self, device_id: u8, id: ProfileId) -> Result<Option<Vec<u8>>, Error> {
+        if self.profile(device_id, id).is_some() {
+            return Ok(None);
+        }
+        let report = ProfileAdded {
+            device_id,
+            source: self.muid,
+            profile: id,
+        }
+        .to_sysex()?;
+        self.profiles.entry(device_id).or_default().push(Profile {
+            id,
+            enabled: false,
+            channels: 0,
+        });
+        Ok(Some(report))
+    }
+
+    /// Remove a profile, returning the Profile Removed Report to broadcast. Returns `None` if
+    /// the profile was not supported.
+    pub fn remove(&mut self, device_id: u8, id: ProfileId) -> Result<Option<Vec<u8>>, Error> {
+        let Some(index) = self.profiles(device_id).iter().position(|p| p.id == id) else {
+            return Ok(None);
+        };
+        let report = ProfileRemoved {
+            device_id,
+            source: self.muid,
+            profile: id,
+        }
+        .to_sysex()?;
+        let profiles = self.profiles.entry(device_id).or_default();
+        profiles.remove(index);
+        if profiles.is_empty() {
+            self.profiles.remove(&device_id);
+        }
+        Ok(Some(report))
+    }
+
+    /// Enable a profile, returning the Profile Enabled Report to broadcast. Returns `None` if
+    /// the profile is not supported.
+    pub fn enable(
+        &mut self,
+        device_id: u8,
+        id: ProfileId,
+        channels: u16,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if self.profile(device_id, id).is_none() {
+            return Ok(None);
+        }
+        let report = ProfileEnabled {
+            device_id,
+            source: self.muid,
+            profile: id,
+            channels,
+        }
+        .to_sysex()?;
+        if let Some(profile) = self.profile_mut(device_id, id) {
+            profile.enabled = true;
+            profile.channels = channels;
+        }
+        Ok(Some(report))
+    }
+
+    /// Disable a profile, returning the Profile Disabled Report to broadcast. Returns `None` if
+    /// the profile is not supported.
+    pub fn disable(&mut self, device_id: u8, id: ProfileId) -> Result<Option<Vec<u8>>, Error> {
+        let Some(profile) = self.profile(device_id, id) else {
+            return Ok(None);
+        };
+        let report = ProfileDisabled {
+            device_id,
+            source: self.muid,
+            profile: id,
+            channels: profile.channels,
+        }
+        .to_sysex()?;
+        if let Some(profile) = self.profile_mut(device_id, id) {
+            profile.enabled = false;
+            profile.channels = 0;
+        }
+        Ok(Some(report))
+    }
+
+    /// Answer a Profile Inquiry, Set Profile On or Set Profile Off message addressed to the
+    /// local device, returning the messages to send. Requests for unsupported profiles are
+    /// answered with NAK. Other messages, including Profile Details Inquiry whose answer is
+    /// defined by each profile, are left to the caller.
+    pub fn respond(&mut self, message: &CiMessage<'_>) -> Result<Vec<Vec<u8>>, Error> {
+        let header = message.header();
+        if header.destination != self.muid && header.destination != BROADCAST {
+            return Ok(Vec::new());
+        }
+        let report = match message {
+            CiMessage::ProfileInquiry(inquiry) => {
+                let mut device_ids = vec![inquiry.device_id];
+                if inquiry.device_id == 0x7f {
+                    // An inquiry to the function block is answered for every address.
+                    device_ids = self
+                        .profiles
+                        .keys()
+                        .copied()
+                        .filter(|d| *d != 0x7f)
+                        .collect();
+                    device_ids.push(0x7f);
+                }
+                return device_ids
+                    .into_iter()
+                    .map(|device_id| self.inquiry_reply(device_id, inquiry.source))
+                    .collect();
+            }
+            CiMessage::SetProfileOn(request) => (
+                self.enable(request.device_id, request.profile, request.channels)?,
+                request.profile,
+            ),
+            CiMessage::SetProfileOff(request) => (
+                self.disable(request.device_id, request.profile)?,
+                request.profile,
+            ),
+            _ => return Ok(Vec::new()),
+        };
+        match report {
+            (Some(report), _) => Ok(vec![report]),
+            (None, profile) => Ok(vec![self.nak(header, profile)?]),
+        }
+    }
+
+    fn profile_mut(&mut self, device_id: u8, id: ProfileId) -> Option<&mut Profile> {
+        self.profiles
+            .get_mut(&device_id)?
+            .iter_mut()
+            .find(|p| p.id == id)
+    }
+
+    fn inquiry_reply(&self, device_id: u8, destination: MUID) -> Result<Vec<u8>, Error> {
+        let profiles = self.profiles(device_id);
+        let ids = |enabled: bool| -> Vec<ProfileId> {
+            profiles
+                .iter()
+                .filter(|p| p.enabled == enabled)
+                .map(|p| p.id)
+                .collect()
+        };
+        let (enabled, disabled) = (ids(true), ids(false));
+        ProfileInquiryReply {
+            device_id,
+            source: self.muid,
+            destination,
+            enabled: ProfileList::new(&enabled),
+            disabled: ProfileList::new(&disabled),
+        }
+        .to_sysex()
+    }
+
+    fn nak(&self, header: Header, profile: ProfileId) -> Result<Vec<u8>, Error> {
+        NotAcknowledged::profile_not_supported(self.muid, &header, profile).to_sysex()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::StatusCode;
+    use crate::muid::muid;
+
+    const LOCAL: u32 = 1;
+    const REMOTE: u32 = 2;
+
+    #[test]
+    fn answers_inquiries() {
+        let organ = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        let mixer = ProfileId::standard(0x22, 0x01, 0x00, 0x01);
+        let mut registry = ProfileRegistry::new(muid(LOCAL));
+        assert!(registry.add(0, organ).unwrap().is_some());
+        assert!(registry.add(0, organ).unwrap().is_none());
+        registry.add(0x7f, mixer).unwrap();
+        registry.enable(0x7f, mixer, 0).unwrap();
+
+        let inquiry = ProfileInquiry {
+            device_id: 0x7f,
+            source: muid(REMOTE),
+            destination: muid(LOCAL),
+        };
+        let replies = registry
+            .respond(&CiMessage::ProfileInquiry(inquiry))
+            .unwrap();
+        let replies: Vec<_> = replies
+            .iter()
+            .map(|r| ProfileInquiryReply::decode(r).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].device_id, 0);
+        assert_eq!(replies[0].disabled, ProfileList::new(&[organ]));
+        assert_eq!(replies[1].device_id, 0x7f);
+        assert_eq!(replies[1].enabled, ProfileList::new(&[mixer]));
+        assert_eq!(replies[1].destination, muid(REMOTE));
+    }
+
+    #[test]
+    fn enables_and_disables_profiles() {
+        let organ = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        let mut registry = ProfileRegistry::new(muid(LOCAL));
+        registry.add(3, organ).unwrap();
+
+        let on = SetProfileOn {
+            device_id: 3,
+            source: muid(REMOTE),
+            destination: muid(LOCAL),
+            profile: organ,
+            channels: 0,
+        };
+        let sent = registry.respond(&CiMessage::SetProfileOn(on)).unwrap();
+        assert_eq!(ProfileEnabled::decode(&sent[0]).unwrap().profile, organ);
+        assert!(registry.profile(3, organ).unwrap().enabled);
+
+        // The profile is not supported on channel 4.
+        let sent = registry
+            .respond(&CiMessage::SetProfileOn(SetProfileOn {
+                device_id: 4,
+                ..on
+            }))
+            .unwrap();
+        let nak = NotAcknowledged::decode(&sent[0]).unwrap();
+        assert_eq!(nak.status.original_sub_id, SetProfileOn::SUB_ID);
+        assert_eq!(nak.status.code, StatusCode::ProfileNotSupported);
+        assert_eq!(nak.status.details, organ.0);
+        assert_eq!(nak.destination, muid(REMOTE));
+
+        let off = SetProfileOff {
+            device_id: 3,
+            source: muid(REMOTE),
+            destination: muid(LOCAL),
+            profile: organ,
+        };
+        let sent = registry.respond(&CiMessage::SetProfileOff(off)).unwrap();
+        assert!(ProfileDisabled::decode(&sent[0]).is_ok());
+        assert!(!registry.profile(3, organ).unwrap().enabled);
+
+        assert!(registry.remove(3, organ).unwrap().is_some());
+        assert!(registry.profiles(3).is_empty());
+    }
+
+    #[test]
+    fn invalid_profiles_are_errors() {
+        let invalid = ProfileId([0x7e, 0x80, 0x01, 0x00, 0x01]);
+        let mut registry = ProfileRegistry::new(muid(LOCAL));
+        assert_eq!(registry.add(0, invalid), Err(Error::InvalidByte(0x80)));
+        assert!(registry.profiles(0).is_empty());
+    }
+}
@@ -0,0 +1,415 @@
+//! Property Exchange: messages to get, set and subscribe to the properties of a device, like its
+//! program list or settings.
+//!
+//! A property message carries a JSON header and a body, which may be split into several chunks
+//! to fit the maximum SysEx size of the receiver. Only the first chunk carries the header.
+use core::convert::TryFrom;
+
+use super::sysex::{Header, Reader, Writer, HEADER_LEN};
+use super::{CapabilityInquiryMessage, Error};
+use crate::muid::MUID;
+
+#[cfg(not(feature = "no-std"))]
+mod assembler;
+#[cfg(not(feature = "no-std"))]
+mod header;
+#[cfg(not(feature = "no-std"))]
+pub mod json;
+pub mod mcoded7;
+#[cfg(not(feature = "no-std"))]
+pub mod server;
+
+#[cfg(not(feature = "no-std"))]
+pub use self::assembler::{Property, PropertyAssembler, MAX_PENDING};
+#[cfg(not(feature = "no-std"))]
+pub use self::header::{Encoding, PropertyHeader, SubscriptionCommand, MAX_DECODED_LEN};
+#[cfg(not(feature = "no-std"))]
+pub use self::server::{PropertyError, PropertyServer, ResourceInfo, Subscription};
+
+/// Bytes of a property data message other than its header and body data, including `F0`/`F7`.
+pub const PROPERTY_DATA_OVERHEAD: usize = 2 + HEADER_LEN + 1 + 2 + 2 + 2 + 2;
+
+/// Asks a device whether it supports Property Exchange, advertising the capabilities of the
+/// sender.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct PropertyCapabilitiesInquiry {
+    /// The device sending the inquiry.
+    pub source: MUID,
+
+    /// The device being asked.
+    pub destination: MUID,
+
+    /// The number of requests the sender can handle at the same time.
+    pub max_requests: u8,
+
+    /// The major version of Property Exchange supported by the sender.
+    pub major_version: u8,
+
+    /// The minor version of Property Exchange supported by the sender.
+    pub minor_version: u8,
+}
+
+/// The Property Exchange capabilities of a device.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct PropertyCapabilitiesReply {
+    /// The device replying.
+    pub source: MUID,
+
+    /// The device that sent the inquiry.
+    pub destination: MUID,
+
+    /// The number of requests the device can handle at the same time.
+    pub max_requests: u8,
+
+    /// The major version of Property Exchange supported by the device.
+    pub major_version: u8,
+
+    /// The minor version of Property Exchange supported by the device.
+    pub minor_version: u8,
+}
+
+/// The kinds of property data messages.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum PropertyMessageKind {
+    /// Inquiry: Get Property Data.
+    Get = 0x34,
+
+    /// Reply to Get Property Data.
+    GetReply = 0x35,
+
+    /// Inquiry: Set Property Data.
+    Set = 0x36,
+
+    /// Reply to Set Property Data.
+    SetReply = 0x37,
+
+    /// Subscription, sent both by subscribers and by the device notifying them.
+    Subscription = 0x38,
+
+    /// Reply to Subscription.
+    SubscriptionReply = 0x39,
+
+    /// Notify, to end or time out a request.
+    Notify = 0x3f,
+}
+
+impl PropertyMessageKind {
+    /// Returns true if the message is a reply to a request.
+    pub fn is_reply(&self) -> bool {
+        matches!(
+            self,
+            PropertyMessageKind::GetReply
+                | PropertyMessageKind::SetReply
+                | PropertyMessageKind::SubscriptionReply
+        )
+    }
+}
+
+impl TryFrom<u8> for PropertyMessageKind {
+    type Error = Error;
+
+    fn try_from(sub_id: u8) -> Result<Self, Self::Error> {
+        match sub_id {
+            0x34 => Ok(PropertyMessageKind::Get),
+            0x35 => Ok(PropertyMessageKind::GetReply),
+            0x36 => Ok(PropertyMessageKind::Set),
+            0x37 => Ok(PropertyMessageKind::SetReply),
+            0x38 => Ok(PropertyMessageKind::Subscription),
+            0x39 => Ok(PropertyMessageKind::SubscriptionReply),
+            0x3f => Ok(PropertyMessageKind::Notify),
+            sub_id => Err(Error::UnexpectedSubId(sub_id)),
+        }
+    }
+}
+
+/// A single chunk of a property data message.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct PropertyData<'a> {
+    /// The kind of message.
+    pub kind: PropertyMessageKind,
+
+    /// The device sending the message.
+    pub source: MUID,
+
+    /// The device receiving the message.
+    pub destination: MUID,
+
+    /// Identifies a request and its reply, 0-127.
+    pub request_id: u8,
+
+    /// The JSON header, only sent in the first chunk.
+    pub header: &'a [u8],
+
+    /// The number of chunks in the message, or 0 if unknown.
+    pub chunks: u16,
+
+    /// The number of this chunk, starting at 1.
+    pub chunk: u16,
+
+    /// The part of the body in this chunk.
+    pub body: &'a [u8],
+}
+
+impl<'a> PropertyData<'a> {
+    /// Split a header and body into chunks that each fit in SysEx messages of at most
+    /// `max_sysex_size` bytes, including `F0`/`F7`.
+    pub fn chunked(
+        kind: PropertyMessageKind,
+        source: MUID,
+        destination: MUID,
+        request_id: u8,
+        header: &'a [u8],
+        body: &'a [u8],
+        max_sysex_size: usize,
+    ) -> Result<PropertyChunks<'a>, Error> {
+        debug_assert!(request_id < 0x80, "Wrong integer size: request_id is u7");
+        let capacity = max_sysex_size
+            .checked_sub(PROPERTY_DATA_OVERHEAD)
+            .filter(|c| *c > 0)
+            .ok_or(Error::BufferTooSmall)?;
+        if header.len() > capacity || header.len() >= 0x4000 {
+            return Err(Error::BufferTooSmall);
+        }
+        let first = (capacity - header.len()).min(body.len());
+        let chunks = 1 + (body.len() - first).div_ceil(capacity);
+        if chunks >= 0x4000 {
+            return Err(Error::InvalidValue);
+        }
+        Ok(PropertyChunks {
+            message: PropertyData {
+                kind,
+                source,
+                destination,
+                request_id,
+                header,
+                chunks: chunks as u16,
+                chunk: 0,
+                body: &[],
+            },
+            capacity,
+            first,
+            remaining: body,
+        })
+    }
+}
+
+/// An iterator over the chunks of a property data message, see [PropertyData::chunked].
+#[derive(Clone, Debug)]
+pub struct PropertyChunks<'a> {
+    message: PropertyData<'a>,
+    capacity: usize,
+    first: usize,
+    remaining: &'a [u8],
+}
+
+impl<'a> Iterator for PropertyChunks<'a> {
+    type Item = PropertyData<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.message.chunk == self.message.chunks {
+            return None;
+        }
+        let len = if self.message.chunk == 0 {
+            self.first
+        } else {
+            self.message.header = &[];
+            self.capacity.min(self.remaining.len())
+        };
+        let (body, rest) = self.remaining.split_at(len);
+        self.remaining = rest;
+        self.message.chunk += 1;
+        Some(PropertyData {
+            body,
+            ..self.message
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for PropertyCapabilitiesInquiry {
+    const SUB_ID: u8 = 0x30;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&[self.max_requests, self.major_version, self.minor_version])
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            max_requests: reader.u8()?,
+            major_version: reader.u8_or_default()?,
+            minor_version: reader.u8_or_default()?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for PropertyCapabilitiesReply {
+    const SUB_ID: u8 = 0x31;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&[self.max_requests, self.major_version, self.minor_version])
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            max_requests: reader.u8()?,
+            major_version: reader.u8_or_default()?,
+            minor_version: reader.u8_or_default()?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for PropertyData<'a> {
+    const SUB_ID: u8 = PropertyMessageKind::Get as u8;
+
+    fn accepts(sub_id: u8) -> bool {
+        PropertyMessageKind::try_from(sub_id).is_ok()
+    }
+
+    fn subcategory(&self) -> u8 {
+        self.kind as u8
+    }
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        if self.header.len() >= 0x4000 || self.body.len() >= 0x4000 {
+            return Err(Error::InvalidValue);
+        }
+        writer.u8(self.request_id)?;
+        writer.u14(self.header.len() as u16)?;
+        writer.bytes(self.header)?;
+        writer.u14(self.chunks)?;
+        writer.u14(self.chunk)?;
+        writer.u14(self.body.len() as u16)?;
+        writer.bytes(self.body)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let kind = PropertyMessageKind::try_from(header.sub_id)?;
+        let request_id = reader.u8()?;
+        let len = reader.u14()?;
+        let json = reader.bytes(len as usize)?;
+        let chunks = reader.u14()?;
+        let chunk = reader.u14()?;
+        let len = reader.u14()?;
+        Ok(Self {
+            kind,
+            source: header.source,
+            destination: header.destination,
+            request_id,
+            header: json,
+            chunks,
+            chunk,
+            body: reader.bytes(len as usize)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::CiMessage;
+
+    #[test]
+    fn capabilities_round_trip() {
+        let inquiry = PropertyCapabilitiesInquiry {
+            source: MUID::from_wire([1, 0, 0, 0]),
+            destination: MUID::from_wire([2, 0, 0, 0]),
+            max_requests: 4,
+            major_version: 0,
+            minor_version: 0,
+        };
+        let sysex = inquiry.to_sysex().unwrap();
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::PropertyCapabilitiesInquiry(inquiry))
+        );
+    }
+
+    #[test]
+    fn chunks_fit_max_sysex_size() {
+        let header = br#"{"resource":"ProgramList"}"#;
+        let body: Vec<u8> = (0..1000).map(|i| (i % 128) as u8).collect();
+        for max_sysex_size in [128, 200, 512, 4096] {
+            let chunks: Vec<_> = PropertyData::chunked(
+                PropertyMessageKind::GetReply,
+                MUID::from_wire([1, 0, 0, 0]),
+                MUID::from_wire([2, 0, 0, 0]),
+                7,
+                header,
+                &body,
+                max_sysex_size,
+            )
+            .unwrap()
+            .collect();
+            let mut received = Vec::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let sysex = chunk.to_sysex().unwrap();
+                assert!(sysex.len() + 2 <= max_sysex_size);
+                let decoded = PropertyData::decode(&sysex).unwrap();
+                assert_eq!(decoded, *chunk);
+                assert_eq!(decoded.chunk as usize, i + 1);
+                assert_eq!(decoded.chunks as usize, chunks.len());
+                assert_eq!(decoded.header.is_empty(), i > 0);
+                received.extend_from_slice(decoded.body);
+            }
+            assert_eq!(received, body);
+        }
+    }
+
+    #[test]
+    fn empty_body_is_one_chunk() {
+        let chunks: Vec<_> = PropertyData::chunked(
+            PropertyMessageKind::Get,
+            MUID::from_wire([1, 0, 0, 0]),
+            MUID::from_wire([2, 0, 0, 0]),
+            0,
+            br#"{"resource":"DeviceInfo"}"#,
+            &[],
+            128,
+        )
+        .unwrap()
+        .collect();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].chunk, chunks[0].chunks), (1, 1));
+        assert_eq!(
+            PropertyData::chunked(
+                PropertyMessageKind::Get,
+                MUID::from_wire([1, 0, 0, 0]),
+                MUID::from_wire([2, 0, 0, 0]),
+                0,
+                &[b' '; 200],
+                &[],
+                128,
+            )
+            .err(),
+            Some(Error::BufferTooSmall)
+        );
+    }
+}
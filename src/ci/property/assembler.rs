@@ -0,0 +1,204 @@
+//! Reassembly of chunked property data messages.
+use std::collections::HashMap;
+
+use super::header::PropertyHeader;
+use super::json::ParseJsonError;
+use super::{PropertyData, PropertyMessageKind};
+use crate::ci::{CapabilityInquiryMessage, Error};
+use crate::muid::MUID;
+
+/// A complete property data message.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Property {
+    /// The kind of message.
+    pub kind: PropertyMessageKind,
+
+    /// The device sending the message.
+    pub source: MUID,
+
+    /// The device receiving the message.
+    pub destination: MUID,
+
+    /// Identifies a request and its reply, 0-127.
+    pub request_id: u8,
+
+    /// The JSON header.
+    pub header: Vec<u8>,
+
+    /// The body, in the encoding declared by the header.
+    pub body: Vec<u8>,
+}
+
+impl Property {
+    /// Parse the JSON header.
+    pub fn parse_header(&self) -> Result<PropertyHeader, ParseJsonError> {
+        PropertyHeader::parse(&self.header)
+    }
+
+    /// Encode the message as SysEx messages of at most `max_sysex_size` bytes, including
+    /// `F0`/`F7`.
+    pub fn to_sysex(&self, max_sysex_size: usize) -> Result<Vec<Vec<u8>>, Error> {
+        let chunks = PropertyData::chunked(
+            self.kind,
+            self.source,
+            self.destination,
+            self.request_id,
+            &self.header,
+            &self.body,
+            max_sysex_size,
+        )?;
+        chunks.map(|chunk| chunk.to_sysex()).collect()
+    }
+}
+
+/// The default number of messages a [PropertyAssembler] keeps in flight.
+pub const MAX_PENDING: usize = 64;
+
+/// Collects the chunks of property data messages into complete messages.
+///
+/// Messages in flight are tracked per sender and request ID. A chunk that does not follow the
+/// previous chunk of its message drops the message, and so does the first chunk of a new
+/// message while [PropertyAssembler::is_full].
+#[derive(Clone, Debug)]
+pub struct PropertyAssembler {
+    pending: HashMap<(MUID, u8), (u16, Property)>,
+    max_pending: usize,
+}
+
+impl Default for PropertyAssembler {
+    fn default() -> Self {
+        Self {
+            pending: HashMap::new(),
+            max_pending: MAX_PENDING,
+        }
+    }
+}
+
+impl PropertyAssembler {
+    /// Create a new assembler, keeping up to [MAX_PENDING] messages in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define the number of messages kept in flight, from all senders.
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// Returns true if a message from a sender with a request ID is in flight.
+    pub fn is_pending(&self, source: MUID, request_id: u8) -> bool {
+        self.pending.contains_key(&(source, request_id))
+    }
+
+    /// The number of messages in flight from a sender.
+    pub fn pending(&self, source: MUID) -> usize {
+        self.pending.keys().filter(|(s, _)| *s == source).count()
+    }
+
+    /// Returns true if no more messages can be kept in flight.
+    pub fn is_full(&self) -> bool {
+        self.pending.len() >= self.max_pending
+    }
+
+    /// Add a chunk, returning the message if the chunk completes it.
+    pub fn push(&mut self, chunk: &PropertyData<'_>) -> Option<Property> {
+        let key = (chunk.source, chunk.request_id);
+        let (received, mut property) = if chunk.chunk == 1 {
+            let property = Property {
+                kind: chunk.kind,
+                source: chunk.source,
+                destination: chunk.destination,
+                request_id: chunk.request_id,
+                header: chunk.header.to_vec(),
+                body: Vec::new(),
+            };
+            (1, property)
+        } else {
+            let (received, property) = self.pending.remove(&key)?;
+            if chunk.chunk != received + 1 || chunk.kind != property.kind {
+                return None;
+            }
+            (chunk.chunk, property)
+        };
+        property.body.extend_from_slice(chunk.body);
+        if chunk.chunks != 0 && received >= chunk.chunks {
+            return Some(property);
+        }
+        if received == 1 && !self.is_pending(key.0, key.1) && self.is_full() {
+            return None;
+        }
+        self.pending.insert(key, (received, property));
+        None
+    }
+
+    /// Drop the partially received message of a request, eg when it times out.
+    pub fn cancel(&mut self, source: MUID, request_id: u8) {
+        self.pending.remove(&(source, request_id));
+    }
+
+    /// Drop all partially received messages.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_interleaved_messages() {
+        let message = |source: u8, body: &[u8]| Property {
+            kind: PropertyMessageKind::GetReply,
+            source: MUID::from_wire([source, 0, 0, 0]),
+            destination: MUID::from_wire([9, 0, 0, 0]),
+            request_id: 1,
+            header: PropertyHeader::for_status(200).to_json(),
+            body: body.to_vec(),
+        };
+        let a = message(1, &[b'a'; 500]);
+        let b = message(2, &[b'b'; 300]);
+        let a_sysex = a.to_sysex(128).unwrap();
+        let b_sysex = b.to_sysex(128).unwrap();
+        assert!(a_sysex.len() > b_sysex.len());
+
+        let mut assembler = PropertyAssembler::new();
+        let mut complete = Vec::new();
+        for i in 0..a_sysex.len() {
+            for sysex in [a_sysex.get(i), b_sysex.get(i)].iter().flatten() {
+                let chunk = PropertyData::decode(sysex).unwrap();
+                complete.extend(assembler.push(&chunk));
+            }
+        }
+        assert_eq!(complete, [b.clone(), a.clone()]);
+        assert_eq!(complete[0].parse_header().unwrap().status, Some(200));
+
+        // A missing chunk drops the message.
+        let chunks: Vec<_> = b_sysex
+            .iter()
+            .map(|s| PropertyData::decode(s).unwrap())
+            .collect();
+        assert_eq!(assembler.push(&chunks[0]), None);
+        assert_eq!(assembler.push(&chunks[2]), None);
+        assert_eq!(assembler.push(&chunks[3]), None);
+
+        // New messages are dropped while the assembler is full.
+        let mut assembler = PropertyAssembler::new().with_max_pending(1);
+        let a_chunks: Vec<_> = a_sysex
+            .iter()
+            .map(|s| PropertyData::decode(s).unwrap())
+            .collect();
+        assert_eq!(assembler.push(&a_chunks[0]), None);
+        assert!(assembler.is_full());
+        assert_eq!(assembler.pending(a.source), 1);
+        assert_eq!(assembler.push(&chunks[0]), None);
+        assert!(!assembler.is_pending(b.source, b.request_id));
+        let complete: Vec<_> = a_chunks[1..]
+            .iter()
+            .filter_map(|chunk| assembler.push(chunk))
+            .collect();
+        assert_eq!(complete, [a]);
+        assert!(!assembler.is_full());
+    }
+}
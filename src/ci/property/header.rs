@@ -0,0 +1,348 @@
+//! The JSON header of Property Exchange messages.
+use core::fmt;
+
+use super::json::{ParseJsonError, Value};
+use super::mcoded7;
+use crate::ci::Error;
+
+/// The most bytes a zlib+Mcoded7 body is inflated to by [Encoding::decode], so that a small
+/// compressed body cannot exhaust memory.
+pub const MAX_DECODED_LEN: usize = 1 << 20;
+
+/// How the body of a Property Exchange message is encoded.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Encoding {
+    /// 7-bit ASCII, the default.
+    Ascii,
+
+    /// 8-bit data packed into 7-bit bytes.
+    Mcoded7,
+
+    /// zlib compressed data packed into 7-bit bytes.
+    ZlibMcoded7,
+}
+
+impl Encoding {
+    /// The name of the encoding in a header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Ascii => "ASCII",
+            Encoding::Mcoded7 => "Mcoded7",
+            Encoding::ZlibMcoded7 => "zlib+Mcoded7",
+        }
+    }
+
+    /// Find an encoding by its name in a header.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Encoding::Ascii, Encoding::Mcoded7, Encoding::ZlibMcoded7]
+            .iter()
+            .copied()
+            .find(|e| e.name() == name)
+    }
+
+    /// Encode a body to send it. ASCII bodies must only hold 7-bit bytes.
+    pub fn encode(&self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Ascii => match body.iter().find(|b| **b >= 0x80) {
+                Some(byte) => Err(Error::InvalidByte(*byte)),
+                None => Ok(body.to_vec()),
+            },
+            Encoding::Mcoded7 => Ok(mcoded7::encode(body)),
+            #[cfg(feature = "zlib")]
+            Encoding::ZlibMcoded7 => {
+                let compressed = miniz_oxide::deflate::compress_to_vec_zlib(body, 6);
+                Ok(mcoded7::encode(&compressed))
+            }
+            #[cfg(not(feature = "zlib"))]
+            Encoding::ZlibMcoded7 => Err(Error::UnsupportedEncoding),
+        }
+    }
+
+    /// Decode a received body, inflating zlib+Mcoded7 bodies to at most [MAX_DECODED_LEN]
+    /// bytes.
+    pub fn decode(&self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        self.decode_with_limit(body, MAX_DECODED_LEN)
+    }
+
+    /// Decode a received body, failing with [Error::TooLarge] if a zlib+Mcoded7 body inflates
+    /// to more than `limit` bytes.
+    #[cfg_attr(not(feature = "zlib"), allow(unused_variables))]
+    pub fn decode_with_limit(&self, body: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Ascii => match body.iter().find(|b| **b >= 0x80) {
+                Some(byte) => Err(Error::InvalidByte(*byte)),
+                None => Ok(body.to_vec()),
+            },
+            Encoding::Mcoded7 => mcoded7::decode(body),
+            #[cfg(feature = "zlib")]
+            Encoding::ZlibMcoded7 => {
+                let compressed = mcoded7::decode(body)?;
+                miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&compressed, limit).map_err(
+                    |err| match err.status {
+                        miniz_oxide::inflate::TINFLStatus::HasMoreOutput => Error::TooLarge,
+                        _ => Error::InvalidValue,
+                    },
+                )
+            }
+            #[cfg(not(feature = "zlib"))]
+            Encoding::ZlibMcoded7 => Err(Error::UnsupportedEncoding),
+        }
+    }
+
+    /// Whether bodies can be encoded and decoded, which requires the `zlib` feature for
+    /// zlib+Mcoded7.
+    pub fn is_supported(&self) -> bool {
+        cfg!(feature = "zlib") || *self != Encoding::ZlibMcoded7
+    }
+}
+
+/// The `command` of a subscription message.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum SubscriptionCommand {
+    /// Start a subscription.
+    Start,
+
+    /// Some of the resource changed, the body holds the changes.
+    Partial,
+
+    /// The resource changed, the body holds all of it.
+    Full,
+
+    /// The resource changed, the subscriber should get it.
+    Notify,
+
+    /// End a subscription.
+    End,
+}
+
+impl SubscriptionCommand {
+    /// The name of the command in a header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SubscriptionCommand::Start => "start",
+            SubscriptionCommand::Partial => "partial",
+            SubscriptionCommand::Full => "full",
+            SubscriptionCommand::Notify => "notify",
+            SubscriptionCommand::End => "end",
+        }
+    }
+
+    /// Find a command by its name in a header.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            SubscriptionCommand::Start,
+            SubscriptionCommand::Partial,
+            SubscriptionCommand::Full,
+            SubscriptionCommand::Notify,
+            SubscriptionCommand::End,
+        ]
+        .iter()
+        .copied()
+        .find(|c| c.name() == name)
+    }
+}
+
+/// The fields of a Property Exchange header. Fields not known to this crate are kept in
+/// `other`, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PropertyHeader {
+    /// The name of the resource, eg `DeviceInfo`.
+    pub resource: Option<String>,
+
+    /// The ID of a resource when there are several of the same name.
+    pub res_id: Option<String>,
+
+    /// The index of the first item of a list resource to get.
+    pub offset: Option<u64>,
+
+    /// The maximum number of items of a list resource to get.
+    pub limit: Option<u64>,
+
+    /// The encoding of the body.
+    pub mutual_encoding: Option<Encoding>,
+
+    /// The media type of the body, `application/json` if not specified.
+    pub media_type: Option<String>,
+
+    /// The subscription command.
+    pub command: Option<SubscriptionCommand>,
+
+    /// Identifies a subscription.
+    pub subscribe_id: Option<String>,
+
+    /// If the body of a set request only holds changes to the resource.
+    pub set_partial: Option<bool>,
+
+    /// The number of items in a list resource.
+    pub total_count: Option<u64>,
+
+    /// The status code of a reply, eg 200.
+    pub status: Option<u16>,
+
+    /// A human readable message about the status.
+    pub message: Option<String>,
+
+    /// Other fields.
+    pub other: Vec<(String, Value)>,
+}
+
+impl PropertyHeader {
+    /// A header for a request of a resource.
+    pub fn for_resource(resource: impl Into<String>) -> Self {
+        Self {
+            resource: Some(resource.into()),
+            ..Self::default()
+        }
+    }
+
+    /// A header for a reply with a status code.
+    pub fn for_status(status: u16) -> Self {
+        Self {
+            status: Some(status),
+            ..Self::default()
+        }
+    }
+
+    /// Parse a header from JSON. Known fields with unexpected types are kept in `other`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseJsonError> {
+        let value = Value::parse(bytes)?;
+        let Value::Object(members) = value else {
+            return Err(ParseJsonError { position: 0 });
+        };
+        let mut header = Self::default();
+        for (key, value) in members {
+            let string = || value.as_str().map(String::from);
+            let known = match key.as_str() {
+                "resource" => string().map(|s| header.resource = Some(s)),
+                "resId" => string().map(|s| header.res_id = Some(s)),
+                "offset" => value.as_u64().map(|n| header.offset = Some(n)),
+                "limit" => value.as_u64().map(|n| header.limit = Some(n)),
+                "mutualEncoding" => value
+                    .as_str()
+                    .and_then(Encoding::from_name)
+                    .map(|e| header.mutual_encoding = Some(e)),
+                "mediaType" => string().map(|s| header.media_type = Some(s)),
+                "command" => value
+                    .as_str()
+                    .and_then(SubscriptionCommand::from_name)
+                    .map(|c| header.command = Some(c)),
+                "subscribeId" => string().map(|s| header.subscribe_id = Some(s)),
+                "setPartial" => value.as_bool().map(|b| header.set_partial = Some(b)),
+                "totalCount" => value.as_u64().map(|n| header.total_count = Some(n)),
+                "status" => value
+                    .as_u64()
+                    .filter(|n| *n <= u16::MAX as u64)
+                    .map(|n| header.status = Some(n as u16)),
+                "message" => string().map(|s| header.message = Some(s)),
+                _ => None,
+            };
+            if known.is_none() {
+                header.other.push((key, value));
+            }
+        }
+        Ok(header)
+    }
+
+    /// The header as a JSON object.
+    pub fn to_value(&self) -> Value {
+        let mut members: Vec<(String, Value)> = Vec::new();
+        let mut push = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                members.push((key.into(), value));
+            }
+        };
+        push("resource", self.resource.clone().map(Value::from));
+        push("resId", self.res_id.clone().map(Value::from));
+        push("offset", self.offset.map(Value::from));
+        push("limit", self.limit.map(Value::from));
+        push(
+            "mutualEncoding",
+            self.mutual_encoding.map(|e| e.name().into()),
+        );
+        push("mediaType", self.media_type.clone().map(Value::from));
+        push("command", self.command.map(|c| c.name().into()));
+        push("subscribeId", self.subscribe_id.clone().map(Value::from));
+        push("setPartial", self.set_partial.map(Value::from));
+        push("totalCount", self.total_count.map(Value::from));
+        push("status", self.status.map(|s| Value::from(s as u64)));
+        push("message", self.message.clone().map(Value::from));
+        members.extend(self.other.iter().cloned());
+        Value::Object(members)
+    }
+
+    /// The header as ASCII JSON, ready to be sent.
+    pub fn to_json(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+
+    /// The encoding of the body, ASCII if not specified.
+    pub fn encoding(&self) -> Encoding {
+        self.mutual_encoding.unwrap_or(Encoding::Ascii)
+    }
+}
+
+impl fmt::Display for PropertyHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = br#"{"resource":"ProgramList","resId":"bank1","offset":10,"limit":20,"mutualEncoding":"zlib+Mcoded7","x-vendor":[1]}"#;
+        let header = PropertyHeader::parse(text).unwrap();
+        assert_eq!(header.resource.as_deref(), Some("ProgramList"));
+        assert_eq!(header.offset, Some(10));
+        assert_eq!(header.encoding(), Encoding::ZlibMcoded7);
+        assert_eq!(header.other.len(), 1);
+        assert_eq!(header.to_json(), text);
+    }
+
+    #[test]
+    fn reply_header() {
+        let header = PropertyHeader {
+            command: Some(SubscriptionCommand::Start),
+            ..PropertyHeader::for_status(200)
+        };
+        assert_eq!(header.to_string(), r#"{"command":"start","status":200}"#);
+        assert_eq!(PropertyHeader::parse(&header.to_json()), Ok(header));
+        assert!(PropertyHeader::parse(b"[]").is_err());
+    }
+
+    #[test]
+    fn encodes_bodies() {
+        let body: Vec<u8> = (0..=255).cycle().take(2000).collect();
+        assert_eq!(Encoding::Ascii.encode(&body), Err(Error::InvalidByte(0x80)));
+        let encoded = Encoding::Mcoded7.encode(&body).unwrap();
+        assert_eq!(Encoding::Mcoded7.decode(&encoded), Ok(body.clone()));
+
+        let encoded = Encoding::ZlibMcoded7.encode(&body);
+        if Encoding::ZlibMcoded7.is_supported() {
+            let encoded = encoded.unwrap();
+            assert!(encoded.len() < body.len() / 2);
+            assert!(encoded.iter().all(|b| *b < 0x80));
+            assert_eq!(Encoding::ZlibMcoded7.decode(&encoded), Ok(body));
+            assert_eq!(
+                Encoding::ZlibMcoded7.decode(&[0x00, 0x01]),
+                Err(Error::InvalidValue)
+            );
+            assert_eq!(
+                Encoding::ZlibMcoded7.decode_with_limit(&encoded, 1999),
+                Err(Error::TooLarge)
+            );
+
+            // A megabyte of zeros compresses to about a kilobyte.
+            let bomb = Encoding::ZlibMcoded7.encode(&vec![0; MAX_DECODED_LEN + 1]);
+            assert_eq!(
+                Encoding::ZlibMcoded7.decode(&bomb.unwrap()),
+                Err(Error::TooLarge)
+            );
+        } else {
+            assert_eq!(encoded, Err(Error::UnsupportedEncoding));
+        }
+    }
+}
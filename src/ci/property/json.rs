@@ -0,0 +1,422 @@
+//! A minimal JSON document model for Property Exchange headers and bodies.
+//!
+//! Serialized JSON escapes every non-ASCII character, so it can be sent as 7-bit SysEx data.
+use core::fmt;
+use std::str::FromStr;
+
+/// A JSON value. Object members keep their order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// `null`
+    Null,
+
+    /// `true` or `false`
+    Bool(bool),
+
+    /// A number.
+    Number(f64),
+
+    /// A string.
+    String(String),
+
+    /// An array.
+    Array(Vec<Value>),
+
+    /// An object.
+    Object(Vec<(String, Value)>),
+}
+
+/// An error parsing JSON, at a byte offset of the input.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ParseJsonError {
+    /// The byte offset of the error.
+    pub position: usize,
+}
+
+impl fmt::Display for ParseJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid JSON at byte {}", self.position)
+    }
+}
+
+impl std::error::Error for ParseJsonError {}
+
+impl Value {
+    /// Parse JSON from bytes.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParseJsonError> {
+        let mut parser = Parser { bytes, position: 0 };
+        let value = parser.value(0)?;
+        parser.whitespace();
+        if parser.position != bytes.len() {
+            return Err(parser.error());
+        }
+        Ok(value)
+    }
+
+    /// The member of an object with a key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Set the member of an object, replacing any member with the same key. Does nothing if
+    /// the value is not an object.
+    pub fn insert(&mut self, key: impl Into<String>, value: Value) {
+        if let Value::Object(members) = self {
+            let key = key.into();
+            match members.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, v)) => *v = value,
+                None => members.push((key, value)),
+            }
+        }
+    }
+
+    /// The value as a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as a non-negative integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// The value as a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The value as an array.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// The members of the value if it is an object.
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = ParseJsonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s.as_bytes())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ' '..='~' => write!(f, "{c}")?,
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    write!(f, "\\u{unit:04x}")?;
+                }
+            }
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) if n.is_finite() => write!(f, "{n}"),
+            Value::Number(_) => f.write_str("null"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Maximum nesting of arrays and objects.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> ParseJsonError {
+        ParseJsonError {
+            position: self.position,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ParseJsonError> {
+        self.whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.error());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Value, ParseJsonError> {
+        if !self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.error());
+        }
+        self.position += literal.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseJsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error());
+        }
+        self.whitespace();
+        match self.peek().ok_or_else(|| self.error())? {
+            b'n' => self.literal("null", Value::Null),
+            b't' => self.literal("true", Value::Bool(true)),
+            b'f' => self.literal("false", Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            b'{' => {
+                self.position += 1;
+                let mut members = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value(depth + 1)?));
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseJsonError> {
+        let start = self.position;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.position += 1;
+        }
+        core::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Value::Number)
+            .ok_or(ParseJsonError { position: start })
+    }
+
+    fn hex4(&mut self) -> Result<u16, ParseJsonError> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .ok_or_else(|| self.error())?;
+        let unit = core::str::from_utf8(digits)
+            .ok()
+            .and_then(|s| u16::from_str_radix(s, 16).ok())
+            .ok_or_else(|| self.error())?;
+        self.position += 4;
+        Ok(unit)
+    }
+
+    fn string(&mut self) -> Result<String, ParseJsonError> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error());
+        }
+        self.position += 1;
+        let mut string = Vec::new();
+        loop {
+            let byte = self.peek().ok_or_else(|| self.error())?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error())?;
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let high = self.hex4()?;
+                            let units = if (0xd800..0xdc00).contains(&high)
+                                && self.bytes[self.position..].starts_with(b"\\u")
+                            {
+                                self.position += 2;
+                                vec![high, self.hex4()?]
+                            } else {
+                                vec![high]
+                            };
+                            char::decode_utf16(units)
+                                .next()
+                                .and_then(Result::ok)
+                                .ok_or_else(|| self.error())?
+                        }
+                        _ => return Err(self.error()),
+                    };
+                    let mut buffer = [0; 4];
+                    string.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                0x00..=0x1f => return Err(self.error()),
+                byte => string.push(byte),
+            }
+        }
+        String::from_utf8(string).map_err(|_| self.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"resource":"ProgramList","offset":0,"limit":10,"list":[true,null,-1.5],"name":"Piano \"Grand\"","nested":{}}"#;
+        let value: Value = text.parse().unwrap();
+        assert_eq!(
+            value.get("resource").and_then(Value::as_str),
+            Some("ProgramList")
+        );
+        assert_eq!(value.get("limit").and_then(Value::as_u64), Some(10));
+        assert_eq!(value.to_string(), text);
+    }
+
+    #[test]
+    fn escapes_non_ascii() {
+        let value = Value::from("Grand piano 🎹 ä");
+        let text = value.to_string();
+        assert!(text.is_ascii());
+        assert_eq!(text, r#""Grand piano \ud83c\udfb9 \u00e4""#);
+        assert_eq!(text.parse::<Value>(), Ok(value));
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert_eq!(
+            "{\"a\":}".parse::<Value>(),
+            Err(ParseJsonError { position: 5 })
+        );
+        assert!("[1,2".parse::<Value>().is_err());
+        assert!("\"abc".parse::<Value>().is_err());
+        assert!("{} x".parse::<Value>().is_err());
+        assert!("[".repeat(100).parse::<Value>().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! The Mcoded7 encoding of 8-bit data as 7-bit SysEx data bytes.
+//!
+//! Each group of up to 7 bytes is sent as a byte holding their high bits, followed by the
+//! bytes with their high bits cleared. The high bit of the first byte of the group is bit 6
+//! of the leading byte, the high bit of the seventh byte is bit 0.
+use crate::ci::Error;
+
+/// The length of `len` bytes of data once encoded.
+pub fn encoded_len(len: usize) -> usize {
+    len + len.div_ceil(7)
+}
+
+/// The maximum length of `len` bytes of encoded data once decoded.
+pub fn decoded_len(len: usize) -> usize {
+    len - len.div_ceil(8)
+}
+
+/// Encodes a stream of bytes in groups of 7.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Encoder {
+    group: [u8; 8],
+    len: usize,
+}
+
+impl Encoder {
+    /// Create an encoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode data, passing each complete group to `output`. Up to 6 bytes are kept until more
+    /// data is encoded or the encoder is finished.
+    pub fn encode(&mut self, data: &[u8], mut output: impl FnMut(&[u8])) {
+        for &byte in data {
+            self.len += 1;
+            self.group[0] |= (byte >> 7) << (7 - self.len);
+            self.group[self.len] = byte & 0x7f;
+            if self.len == 7 {
+                output(&self.group);
+                *self = Self::default();
+            }
+        }
+    }
+
+    /// Pass the last incomplete group, if any, to `output`.
+    pub fn finish(self, mut output: impl FnMut(&[u8])) {
+        if self.len > 0 {
+            output(&self.group[..=self.len]);
+        }
+    }
+}
+
+/// Decodes a stream of Mcoded7 data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Decoder {
+    high_bits: u8,
+    position: u8,
+}
+
+impl Decoder {
+    /// Create a decoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode data, passing each decoded byte to `output`. Fails if a byte has its high bit
+    /// set, after decoding the bytes before it.
+    pub fn decode(&mut self, data: &[u8], mut output: impl FnMut(u8)) -> Result<(), Error> {
+        for &byte in data {
+            if byte >= 0x80 {
+                return Err(Error::InvalidByte(byte));
+            }
+            if self.position == 0 {
+                self.high_bits = byte;
+            } else {
+                output(byte | ((self.high_bits << self.position) & 0x80));
+            }
+            self.position = (self.position + 1) % 8;
+        }
+        Ok(())
+    }
+}
+
+/// Encode data into a buffer, returning the encoded length.
+pub fn encode_into(data: &[u8], buffer: &mut [u8]) -> Result<usize, Error> {
+    let len = encoded_len(data.len());
+    let buffer = buffer.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+    let mut position = 0;
+    let mut encoder = Encoder::new();
+    let mut output = |group: &[u8]| {
+        buffer[position..position + group.len()].copy_from_slice(group);
+        position += group.len();
+    };
+    encoder.encode(data, &mut output);
+    encoder.finish(&mut output);
+    Ok(len)
+}
+
+/// Decode data into a buffer, returning the decoded length.
+pub fn decode_into(data: &[u8], buffer: &mut [u8]) -> Result<usize, Error> {
+    if buffer.len() < decoded_len(data.len()) {
+        return Err(Error::BufferTooSmall);
+    }
+    let mut len = 0;
+    Decoder::new().decode(data, |byte| {
+        buffer[len] = byte;
+        len += 1;
+    })?;
+    Ok(len)
+}
+
+/// Encode data.
+#[cfg(not(feature = "no-std"))]
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![0; encoded_len(data.len())];
+    encode_into(data, &mut encoded).unwrap();
+    encoded
+}
+
+/// Decode data.
+#[cfg(not(feature = "no-std"))]
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoded = Vec::with_capacity(decoded_len(data.len()));
+    Decoder::new().decode(data, |byte| decoded.push(byte))?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A xorshift generator, to produce the same inputs on every run.
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    #[test]
+    fn encodes_groups() {
+        let data = [0x81, 0x02, 0xff, 0x04, 0x05, 0x06, 0x87, 0x80];
+        let encoded = encode(&data);
+        assert_eq!(
+            encoded,
+            [0x51, 0x01, 0x02, 0x7f, 0x04, 0x05, 0x06, 0x07, 0x40, 0x00]
+        );
+        assert_eq!(decode(&encoded), Ok(data.to_vec()));
+        assert_eq!(decode(&[0x40, 0x80]), Err(Error::InvalidByte(0x80)));
+        assert_eq!(encode_into(&data, &mut [0; 9]), Err(Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn round_trip_random_data() {
+        let mut random = Random(0x2545_f491);
+        for _ in 0..500 {
+            let len = random.next() as usize % 200;
+            let data: Vec<u8> = (0..len).map(|_| random.next() as u8).collect();
+            let encoded = encode(&data);
+            assert_eq!(encoded.len(), encoded_len(len));
+            assert!(encoded.iter().all(|b| *b < 0x80));
+            assert_eq!(decoded_len(encoded.len()), len);
+
+            // Stream the data in pieces of random size.
+            let mut streamed = Vec::new();
+            let mut decoded = Vec::new();
+            let mut encoder = Encoder::new();
+            let mut decoder = Decoder::new();
+            let mut rest = &data[..];
+            while !rest.is_empty() {
+                let (piece, tail) = rest.split_at(random.next() as usize % (rest.len() + 1));
+                encoder.encode(piece, |group| streamed.extend_from_slice(group));
+                rest = tail;
+            }
+            encoder.finish(|group| streamed.extend_from_slice(group));
+            assert_eq!(streamed, encoded);
+            for piece in encoded.chunks(1 + random.next() as usize % 10) {
+                decoder.decode(piece, |byte| decoded.push(byte)).unwrap();
+            }
+            assert_eq!(decoded, data);
+        }
+    }
+}
@@ -0,0 +1,852 @@
+//! A Property Exchange responder that serves resources to other devices.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::header::{PropertyHeader, SubscriptionCommand};
+use super::json::Value;
+use super::{Property, PropertyAssembler, PropertyCapabilitiesReply, PropertyMessageKind};
+use crate::ci::{CapabilityInquiryMessage, CiMessage, Error, NotAcknowledged};
+use crate::muid::MUID;
+
+/// The request succeeded.
+pub const STATUS_OK: u16 = 200;
+
+/// The request was accepted and will be completed later.
+pub const STATUS_ACCEPTED: u16 = 202;
+
+/// The resource is busy or temporarily unavailable.
+pub const STATUS_UNAVAILABLE: u16 = 341;
+
+/// The body of a set request is invalid.
+pub const STATUS_BAD_DATA: u16 = 342;
+
+/// The device is handling too many requests.
+pub const STATUS_TOO_MANY_REQUESTS: u16 = 343;
+
+/// The header of a request is invalid.
+pub const STATUS_BAD_REQUEST: u16 = 400;
+
+/// The request is not authorized.
+pub const STATUS_NOT_AUTHORIZED: u16 = 403;
+
+/// The resource is not supported.
+pub const STATUS_NOT_FOUND: u16 = 404;
+
+/// The request is not allowed on the resource, eg setting a read-only resource.
+pub const STATUS_NOT_ALLOWED: u16 = 405;
+
+/// The body of a request is too large.
+pub const STATUS_PAYLOAD_TOO_LARGE: u16 = 413;
+
+/// The encoding or media type of a request is not supported.
+pub const STATUS_UNSUPPORTED_MEDIA_TYPE: u16 = 415;
+
+/// The device failed to complete the request.
+pub const STATUS_INTERNAL_ERROR: u16 = 500;
+
+/// The list of resources of a device.
+pub const RESOURCE_LIST: &str = "ResourceList";
+
+/// The identity of a device.
+pub const DEVICE_INFO: &str = "DeviceInfo";
+
+/// The channels of a device and their programs.
+pub const CHANNEL_LIST: &str = "ChannelList";
+
+/// The programs of a channel.
+pub const PROGRAM_LIST: &str = "ProgramList";
+
+/// JSON schemas of custom resources, selected by `resId`.
+pub const JSON_SCHEMA: &str = "JSONSchema";
+
+/// Why a request for a resource failed.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PropertyError {
+    /// The status code, eg [STATUS_NOT_FOUND].
+    pub status: u16,
+
+    /// A human readable description of the error.
+    pub message: Option<String>,
+}
+
+impl PropertyError {
+    /// An error with a status code.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            message: None,
+        }
+    }
+
+    /// Add a description of the error.
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "property error {}", self.status)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {message}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PropertyError {}
+
+/// How a resource can be used, as advertised in the ResourceList.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceInfo {
+    /// The name of the resource.
+    pub name: String,
+
+    /// Whether the resource can be set.
+    pub can_set: bool,
+
+    /// Whether other devices can subscribe to changes of the resource.
+    pub can_subscribe: bool,
+
+    /// Whether requests must select one of several resources with `resId`.
+    pub require_res_id: bool,
+
+    /// Whether the resource is a list that can be read in pages with `offset` and `limit`.
+    pub can_paginate: bool,
+
+    /// The JSON schema of the resource, for custom resources.
+    pub schema: Option<Value>,
+}
+
+impl ResourceInfo {
+    /// A read-only resource.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            can_set: false,
+            can_subscribe: false,
+            require_res_id: false,
+            can_paginate: false,
+            schema: None,
+        }
+    }
+
+    /// Notate the resource can be set.
+    pub fn with_set(mut self) -> Self {
+        self.can_set = true;
+        self
+    }
+
+    /// Notate the resource can be subscribed to.
+    pub fn with_subscribe(mut self) -> Self {
+        self.can_subscribe = true;
+        self
+    }
+
+    /// Notate requests must include a `resId`.
+    pub fn with_res_id(mut self) -> Self {
+        self.require_res_id = true;
+        self
+    }
+
+    /// Notate the resource is a list that can be read in pages.
+    pub fn with_pagination(mut self) -> Self {
+        self.can_paginate = true;
+        self
+    }
+
+    /// Add a JSON schema for the resource.
+    pub fn with_schema(mut self, schema: Value) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// The entry of the resource in the ResourceList.
+    pub fn to_value(&self) -> Value {
+        let mut value = Value::Object(vec![("resource".into(), self.name.as_str().into())]);
+        if self.can_set {
+            value.insert("canSet", "full".into());
+        }
+        if self.can_subscribe {
+            value.insert("canSubscribe", true.into());
+        }
+        if self.require_res_id {
+            value.insert("requireResId", true.into());
+        }
+        if self.can_paginate {
+            value.insert("canPaginate", true.into());
+        }
+        if let Some(schema) = &self.schema {
+            value.insert("schema", schema.clone());
+        }
+        value
+    }
+}
+
+type Getter = Box<dyn FnMut(&PropertyHeader) -> Result<Value, PropertyError>>;
+type Setter = Box<dyn FnMut(&PropertyHeader, Value) -> Result<(), PropertyError>>;
+
+struct Resource {
+    info: ResourceInfo,
+    get: Getter,
+    set: Option<Setter>,
+}
+
+/// A subscription of another device to a resource.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Subscription {
+    /// Identifies the subscription, chosen by the server.
+    pub subscribe_id: String,
+
+    /// The subscribed device.
+    pub subscriber: MUID,
+
+    /// The name of the resource.
+    pub resource: String,
+
+    /// The ID of the resource, if it has one.
+    pub res_id: Option<String>,
+}
+
+/// Serves resources to other devices, answering Property Exchange requests addressed to the
+/// local device.
+///
+/// The ResourceList and JSONSchema resources are generated from the registered resources and
+/// schemas. Getters and setters may fail with a [PropertyError], which is sent as the status of
+/// the reply. Bodies are JSON, sent in the `mutualEncoding` of the request; requests with an
+/// unsupported encoding are rejected.
+pub struct PropertyServer {
+    muid: MUID,
+    max_sysex_size: usize,
+    max_requests: u8,
+    resources: Vec<Resource>,
+    schemas: BTreeMap<String, Value>,
+    assembler: PropertyAssembler,
+    subscriptions: Vec<Subscription>,
+    next_subscribe_id: u32,
+    next_request_id: u8,
+}
+
+impl fmt::Debug for PropertyServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resources: Vec<_> = self.resources.iter().map(|r| &r.info.name).collect();
+        f.debug_struct("PropertyServer")
+            .field("muid", &self.muid)
+            .field("resources", &resources)
+            .field("subscriptions", &self.subscriptions)
+            .finish()
+    }
+}
+
+impl PropertyServer {
+    /// Create a server for the device with a MUID, serving only the ResourceList.
+    pub fn new(muid: MUID) -> Self {
+        Self {
+            muid,
+            max_sysex_size: 512,
+            max_requests: 1,
+            resources: Vec::new(),
+            schemas: BTreeMap::new(),
+            assembler: PropertyAssembler::new(),
+            subscriptions: Vec::new(),
+            next_subscribe_id: 1,
+            next_request_id: 0,
+        }
+    }
+
+    /// Define the maximum length of the SysEx messages sent, which should be no more than
+    /// the maximum of the receiver. Must be at least 128.
+    pub fn with_max_sysex_size(mut self, len: usize) -> Self {
+        debug_assert!(len >= 128);
+        self.max_sysex_size = len;
+        self
+    }
+
+    /// Define the number of simultaneous requests advertised in the capabilities reply. Requests
+    /// from a device that already has as many requests in flight are rejected with a Retry
+    /// NAK.
+    pub fn with_max_requests(mut self, max_requests: u8) -> Self {
+        debug_assert!(max_requests > 0 && max_requests < 0x80);
+        self.max_requests = max_requests;
+        self
+    }
+
+    /// Change the MUID of the local device, eg after a collision.
+    pub fn set_muid(&mut self, muid: MUID) {
+        self.muid = muid;
+    }
+
+    /// Serve a resource with a getter. Registering a resource again replaces it.
+    pub fn add_resource(
+        &mut self,
+        info: ResourceInfo,
+        get: impl FnMut(&PropertyHeader) -> Result<Value, PropertyError> + 'static,
+    ) {
+        self.insert(Resource {
+            info,
+            get: Box::new(get),
+            set: None,
+        });
+    }
+
+    /// Serve a resource with a getter and a setter.
+    pub fn add_settable_resource(
+        &mut self,
+        info: ResourceInfo,
+        get: impl FnMut(&PropertyHeader) -> Result<Value, PropertyError> + 'static,
+        set: impl FnMut(&PropertyHeader, Value) -> Result<(), PropertyError> + 'static,
+    ) {
+        self.insert(Resource {
+            info: info.with_set(),
+            get: Box::new(get),
+            set: Some(Box::new(set)),
+        });
+    }
+
+    /// Serve a read-only resource with a fixed value, eg DeviceInfo.
+    pub fn add_value(&mut self, info: ResourceInfo, value: Value) {
+        self.add_resource(info, move |_| Ok(value.clone()));
+    }
+
+    /// Serve a JSON schema from the JSONSchema resource, for requests with a `resId`.
+    pub fn add_schema(&mut self, res_id: impl Into<String>, schema: Value) {
+        self.schemas.insert(res_id.into(), schema);
+    }
+
+    /// The resources served, excluding ResourceList.
+    pub fn resources(&self) -> impl Iterator<Item = &ResourceInfo> + '_ {
+        self.resources.iter().map(|r| &r.info)
+    }
+
+    /// The current subscriptions.
+    pub fn subscriptions(&self) -> &[Subscription] {
+        &self.subscriptions
+    }
+
+    /// Handle a received message, returning the SysEx messages to send in reply.
+    pub fn receive(&mut self, message: &CiMessage<'_>) -> Result<Vec<Vec<u8>>, Error> {
+        if message.header().destination != self.muid {
+            return Ok(Vec::new());
+        }
+        match message {
+            CiMessage::PropertyCapabilitiesInquiry(inquiry) => {
+                let reply = PropertyCapabilitiesReply {
+                    source: self.muid,
+                    destination: inquiry.source,
+                    max_requests: self.max_requests,
+                    major_version: 0,
+                    minor_version: 0,
+                };
+                Ok(vec![reply.to_sysex()?])
+            }
+            CiMessage::PropertyData(chunk) => {
+                let new =
+                    chunk.chunk == 1 && !self.assembler.is_pending(chunk.source, chunk.request_id);
+                let full = self.assembler.pending(chunk.source) >= self.max_requests as usize
+                    || (chunk.chunks != 1 && self.assembler.is_full());
+                if new && full {
+                    let mut nak = NotAcknowledged::retry(self.muid, &message.header(), 0);
+                    // The request ID lets the requester tell which request was rejected.
+                    nak.status = nak.status.with_details([chunk.request_id, 0, 0, 0, 0]);
+                    return Ok(vec![nak.to_sysex()?]);
+                }
+                match self.assembler.push(chunk) {
+                    Some(request) => Ok(self.handle(request)),
+                    None => Ok(Vec::new()),
+                }
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Notify the subscribers of a resource that it changed, returning the SysEx messages
+    /// to send.
+    pub fn notify(&mut self, resource: &str, res_id: Option<&str>) -> Vec<Vec<u8>> {
+        let subscriptions: Vec<_> = self
+            .subscriptions
+            .iter()
+            .filter(|s| s.resource == resource && s.res_id.as_deref() == res_id)
+            .cloned()
+            .collect();
+        if subscriptions.is_empty() {
+            return Vec::new();
+        }
+        let mut header = PropertyHeader::for_resource(resource);
+        header.res_id = res_id.map(String::from);
+        let body = match self.get(&header) {
+            Ok(value) => value.to_string().into_bytes(),
+            Err(_) => return Vec::new(),
+        };
+        let mut messages = Vec::new();
+        for subscription in subscriptions {
+            let header = PropertyHeader {
+                command: Some(SubscriptionCommand::Full),
+                subscribe_id: Some(subscription.subscribe_id),
+                ..header.clone()
+            };
+            let request_id = self.next_request_id;
+            self.next_request_id = (self.next_request_id + 1) % 0x80;
+            messages.extend(self.send(
+                PropertyMessageKind::Subscription,
+                subscription.subscriber,
+                request_id,
+                &header,
+                body.clone(),
+            ));
+        }
+        messages
+    }
+
+    fn insert(&mut self, resource: Resource) {
+        self.resources.retain(|r| r.info.name != resource.info.name);
+        self.resources.push(resource);
+    }
+
+    fn send(
+        &self,
+        kind: PropertyMessageKind,
+        destination: MUID,
+        request_id: u8,
+        header: &PropertyHeader,
+        body: Vec<u8>,
+    ) -> Vec<Vec<u8>> {
+        let property = Property {
+            kind,
+            source: self.muid,
+            destination,
+            request_id,
+            header: header.to_json(),
+            body,
+        };
+        match property.to_sysex(self.max_sysex_size) {
+            Ok(messages) => messages,
+            Err(_) => {
+                let error = PropertyError::new(STATUS_PAYLOAD_TOO_LARGE);
+                let property = Property {
+                    header: error_header(&error).to_json(),
+                    body: Vec::new(),
+                    ..property
+                };
+                property.to_sysex(self.max_sysex_size).unwrap_or_default()
+            }
+        }
+    }
+
+    fn handle(&mut self, request: Property) -> Vec<Vec<u8>> {
+        let reply_kind = match request.kind {
+            PropertyMessageKind::Get => PropertyMessageKind::GetReply,
+            PropertyMessageKind::Set => PropertyMessageKind::SetReply,
+            PropertyMessageKind::Subscription => PropertyMessageKind::SubscriptionReply,
+            _ => return Vec::new(),
+        };
+        let result = match request.parse_header() {
+            Ok(header) => match request.kind {
+                PropertyMessageKind::Get => self.handle_get(&header),
+                PropertyMessageKind::Set => self.handle_set(&header, &request.body),
+                _ => self.handle_subscription(request.source, &header),
+            },
+            Err(_) => Err(PropertyError::new(STATUS_BAD_REQUEST)),
+        };
+        let (header, body) = match result {
+            Ok(reply) => reply,
+            Err(error) => (error_header(&error), Vec::new()),
+        };
+        let mut messages = self.send(
+            reply_kind,
+            request.source,
+            request.request_id,
+            &header,
+            body,
+        );
+        if request.kind == PropertyMessageKind::Set && header.status == Some(STATUS_OK) {
+            if let Ok(header) = request.parse_header() {
+                let resource = header.resource.unwrap_or_default();
+                messages.extend(self.notify(&resource, header.res_id.as_deref()));
+            }
+        }
+        messages
+    }
+
+    fn find(&mut self, header: &PropertyHeader) -> Result<&mut Resource, PropertyError> {
+        let name = header
+            .resource
+            .as_deref()
+            .ok_or_else(|| PropertyError::new(STATUS_BAD_REQUEST))?;
+        let resource = self
+            .resources
+            .iter_mut()
+            .find(|r| r.info.name == name)
+            .ok_or_else(|| PropertyError::new(STATUS_NOT_FOUND))?;
+        if resource.info.require_res_id && header.res_id.is_none() {
+            return Err(PropertyError::new(STATUS_BAD_REQUEST).with_message("resId is required"));
+        }
+        Ok(resource)
+    }
+
+    fn get(&mut self, header: &PropertyHeader) -> Result<Value, PropertyError> {
+        match header.resource.as_deref() {
+            Some(RESOURCE_LIST) => {
+                let mut list = vec![ResourceInfo::new(RESOURCE_LIST).to_value()];
+                if !self.schemas.is_empty() {
+                    list.push(ResourceInfo::new(JSON_SCHEMA).with_res_id().to_value());
+                }
+                list.extend(self.resources.iter().map(|r| r.info.to_value()));
+                Ok(Value::Array(list))
+            }
+            Some(JSON_SCHEMA) if !self.schemas.is_empty() => header
+                .res_id
+                .as_ref()
+                .and_then(|id| self.schemas.get(id))
+                .cloned()
+                .ok_or_else(|| PropertyError::new(STATUS_NOT_FOUND)),
+            _ => {
+                let resource = self.find(header)?;
+                (resource.get)(header)
+            }
+        }
+    }
+
+    fn handle_get(
+        &mut self,
+        header: &PropertyHeader,
+    ) -> Result<(PropertyHeader, Vec<u8>), PropertyError> {
+        let encoding = header.encoding();
+        if !encoding.is_supported() {
+            return Err(PropertyError::new(STATUS_UNSUPPORTED_MEDIA_TYPE));
+        }
+        let value = self.get(header)?;
+        let mut reply = PropertyHeader::for_status(STATUS_OK);
+        reply.mutual_encoding = header.mutual_encoding;
+        let paginate = header.offset.is_some() || header.limit.is_some();
+        let value = match value {
+            Value::Array(items) if paginate => {
+                reply.total_count = Some(items.len() as u64);
+                let offset = header.offset.unwrap_or(0) as usize;
+                let limit = header.limit.map_or(usize::MAX, |l| l as usize);
+                Value::Array(items.into_iter().skip(offset).take(limit).collect())
+            }
+            value => value,
+        };
+        let body = encoding
+            .encode(value.to_string().as_bytes())
+            .map_err(|_| PropertyError::new(STATUS_INTERNAL_ERROR))?;
+        Ok((reply, body))
+    }
+
+    fn handle_set(
+        &mut self,
+        header: &PropertyHeader,
+        body: &[u8],
+    ) -> Result<(PropertyHeader, Vec<u8>), PropertyError> {
+        let body = match header.encoding().decode(body) {
+            Ok(body) => body,
+            Err(Error::UnsupportedEncoding) => {
+                return Err(PropertyError::new(STATUS_UNSUPPORTED_MEDIA_TYPE))
+            }
+            Err(Error::TooLarge) => return Err(PropertyError::new(STATUS_PAYLOAD_TOO_LARGE)),
+            Err(_) => return Err(PropertyError::new(STATUS_BAD_DATA)),
+        };
+        let resource = self.find(header)?;
+        let set = resource
+            .set
+            .as_mut()
+            .ok_or_else(|| PropertyError::new(STATUS_NOT_ALLOWED))?;
+        let value = Value::parse(&body).map_err(|_| PropertyError::new(STATUS_BAD_DATA))?;
+        set(header, value)?;
+        Ok((PropertyHeader::for_status(STATUS_OK), Vec::new()))
+    }
+
+    fn handle_subscription(
+        &mut self,
+        subscriber: MUID,
+        header: &PropertyHeader,
+    ) -> Result<(PropertyHeader, Vec<u8>), PropertyError> {
+        let mut reply = PropertyHeader::for_status(STATUS_OK);
+        match header.command {
+            Some(SubscriptionCommand::Start) => {
+                let resource = self.find(header)?;
+                if !resource.info.can_subscribe {
+                    return Err(PropertyError::new(STATUS_NOT_ALLOWED));
+                }
+                let subscribe_id = format!("sub{}", self.next_subscribe_id);
+                self.next_subscribe_id += 1;
+                self.subscriptions.push(Subscription {
+                    subscribe_id: subscribe_id.clone(),
+                    subscriber,
+                    resource: header.resource.clone().unwrap_or_default(),
+                    res_id: header.res_id.clone(),
+                });
+                reply.subscribe_id = Some(subscribe_id);
+            }
+            Some(SubscriptionCommand::End) => {
+                let id = header.subscribe_id.as_deref();
+                let len = self.subscriptions.len();
+                self.subscriptions.retain(|s| {
+                    !(s.subscriber == subscriber && Some(s.subscribe_id.as_str()) == id)
+                });
+                if self.subscriptions.len() == len {
+                    return Err(PropertyError::new(STATUS_NOT_FOUND));
+                }
+            }
+            _ => return Err(PropertyError::new(STATUS_BAD_REQUEST)),
+        }
+        Ok((reply, Vec::new()))
+    }
+}
+
+fn error_header(error: &PropertyError) -> PropertyHeader {
+    PropertyHeader {
+        message: error.message.clone(),
+        ..PropertyHeader::for_status(error.status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::property::Encoding;
+    use crate::ci::{PropertyData, StatusCode};
+    use crate::muid::muid;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const HOST: u32 = 1;
+    const DEVICE: u32 = 2;
+
+    /// Send a request from the host and reassemble the replies.
+    fn request(
+        server: &mut PropertyServer,
+        kind: PropertyMessageKind,
+        header: PropertyHeader,
+        body: &[u8],
+    ) -> Vec<Property> {
+        let request = Property {
+            kind,
+            source: muid(HOST),
+            destination: muid(DEVICE),
+            request_id: 5,
+            header: header.to_json(),
+            body: body.to_vec(),
+        };
+        let mut replies = Vec::new();
+        let mut assembler = PropertyAssembler::new();
+        for sysex in request.to_sysex(128).unwrap() {
+            for reply in server.receive(&CiMessage::decode(&sysex).unwrap()).unwrap() {
+                let chunk = PropertyData::decode(&reply).unwrap();
+                assert!(reply.len() + 2 <= 512);
+                replies.extend(assembler.push(&chunk));
+            }
+        }
+        replies
+    }
+
+    fn body(property: &Property) -> Value {
+        Value::parse(&property.body).unwrap()
+    }
+
+    fn status(property: &Property) -> Option<u16> {
+        property.parse_header().unwrap().status
+    }
+
+    #[test]
+    fn serves_resource_list_and_device_info() {
+        let mut server = PropertyServer::new(muid(DEVICE));
+        let info: Value = r#"{"manufacturer":"Acme","model":"Synth"}"#.parse().unwrap();
+        server.add_value(ResourceInfo::new(DEVICE_INFO), info.clone());
+        server.add_schema("x_settings", r#"{"type":"object"}"#.parse().unwrap());
+
+        let replies = request(
+            &mut server,
+            PropertyMessageKind::Get,
+            PropertyHeader::for_resource(RESOURCE_LIST),
+            &[],
+        );
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].kind, PropertyMessageKind::GetReply);
+        assert_eq!(replies[0].request_id, 5);
+        let list = body(&replies[0]);
+        let names: Vec<_> = list
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r.get("resource").and_then(Value::as_str).unwrap())
+            .collect();
+        assert_eq!(names, [RESOURCE_LIST, JSON_SCHEMA, DEVICE_INFO]);
+
+        let replies = request(
+            &mut server,
+            PropertyMessageKind::Get,
+            PropertyHeader::for_resource(DEVICE_INFO),
+            &[],
+        );
+        assert_eq!(status(&replies[0]), Some(STATUS_OK));
+        assert_eq!(body(&replies[0]), info);
+
+        let header = PropertyHeader {
+            res_id: Some("x_settings".into()),
+            ..PropertyHeader::for_resource(JSON_SCHEMA)
+        };
+        let replies = request(&mut server, PropertyMessageKind::Get, header, &[]);
+        assert_eq!(
+            body(&replies[0]).get("type").and_then(Value::as_str),
+            Some("object")
+        );
+
+        let replies = request(
+            &mut server,
+            PropertyMessageKind::Get,
+            PropertyHeader::for_resource("Missing"),
+            &[],
+        );
+        assert_eq!(status(&replies[0]), Some(STATUS_NOT_FOUND));
+    }
+
+    #[test]
+    fn paginates_lists() {
+        let mut server = PropertyServer::new(muid(DEVICE));
+        let programs: Vec<Value> = (0..100u64)
+            .map(|i| Value::Object(vec![("title".into(), format!("Program {i}").into())]))
+            .collect();
+        server.add_value(
+            ResourceInfo::new(PROGRAM_LIST).with_pagination(),
+            Value::Array(programs),
+        );
+        let header = PropertyHeader {
+            offset: Some(10),
+            limit: Some(5),
+            ..PropertyHeader::for_resource(PROGRAM_LIST)
+        };
+        let replies = request(&mut server, PropertyMessageKind::Get, header, &[]);
+        let reply = replies[0].parse_header().unwrap();
+        assert_eq!(reply.total_count, Some(100));
+        let page = body(&replies[0]);
+        let page = page.as_array().unwrap();
+        assert_eq!(page.len(), 5);
+        assert_eq!(
+            page[0].get("title").and_then(Value::as_str),
+            Some("Program 10")
+        );
+
+        // Without pagination the whole list is sent, in several chunks.
+        let replies = request(
+            &mut server,
+            PropertyMessageKind::Get,
+            PropertyHeader::for_resource(PROGRAM_LIST),
+            &[],
+        );
+        assert_eq!(body(&replies[0]).as_array().unwrap().len(), 100);
+
+        // Replies are sent in the encoding of the request.
+        let header = PropertyHeader {
+            mutual_encoding: Some(Encoding::Mcoded7),
+            ..PropertyHeader::for_resource(PROGRAM_LIST)
+        };
+        let replies = request(&mut server, PropertyMessageKind::Get, header, &[]);
+        let reply = replies[0].parse_header().unwrap();
+        assert_eq!(reply.mutual_encoding, Some(Encoding::Mcoded7));
+        let list = Value::parse(&Encoding::Mcoded7.decode(&replies[0].body).unwrap()).unwrap();
+        assert_eq!(list.as_array().unwrap().len(), 100);
+    }
+
+    #[test]
+    fn sets_and_notifies_subscribers() {
+        let volume = Rc::new(RefCell::new(Value::from(100u64)));
+        let mut server = PropertyServer::new(muid(DEVICE));
+        let get = volume.clone();
+        let set = volume.clone();
+        server.add_settable_resource(
+            ResourceInfo::new("X-Volume").with_subscribe(),
+            move |_| Ok(get.borrow().clone()),
+            move |_, value| match value {
+                Value::Number(_) => {
+                    *set.borrow_mut() = value;
+                    Ok(())
+                }
+                _ => Err(PropertyError::new(STATUS_BAD_DATA)),
+            },
+        );
+
+        let header = PropertyHeader {
+            command: Some(SubscriptionCommand::Start),
+            ..PropertyHeader::for_resource("X-Volume")
+        };
+        let replies = request(&mut server, PropertyMessageKind::Subscription, header, &[]);
+        let reply = replies[0].parse_header().unwrap();
+        assert_eq!(reply.status, Some(STATUS_OK));
+        let subscribe_id = reply.subscribe_id.unwrap();
+        assert_eq!(server.subscriptions().len(), 1);
+
+        let replies = request(
+            &mut server,
+            PropertyMessageKind::Set,
+            PropertyHeader::for_resource("X-Volume"),
+            b"64",
+        );
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].kind, PropertyMessageKind::SetReply);
+        assert_eq!(status(&replies[0]), Some(STATUS_OK));
+        assert_eq!(replies[1].kind, PropertyMessageKind::Subscription);
+        let notification = replies[1].parse_header().unwrap();
+        assert_eq!(notification.command, Some(SubscriptionCommand::Full));
+        assert_eq!(
+            notification.subscribe_id.as_deref(),
+            Some(subscribe_id.as_str())
+        );
+        assert_eq!(body(&replies[1]), Value::from(64u64));
+
+        let replies = request(
+            &mut server,
+            PropertyMessageKind::Set,
+            PropertyHeader::for_resource("X-Volume"),
+            b"\"loud\"",
+        );
+        assert_eq!(status(&replies[0]), Some(STATUS_BAD_DATA));
+
+        let header = PropertyHeader {
+            command: Some(SubscriptionCommand::End),
+            subscribe_id: Some(subscribe_id),
+            ..PropertyHeader::for_resource("X-Volume")
+        };
+        let replies = request(&mut server, PropertyMessageKind::Subscription, header, &[]);
+        assert_eq!(status(&replies[0]), Some(STATUS_OK));
+        assert!(server.subscriptions().is_empty());
+        assert!(server.notify("X-Volume", None).is_empty());
+    }
+
+    #[test]
+    fn rejects_too_many_requests() {
+        let mut server = PropertyServer::new(muid(DEVICE));
+        server.add_settable_resource(
+            ResourceInfo::new("X-Name"),
+            |_| Ok(Value::from("")),
+            |_, _| Ok(()),
+        );
+        let set = |request_id| Property {
+            kind: PropertyMessageKind::Set,
+            source: muid(HOST),
+            destination: muid(DEVICE),
+            request_id,
+            header: PropertyHeader::for_resource("X-Name").to_json(),
+            body: format!("\"{}\"", "x".repeat(300)).into_bytes(),
+        };
+        let first = set(5).to_sysex(128).unwrap();
+        let second = set(6).to_sysex(128).unwrap();
+        assert!(first.len() > 1);
+        let mut receive =
+            |sysex: &[u8]| server.receive(&CiMessage::decode(sysex).unwrap()).unwrap();
+
+        assert!(receive(&first[0]).is_empty());
+        let sent = receive(&second[0]);
+        let nak = NotAcknowledged::decode(&sent[0]).unwrap();
+        assert_eq!(nak.status.code, StatusCode::Retry);
+        assert_eq!(nak.destination, muid(HOST));
+        assert_eq!(nak.status.details[0], 6);
+        assert!(receive(&second[1]).is_empty());
+
+        let sent: Vec<_> = first[1..].iter().flat_map(|sysex| receive(sysex)).collect();
+        let reply = PropertyData::decode(&sent[0]).unwrap();
+        assert_eq!(reply.kind, PropertyMessageKind::SetReply);
+        assert_eq!(reply.request_id, 5);
+    }
+}
@@ -0,0 +1,495 @@
+//! Protocol Negotiation from MIDI-CI 1.1: messages to agree on the MIDI 1.0 or MIDI 2.0
+//! protocol for a connection. Deprecated by MIDI-CI 1.2, but still used by older devices.
+use core::convert::TryFrom;
+use core::hash::{Hash, Hasher};
+use core::{fmt, slice};
+
+use super::sysex::{Header, Reader, Writer};
+use super::{
+    CapabilityInquiryMessage, Error, InitiateProtocolNegotiation, MidiVersion, Protocol,
+    SetNewProtocol,
+};
+use crate::muid::MUID;
+
+#[cfg(not(feature = "no-std"))]
+mod negotiation;
+#[cfg(not(feature = "no-std"))]
+pub use self::negotiation::{
+    NegotiationEvent, ProtocolNegotiation, NEGOTIATION_TIMEOUT, TEST_DELAY,
+};
+
+/// The length of a protocol on the wire.
+const PROTOCOL_LEN: usize = 5;
+
+/// The data of the test messages sent once a new protocol is set.
+const TEST_DATA: [u8; 48] = {
+    let mut data = [0; 48];
+    let mut i = 0;
+    while i < data.len() {
+        data[i] = i as u8;
+        i += 1;
+    }
+    data
+};
+
+impl TryFrom<u8> for MidiVersion {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Midi1),
+            0x02 => Ok(Self::Midi2),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+impl Protocol {
+    /// Returns true if both protocols are the same MIDI version and protocol version, ignoring
+    /// extensions.
+    pub fn is_compatible(&self, other: &Protocol) -> bool {
+        self.midi_version == other.midi_version && self.version == other.version
+    }
+
+    fn to_bytes(self) -> [u8; PROTOCOL_LEN] {
+        [self.midi_version as u8, self.version, self.extensions, 0, 0]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            midi_version: MidiVersion::try_from(bytes[0])?,
+            version: bytes[1],
+            extensions: bytes[2],
+        })
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, Error> {
+        Self::from_bytes(reader.bytes(PROTOCOL_LEN)?)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Protocols<'a> {
+    Decoded(&'a [Protocol]),
+    Encoded(&'a [u8]),
+}
+
+/// A list of protocols, either borrowed from the caller or from a received message.
+#[derive(Clone, Copy)]
+pub struct ProtocolList<'a>(Protocols<'a>);
+
+impl<'a> ProtocolList<'a> {
+    /// A list of protocols.
+    pub fn new(protocols: &'a [Protocol]) -> Self {
+        Self(Protocols::Decoded(protocols))
+    }
+
+    /// The number of protocols.
+    pub fn len(&self) -> usize {
+        match self.0 {
+            Protocols::Decoded(protocols) => protocols.len(),
+            Protocols::Encoded(bytes) => bytes.len() / PROTOCOL_LEN,
+        }
+    }
+
+    /// Returns true if the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The protocols, in order of preference.
+    pub fn iter(&self) -> ProtocolIter<'a> {
+        match self.0 {
+            Protocols::Decoded(protocols) => ProtocolIter(Iter::Decoded(protocols.iter())),
+            Protocols::Encoded(bytes) => ProtocolIter(Iter::Encoded(bytes.chunks(PROTOCOL_LEN))),
+        }
+    }
+
+    /// The first protocol in the list compatible with one of `other`, with the extensions
+    /// supported by both.
+    pub fn negotiate(&self, other: &ProtocolList<'_>) -> Option<Protocol> {
+        self.iter().find_map(|protocol| {
+            other
+                .iter()
+                .find(|p| p.is_compatible(&protocol))
+                .map(|p| Protocol {
+                    extensions: protocol.extensions & p.extensions,
+                    ..protocol
+                })
+        })
+    }
+
+    fn write(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        debug_assert!(
+            self.len() < 0x80,
+            "Wrong integer size: protocol count is u7"
+        );
+        writer.u8(self.len() as u8)?;
+        for protocol in self.iter() {
+            writer.bytes(&protocol.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read(reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let len = reader.u8()? as usize;
+        let bytes = reader.bytes(len * PROTOCOL_LEN)?;
+        for protocol in bytes.chunks(PROTOCOL_LEN) {
+            Protocol::from_bytes(protocol)?;
+        }
+        Ok(Self(Protocols::Encoded(bytes)))
+    }
+}
+
+impl<'a> From<&'a [Protocol]> for ProtocolList<'a> {
+    fn from(protocols: &'a [Protocol]) -> Self {
+        Self::new(protocols)
+    }
+}
+
+impl<'a> IntoIterator for ProtocolList<'a> {
+    type Item = Protocol;
+    type IntoIter = ProtocolIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl PartialEq for ProtocolList<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for ProtocolList<'_> {}
+
+impl Hash for ProtocolList<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for protocol in self.iter() {
+            protocol.hash(state);
+        }
+    }
+}
+
+impl fmt::Debug for ProtocolList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Iter<'a> {
+    Decoded(slice::Iter<'a, Protocol>),
+    Encoded(slice::Chunks<'a, u8>),
+}
+
+/// An iterator over a [ProtocolList].
+#[derive(Clone, Debug)]
+pub struct ProtocolIter<'a>(Iter<'a>);
+
+impl Iterator for ProtocolIter<'_> {
+    type Item = Protocol;
+
+    fn next(&mut self) -> Option<Protocol> {
+        match &mut self.0 {
+            Iter::Decoded(protocols) => protocols.next().copied(),
+            // Lists are validated when decoded.
+            Iter::Encoded(chunks) => chunks.next().and_then(|b| Protocol::from_bytes(b).ok()),
+        }
+    }
+}
+
+/// Reply to Initiate Protocol Negotiation, listing the protocols supported by the responder.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ProtocolNegotiationReply<'a> {
+    /// The device replying.
+    pub source: MUID,
+
+    /// The device that initiated the negotiation.
+    pub destination: MUID,
+
+    /// The authority of the responder.
+    pub authority: u8,
+
+    /// The supported protocols, preferred first.
+    pub protocols: ProtocolList<'a>,
+}
+
+/// Sent by the initiator in the new protocol, after a delay, to test it.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct TestNewProtocol {
+    /// The initiator.
+    pub source: MUID,
+
+    /// The responder.
+    pub destination: MUID,
+
+    /// The authority of the initiator.
+    pub authority: u8,
+}
+
+/// Sent by the responder in the new protocol in reply to [TestNewProtocol].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct TestNewProtocolReply {
+    /// The responder.
+    pub source: MUID,
+
+    /// The initiator.
+    pub destination: MUID,
+
+    /// The authority of the responder.
+    pub authority: u8,
+}
+
+/// Sent by the initiator once the new protocol was tested.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct NewProtocolEstablished {
+    /// The initiator.
+    pub source: MUID,
+
+    /// The responder.
+    pub destination: MUID,
+
+    /// The authority of the initiator.
+    pub authority: u8,
+}
+
+fn read_test_data(reader: &mut Reader<'_>) -> Result<(), Error> {
+    if reader.bytes(TEST_DATA.len())? != TEST_DATA {
+        return Err(Error::InvalidValue);
+    }
+    Ok(())
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for InitiateProtocolNegotiation<'a> {
+    const SUB_ID: u8 = 0x10;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.authority)?;
+        self.protocols.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            authority: reader.u8()?,
+            protocols: ProtocolList::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for ProtocolNegotiationReply<'a> {
+    const SUB_ID: u8 = 0x11;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.authority)?;
+        self.protocols.write(writer)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            authority: reader.u8()?,
+            protocols: ProtocolList::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for SetNewProtocol {
+    const SUB_ID: u8 = 0x12;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.authority)?;
+        writer.bytes(&self.protocol.to_bytes())
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            authority: reader.u8()?,
+            protocol: Protocol::read(reader)?,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for TestNewProtocol {
+    const SUB_ID: u8 = 0x13;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.authority)?;
+        writer.bytes(&TEST_DATA)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let authority = reader.u8()?;
+        read_test_data(reader)?;
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            authority,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for TestNewProtocolReply {
+    const SUB_ID: u8 = 0x14;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.authority)?;
+        writer.bytes(&TEST_DATA)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        let authority = reader.u8()?;
+        read_test_data(reader)?;
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            authority,
+        })
+    }
+}
+
+impl<'a> CapabilityInquiryMessage<'a> for NewProtocolEstablished {
+    const SUB_ID: u8 = 0x15;
+
+    fn source(&self) -> MUID {
+        self.source
+    }
+
+    fn dest(&self) -> MUID {
+        self.destination
+    }
+
+    fn write_data(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.u8(self.authority)
+    }
+
+    fn read_data(header: &Header, reader: &mut Reader<'a>) -> Result<Self, Error> {
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            authority: reader.u8()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::{AuthorityLevel, CiMessage};
+
+    #[test]
+    fn initiate_round_trip() {
+        let protocols = [
+            Protocol::midi2().with_jitter_reduction(),
+            Protocol::midi1().with_large_packets(),
+        ];
+        let initiate = InitiateProtocolNegotiation::new(
+            MUID::from_wire([1, 0, 0, 0]),
+            MUID::from_wire([2, 0, 0, 0]),
+            AuthorityLevel::Endpoint,
+        )
+        .with_protocols(&protocols);
+        assert_eq!(initiate.preferred(), Some(protocols[0]));
+
+        let sysex = initiate.to_sysex().unwrap();
+        assert_eq!(
+            &sysex[13..],
+            [0x30, 2, 0x02, 0x00, 0x01, 0, 0, 0x01, 0x00, 0x02, 0, 0]
+        );
+        let decoded = InitiateProtocolNegotiation::decode(&sysex).unwrap();
+        assert_eq!(decoded, initiate);
+        assert_eq!(decoded.protocols.len(), 2);
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::InitiateProtocolNegotiation(initiate))
+        );
+
+        let mut sysex = sysex;
+        sysex[15] = 0x03;
+        assert_eq!(
+            InitiateProtocolNegotiation::decode(&sysex),
+            Err(Error::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn negotiates_common_protocol() {
+        let local = [Protocol::midi2().with_jitter_reduction(), Protocol::midi1()];
+        let remote = [Protocol::midi1(), Protocol::midi2()];
+        let local = ProtocolList::new(&local);
+        assert_eq!(
+            local.negotiate(&ProtocolList::new(&remote)),
+            Some(Protocol::midi2())
+        );
+        assert_eq!(
+            local.negotiate(&ProtocolList::new(&[
+                Protocol::midi1().with_jitter_reduction()
+            ])),
+            Some(Protocol::midi1())
+        );
+        assert_eq!(local.negotiate(&ProtocolList::new(&[])), None);
+    }
+
+    #[test]
+    fn test_messages() {
+        let test = TestNewProtocol {
+            source: MUID::from_wire([1, 0, 0, 0]),
+            destination: MUID::from_wire([2, 0, 0, 0]),
+            authority: 0x30,
+        };
+        let mut sysex = test.to_sysex().unwrap();
+        assert_eq!(sysex.len(), 13 + 1 + 48);
+        assert_eq!(
+            CiMessage::decode(&sysex),
+            Ok(CiMessage::TestNewProtocol(test))
+        );
+        sysex[20] = 0;
+        assert_eq!(TestNewProtocol::decode(&sysex), Err(Error::InvalidValue));
+    }
+}
@@ -0,0 +1,446 @@
+//! A sans-IO state machine for MIDI-CI 1.1 protocol negotiation.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::*;
+use crate::ci::{AuthorityLevel, CiMessage};
+
+/// How long the initiator waits after Set New Protocol before testing the new protocol.
+pub const TEST_DELAY: Duration = Duration::from_millis(100);
+
+/// How long to wait for the next message of the negotiation before giving up.
+pub const NEGOTIATION_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Something the caller of [ProtocolNegotiation] must react to.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum NegotiationEvent {
+    /// Send and receive with a protocol from now on, either the new protocol being tested or
+    /// the previous one after a failed test.
+    SwitchProtocol(Protocol),
+
+    /// Both devices confirmed the new protocol.
+    Established(Protocol),
+
+    /// The negotiation with a device failed, because it supports no common protocol or stopped
+    /// replying.
+    Failed(MUID),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    /// The initiator waits for the reply to Initiate Protocol Negotiation.
+    AwaitingReply,
+
+    /// The initiator switched protocol and waits before testing it.
+    Switched,
+
+    /// The initiator waits for the test reply.
+    AwaitingTestReply,
+
+    /// The responder waits for Set New Protocol.
+    AwaitingSetNew,
+
+    /// The responder switched protocol and waits for the test.
+    AwaitingTest,
+
+    /// The responder waits for the confirmation.
+    AwaitingConfirmation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Negotiation {
+    remote: MUID,
+    stage: Stage,
+    since: Duration,
+    previous: Protocol,
+}
+
+/// Runs MIDI-CI 1.1 protocol negotiation for a local device, as initiator or responder.
+///
+/// The initiator sends Initiate Protocol Negotiation with [ProtocolNegotiation::initiate] and
+/// selects the first of its protocols the responder supports. Both devices then switch to it,
+/// the initiator tests it after [TEST_DELAY] and confirms it once the responder replies. If a
+/// device stops replying for [NEGOTIATION_TIMEOUT], both return to the previous protocol. If
+/// both devices initiate at once, the one with the higher authority level, then the higher
+/// MUID, stays the initiator.
+///
+/// Test and confirmation messages are sent in the new protocol, so the caller must switch its
+/// transport on [NegotiationEvent::SwitchProtocol] before sending the next message.
+#[derive(Clone, Debug)]
+pub struct ProtocolNegotiation {
+    muid: MUID,
+    authority: u8,
+    supported: Vec<Protocol>,
+    protocol: Protocol,
+    negotiation: Option<Negotiation>,
+    transmit: VecDeque<Vec<u8>>,
+    events: VecDeque<NegotiationEvent>,
+}
+
+impl ProtocolNegotiation {
+    /// Create a state machine for a local device supporting some protocols, preferred first.
+    /// The connection starts in the MIDI 1 protocol.
+    pub fn new(muid: MUID, authority: AuthorityLevel, supported: Vec<Protocol>) -> Self {
+        Self {
+            muid,
+            authority: authority as u8,
+            supported,
+            protocol: Protocol::midi1(),
+            negotiation: None,
+            transmit: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Change the MUID of the local device, eg after a collision.
+    pub fn set_muid(&mut self, muid: MUID) {
+        self.muid = muid;
+    }
+
+    /// The protocol currently in use.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns true while a negotiation is in progress.
+    pub fn is_negotiating(&self) -> bool {
+        self.negotiation.is_some()
+    }
+
+    /// Start negotiating with a remote device, abandoning any negotiation in progress. Fails
+    /// if the local protocols cannot be encoded.
+    pub fn initiate(&mut self, now: Duration, remote: MUID) -> Result<(), Error> {
+        let initiate = InitiateProtocolNegotiation {
+            source: self.muid,
+            destination: remote,
+            authority: self.authority,
+            protocols: ProtocolList::new(&self.supported),
+        };
+        self.transmit.push_back(initiate.to_sysex()?);
+        self.start(now, remote, Stage::AwaitingReply);
+        Ok(())
+    }
+
+    /// Advance the clock, testing a new protocol once [TEST_DELAY] elapsed and failing the
+    /// negotiation if the remote device stopped replying.
+    pub fn tick(&mut self, now: Duration) -> Result<(), Error> {
+        let Some(negotiation) = self.negotiation else {
+            return Ok(());
+        };
+        let elapsed = now.saturating_sub(negotiation.since);
+        let timeout = match negotiation.stage {
+            Stage::Switched if elapsed >= TEST_DELAY => {
+                let test = TestNewProtocol {
+                    source: self.muid,
+                    destination: negotiation.remote,
+                    authority: self.authority,
+                };
+                self.transmit.push_back(test.to_sysex()?);
+                self.start(now, negotiation.remote, Stage::AwaitingTestReply);
+                return Ok(());
+            }
+            Stage::Switched => return Ok(()),
+            Stage::AwaitingTest => TEST_DELAY + NEGOTIATION_TIMEOUT,
+            _ => NEGOTIATION_TIMEOUT,
+        };
+        if elapsed >= timeout {
+            self.fail();
+        }
+        Ok(())
+    }
+
+    /// Handle a received SysEx message. Messages that are not part of a negotiation with the
+    /// local device are ignored.
+    pub fn receive(&mut self, now: Duration, sysex: &[u8]) -> Result<(), Error> {
+        let message = CiMessage::decode(sysex)?;
+        let header = message.header();
+        if header.destination != self.muid {
+            return Ok(());
+        }
+        if let CiMessage::InitiateProtocolNegotiation(initiate) = message {
+            match self.negotiation {
+                // Both devices initiated: the higher authority level stays the initiator, or
+                // the higher MUID if they are equal, and the other answers as the responder.
+                Some(negotiation)
+                    if negotiation.remote == initiate.source
+                        && negotiation.stage == Stage::AwaitingReply =>
+                {
+                    let local = (self.authority, self.muid.value());
+                    if local > (initiate.authority, initiate.source.value()) {
+                        return Ok(());
+                    }
+                    self.negotiation = None;
+                }
+                Some(_) => self.fail(),
+                None => (),
+            }
+            let reply = ProtocolNegotiationReply {
+                source: self.muid,
+                destination: initiate.source,
+                authority: self.authority,
+                protocols: ProtocolList::new(&self.supported),
+            };
+            self.transmit.push_back(reply.to_sysex()?);
+            self.start(now, initiate.source, Stage::AwaitingSetNew);
+            return Ok(());
+        }
+        let Some(negotiation) = self.negotiation else {
+            return Ok(());
+        };
+        if header.source != negotiation.remote {
+            return Ok(());
+        }
+        match (negotiation.stage, message) {
+            (Stage::AwaitingReply, CiMessage::ProtocolNegotiationReply(reply)) => {
+                let supported = ProtocolList::new(&self.supported);
+                let Some(protocol) = supported.negotiate(&reply.protocols) else {
+                    self.fail();
+                    return Ok(());
+                };
+                let set = SetNewProtocol {
+                    source: self.muid,
+                    destination: negotiation.remote,
+                    authority: self.authority,
+                    protocol,
+                };
+                self.transmit.push_back(set.to_sysex()?);
+                self.switch(protocol);
+                self.start(now, negotiation.remote, Stage::Switched);
+            }
+            (Stage::AwaitingTestReply, CiMessage::TestNewProtocolReply(_)) => {
+                let established = NewProtocolEstablished {
+                    source: self.muid,
+                    destination: negotiation.remote,
+                    authority: self.authority,
+                };
+                self.transmit.push_back(established.to_sysex()?);
+                self.establish();
+            }
+            (Stage::AwaitingSetNew, CiMessage::SetNewProtocol(set)) => {
+                if !self
+                    .supported
+                    .iter()
+                    .any(|p| p.is_compatible(&set.protocol))
+                {
+                    self.fail();
+                    return Ok(());
+                }
+                self.switch(set.protocol);
+                self.start(now, negotiation.remote, Stage::AwaitingTest);
+            }
+            (Stage::AwaitingTest, CiMessage::TestNewProtocol(_)) => {
+                let reply = TestNewProtocolReply {
+                    source: self.muid,
+                    destination: negotiation.remote,
+                    authority: self.authority,
+                };
+                self.transmit.push_back(reply.to_sysex()?);
+                self.start(now, negotiation.remote, Stage::AwaitingConfirmation);
+            }
+            (Stage::AwaitingConfirmation, CiMessage::NewProtocolEstablished(_)) => {
+                self.establish();
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// The next SysEx message to send, without `F0`/`F7` delimiters.
+    pub fn poll_transmit(&mut self) -> Option<Vec<u8>> {
+        self.transmit.pop_front()
+    }
+
+    /// The next event to handle.
+    pub fn poll_event(&mut self) -> Option<NegotiationEvent> {
+        self.events.pop_front()
+    }
+
+    fn start(&mut self, now: Duration, remote: MUID, stage: Stage) {
+        let previous = match self.negotiation {
+            Some(negotiation) if negotiation.remote == remote => negotiation.previous,
+            _ => self.protocol,
+        };
+        self.negotiation = Some(Negotiation {
+            remote,
+            stage,
+            since: now,
+            previous,
+        });
+    }
+
+    fn switch(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+        self.events
+            .push_back(NegotiationEvent::SwitchProtocol(protocol));
+    }
+
+    fn establish(&mut self) {
+        self.negotiation = None;
+        self.events
+            .push_back(NegotiationEvent::Established(self.protocol));
+    }
+
+    fn fail(&mut self) {
+        let Some(negotiation) = self.negotiation.take() else {
+            return;
+        };
+        if self.protocol != negotiation.previous {
+            self.switch(negotiation.previous);
+        }
+        self.events
+            .push_back(NegotiationEvent::Failed(negotiation.remote));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::muid::muid;
+
+    /// Deliver every pending message between two devices.
+    fn exchange(now: Duration, a: &mut ProtocolNegotiation, b: &mut ProtocolNegotiation) {
+        loop {
+            let mut delivered = false;
+            while let Some(sysex) = a.poll_transmit() {
+                b.receive(now, &sysex).unwrap();
+                delivered = true;
+            }
+            while let Some(sysex) = b.poll_transmit() {
+                a.receive(now, &sysex).unwrap();
+                delivered = true;
+            }
+            if !delivered {
+                break;
+            }
+        }
+    }
+
+    fn events(device: &mut ProtocolNegotiation) -> Vec<NegotiationEvent> {
+        std::iter::from_fn(|| device.poll_event()).collect()
+    }
+
+    #[test]
+    fn negotiates_midi2() {
+        let midi2 = Protocol::midi2().with_jitter_reduction();
+        let mut initiator = ProtocolNegotiation::new(
+            muid(1),
+            AuthorityLevel::NodeServer,
+            vec![midi2, Protocol::midi1()],
+        );
+        let mut responder = ProtocolNegotiation::new(
+            muid(2),
+            AuthorityLevel::Endpoint,
+            vec![Protocol::midi1(), Protocol::midi2()],
+        );
+
+        let start = Duration::from_secs(1);
+        initiator.initiate(start, muid(2)).unwrap();
+        exchange(start, &mut initiator, &mut responder);
+        let expected = Protocol::midi2();
+        assert_eq!(
+            events(&mut initiator),
+            [NegotiationEvent::SwitchProtocol(expected)]
+        );
+        assert_eq!(
+            events(&mut responder),
+            [NegotiationEvent::SwitchProtocol(expected)]
+        );
+
+        // The test is only sent after the delay.
+        initiator.tick(start + TEST_DELAY / 2).unwrap();
+        assert_eq!(initiator.poll_transmit(), None);
+        let now = start + TEST_DELAY;
+        initiator.tick(now).unwrap();
+        exchange(now, &mut initiator, &mut responder);
+        assert_eq!(
+            events(&mut initiator),
+            [NegotiationEvent::Established(expected)]
+        );
+        assert_eq!(
+            events(&mut responder),
+            [NegotiationEvent::Established(expected)]
+        );
+        assert!(!initiator.is_negotiating() && !responder.is_negotiating());
+        assert_eq!(responder.protocol(), expected);
+    }
+
+    #[test]
+    fn resolves_simultaneous_initiation() {
+        for (authority, initiator) in [
+            (AuthorityLevel::NodeServer, 1),
+            (AuthorityLevel::Endpoint, 2),
+        ] {
+            let mut a = ProtocolNegotiation::new(muid(1), authority, vec![Protocol::midi2()]);
+            let mut b = ProtocolNegotiation::new(
+                muid(2),
+                AuthorityLevel::Endpoint,
+                vec![Protocol::midi2()],
+            );
+            a.initiate(Duration::ZERO, muid(2)).unwrap();
+            b.initiate(Duration::ZERO, muid(1)).unwrap();
+            exchange(Duration::ZERO, &mut a, &mut b);
+            let (initiator, responder) = if initiator == 1 {
+                (&mut a, &mut b)
+            } else {
+                (&mut b, &mut a)
+            };
+            initiator.tick(TEST_DELAY).unwrap();
+            exchange(TEST_DELAY, initiator, responder);
+
+            let expected = [
+                NegotiationEvent::SwitchProtocol(Protocol::midi2()),
+                NegotiationEvent::Established(Protocol::midi2()),
+            ];
+            assert_eq!(events(initiator), expected);
+            assert_eq!(events(responder), expected);
+            assert!(!initiator.is_negotiating() && !responder.is_negotiating());
+        }
+    }
+
+    #[test]
+    fn reverts_when_test_times_out() {
+        let mut initiator =
+            ProtocolNegotiation::new(muid(1), AuthorityLevel::NodeServer, vec![Protocol::midi2()]);
+        let mut responder =
+            ProtocolNegotiation::new(muid(2), AuthorityLevel::Endpoint, vec![Protocol::midi2()]);
+        let start = Duration::ZERO;
+        initiator.initiate(start, muid(2)).unwrap();
+        exchange(start, &mut initiator, &mut responder);
+        events(&mut initiator);
+        events(&mut responder);
+
+        // The test is lost.
+        initiator.tick(start + TEST_DELAY).unwrap();
+        initiator.poll_transmit().unwrap();
+        responder
+            .tick(start + TEST_DELAY + NEGOTIATION_TIMEOUT)
+            .unwrap();
+        assert_eq!(
+            events(&mut responder),
+            [
+                NegotiationEvent::SwitchProtocol(Protocol::midi1()),
+                NegotiationEvent::Failed(muid(1))
+            ]
+        );
+        initiator
+            .tick(start + TEST_DELAY + NEGOTIATION_TIMEOUT)
+            .unwrap();
+        assert_eq!(initiator.protocol(), Protocol::midi1());
+        assert_eq!(
+            events(&mut initiator).last(),
+            Some(&NegotiationEvent::Failed(muid(2)))
+        );
+    }
+
+    #[test]
+    fn fails_without_common_protocol() {
+        let mut initiator =
+            ProtocolNegotiation::new(muid(1), AuthorityLevel::NodeServer, vec![Protocol::midi2()]);
+        let mut responder =
+            ProtocolNegotiation::new(muid(2), AuthorityLevel::Endpoint, vec![Protocol::midi1()]);
+        initiator.initiate(Duration::ZERO, muid(2)).unwrap();
+        exchange(Duration::ZERO, &mut initiator, &mut responder);
+        assert_eq!(events(&mut initiator), [NegotiationEvent::Failed(muid(2))]);
+        assert!(responder.is_negotiating());
+    }
+}
@@ -0,0 +1,219 @@
+//! The Universal System Exclusive framing shared by all MIDI-CI messages.
+//!
+//! A MIDI-CI message is a Universal Non-Real Time SysEx message with the sub-ID `0x0D`:
+//!
+//! ```text
+//! F0 7E <device id> 0D <sub-id #2> <version> <source MUID x4> <destination MUID x4> <data...> F7
+//! ```
+//!
+//! Messages are encoded without the `F0` and `F7` delimiters, as in SysEx7 packets. Decoding
+//! accepts messages with or without them.
+use super::Error;
+use crate::muid::MUID;
+
+/// Universal Non-Real Time SysEx ID.
+pub const UNIVERSAL_NON_REAL_TIME: u8 = 0x7e;
+
+/// Universal SysEx sub-ID #1 of MIDI-CI messages.
+pub const SUB_ID_CI: u8 = 0x0d;
+
+/// The MIDI-CI message version this crate encodes (MIDI-CI 1.2).
+pub const CI_VERSION: u8 = 0x02;
+
+/// Device ID addressing the whole function block rather than a single channel.
+pub const FUNCTION_BLOCK: u8 = 0x7f;
+
+/// Length of the common header, from the Universal SysEx ID to the destination MUID.
+pub const HEADER_LEN: usize = 13;
+
+/// The fields common to every MIDI-CI message.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Header {
+    /// The channel (0-15), group (0x7e) or function block (0x7f) the message addresses.
+    pub device_id: u8,
+
+    /// Sub-ID #2, the type of message.
+    pub sub_id: u8,
+
+    /// The MIDI-CI message version.
+    pub version: u8,
+
+    /// The sender of the message.
+    pub source: MUID,
+
+    /// The receiver of the message.
+    pub destination: MUID,
+}
+
+impl Header {
+    /// Read the header of a MIDI-CI message, returning it and the message data that follows.
+    pub fn parse(sysex: &[u8]) -> Result<(Self, Reader<'_>), Error> {
+        let sysex = sysex.strip_prefix(&[0xf0]).unwrap_or(sysex);
+        let sysex = sysex.strip_suffix(&[0xf7]).unwrap_or(sysex);
+        let mut reader = Reader::new(sysex);
+        let universal = reader.u8()?;
+        let device_id = reader.u8()?;
+        if universal != UNIVERSAL_NON_REAL_TIME || reader.u8()? != SUB_ID_CI {
+            return Err(Error::NotCapabilityInquiry);
+        }
+        let header = Self {
+            device_id,
+            sub_id: reader.u8()?,
+            version: reader.u8()?,
+            source: reader.muid()?,
+            destination: reader.muid()?,
+        };
+        Ok((header, reader))
+    }
+
+    /// Write the header of a MIDI-CI message.
+    pub fn write(&self, writer: &mut Writer<'_>) -> Result<(), Error> {
+        writer.bytes(&[
+            UNIVERSAL_NON_REAL_TIME,
+            self.device_id,
+            SUB_ID_CI,
+            self.sub_id,
+            self.version,
+        ])?;
+        writer.muid(self.source)?;
+        writer.muid(self.destination)
+    }
+}
+
+/// Reads the 7-bit fields of a SysEx message.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Read from a slice of bytes.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// The bytes that have not been read.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns true if all the bytes have been read.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Read `len` bytes.
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            return Err(Error::Truncated);
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        if let Some(byte) = bytes.iter().find(|b| **b > 0x7f) {
+            return Err(Error::InvalidByte(*byte));
+        }
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    /// Read a fixed number of bytes.
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    /// Read a single byte.
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Read a 14-bit value sent as two 7-bit bytes, least significant first.
+    pub fn u14(&mut self) -> Result<u16, Error> {
+        let [lsb, msb] = self.array()?;
+        Ok(lsb as u16 | (msb as u16) << 7)
+    }
+
+    /// Read a 28-bit value sent as four 7-bit bytes, least significant first.
+    pub fn u28(&mut self) -> Result<u32, Error> {
+        let bytes: [u8; 4] = self.array()?;
+        Ok(bytes.iter().rev().fold(0, |n, b| (n << 7) | *b as u32))
+    }
+
+    /// Read a MUID.
+    pub fn muid(&mut self) -> Result<MUID, Error> {
+        Ok(MUID::from_wire(self.array()?))
+    }
+
+    /// Read a byte that was added in a later MIDI-CI version, or zero if the message ends.
+    pub fn u8_or_default(&mut self) -> Result<u8, Error> {
+        if self.is_empty() {
+            Ok(0)
+        } else {
+            self.u8()
+        }
+    }
+}
+
+/// Writes the 7-bit fields of a SysEx message into a buffer.
+#[derive(Debug)]
+pub struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    /// Write into a buffer.
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// The number of bytes written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Write bytes, which must all be 7-bit.
+    pub fn bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if let Some(byte) = bytes.iter().find(|b| **b > 0x7f) {
+            return Err(Error::InvalidByte(*byte));
+        }
+        let end = self.len + bytes.len();
+        self.buffer
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Write a single 7-bit byte.
+    pub fn u8(&mut self, byte: u8) -> Result<(), Error> {
+        self.bytes(&[byte])
+    }
+
+    /// Write a 14-bit value as two 7-bit bytes, least significant first.
+    pub fn u14(&mut self, value: u16) -> Result<(), Error> {
+        debug_assert!(value < 0x4000, "Wrong integer size: value is u14");
+        self.bytes(&[(value & 0x7f) as u8, ((value >> 7) & 0x7f) as u8])
+    }
+
+    /// Write a 28-bit value as four 7-bit bytes, least significant first.
+    pub fn u28(&mut self, value: u32) -> Result<(), Error> {
+        debug_assert!(value < 0x1000_0000, "Wrong integer size: value is u28");
+        let mut bytes = [0; 4];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = ((value >> (7 * i)) & 0x7f) as u8;
+        }
+        self.bytes(&bytes)
+    }
+
+    /// Write a MUID.
+    pub fn muid(&mut self, muid: MUID) -> Result<(), Error> {
+        self.bytes(&muid.to_wire())
+    }
+}
@@ -0,0 +1,684 @@
+//! Tracking MIDI-CI requests until they are answered, rejected or time out.
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use super::profile::ProfileId;
+use super::property::PropertyMessageKind;
+use super::sysex::Header;
+use super::*;
+use crate::muid::{BROADCAST, MUID};
+
+/// How long to wait for the reply to a request.
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait before retrying a request rejected with [StatusCode::Retry] when the
+/// device did not say.
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Identifies a request sent through a [TransactionManager].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TransactionId(u32);
+
+/// The outcome of a request.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum TransactionEvent {
+    /// The request was answered. Requests broadcast to every device, like Discovery, complete
+    /// when the timeout elapses.
+    Completed(TransactionId),
+
+    /// The request was rejected with a NAK.
+    Rejected(TransactionId, StatusCode),
+
+    /// No reply arrived in time, including after every retry.
+    TimedOut(TransactionId),
+}
+
+/// The sub-ID #2 of each request and of the message answering it.
+const REPLIES: [(u8, u8); 14] = [
+    (DeviceDiscovery::SUB_ID, DiscoveryReply::SUB_ID),
+    (EndpointInfoInquiry::SUB_ID, EndpointInfoReply::SUB_ID),
+    (
+        InitiateProtocolNegotiation::SUB_ID,
+        ProtocolNegotiationReply::SUB_ID,
+    ),
+    (TestNewProtocol::SUB_ID, TestNewProtocolReply::SUB_ID),
+    (ProfileInquiry::SUB_ID, ProfileInquiryReply::SUB_ID),
+    (SetProfileOn::SUB_ID, ProfileEnabled::SUB_ID),
+    (SetProfileOff::SUB_ID, ProfileDisabled::SUB_ID),
+    (ProfileDetailsInquiry::SUB_ID, ProfileDetailsReply::SUB_ID),
+    (
+        PropertyCapabilitiesInquiry::SUB_ID,
+        PropertyCapabilitiesReply::SUB_ID,
+    ),
+    (
+        PropertyMessageKind::Get as u8,
+        PropertyMessageKind::GetReply as u8,
+    ),
+    (
+        PropertyMessageKind::Set as u8,
+        PropertyMessageKind::SetReply as u8,
+    ),
+    (
+        PropertyMessageKind::Subscription as u8,
+        PropertyMessageKind::SubscriptionReply as u8,
+    ),
+    (
+        ProcessCapabilitiesInquiry::SUB_ID,
+        ProcessCapabilitiesReply::SUB_ID,
+    ),
+    (MessageReportInquiry::SUB_ID, MessageReportReply::SUB_ID),
+];
+
+/// The sub-ID #2 of the message answering a request, if it has a reply of its own.
+fn reply_sub_id(request: u8) -> Option<u8> {
+    REPLIES
+        .iter()
+        .find(|(sub_id, _)| *sub_id == request)
+        .map(|(_, reply)| *reply)
+}
+
+#[derive(Clone, Debug)]
+struct Pending {
+    id: TransactionId,
+    destination: MUID,
+    device_id: u8,
+    sub_id: u8,
+    request_id: Option<u8>,
+    profile: Option<ProfileId>,
+    messages: Vec<Vec<u8>>,
+    deadline: Duration,
+    retries: u8,
+}
+
+impl Pending {
+    fn is_property_request(&self) -> bool {
+        self.request_id.is_some()
+    }
+
+    fn answered_by(&self, message: &CiMessage<'_>) -> bool {
+        let header = message.header();
+        if self.destination != BROADCAST && header.source != self.destination {
+            return false;
+        }
+        if reply_sub_id(self.sub_id) != Some(header.sub_id) {
+            return false;
+        }
+        match message {
+            CiMessage::PropertyData(data) => Some(data.request_id) == self.request_id,
+            _ if self.profile.is_some() => {
+                header.device_id == self.device_id && profile(message) == self.profile
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether an ACK or NAK from a device is about this request. Property Exchange statuses
+    /// carry the request ID in their first detail byte, and profile statuses are sent on the
+    /// channel of the request, with the profile ID as details if any.
+    fn acknowledged_by(&self, header: &Header, status: &AckStatus<'_>) -> bool {
+        if header.source != self.destination || status.original_sub_id != self.sub_id {
+            return false;
+        }
+        match (self.request_id, self.profile) {
+            (Some(request_id), _) => status.details[0] == request_id,
+            (None, Some(profile)) => {
+                header.device_id == self.device_id
+                    && (status.details == [0; 5] || status.details == profile.0)
+            }
+            (None, None) => true,
+        }
+    }
+}
+
+/// The profile a message is about, if any.
+fn profile(message: &CiMessage<'_>) -> Option<ProfileId> {
+    match message {
+        CiMessage::SetProfileOn(m) => Some(m.profile),
+        CiMessage::SetProfileOff(m) => Some(m.profile),
+        CiMessage::ProfileEnabled(m) => Some(m.profile),
+        CiMessage::ProfileDisabled(m) => Some(m.profile),
+        CiMessage::ProfileDetailsInquiry(m) => Some(m.profile),
+        CiMessage::ProfileDetailsReply(m) => Some(m.profile),
+        _ => None,
+    }
+}
+
+/// Tracks the requests sent by a local device and correlates the replies it receives.
+///
+/// Requests are answered by their reply from the destination, eg a Profile Inquiry by a
+/// Profile Inquiry Reply and a Set Profile On by a Profile Enabled Report, or by an ACK or
+/// NAK. Replies about a profile must be for the profile and channel of the request. Property
+/// Exchange replies, and their ACKs and NAKs, must also carry the request ID, allocated by
+/// [TransactionManager::next_request_id], and replies complete with their last chunk.
+///
+/// The manager is sans-IO like [CiDiscovery](super::discovery::CiDiscovery): the caller
+/// supplies a monotonic time, and polls it for messages to send and outcomes. Requests that
+/// time out are sent again up to the retry limit, as are requests rejected with
+/// [StatusCode::Retry] or [StatusCode::TimeoutRetry]. An ACK with [StatusCode::Busy] extends
+/// the timeout. Property Exchange requests beyond the number of simultaneous requests the
+/// destination supports are queued until an earlier request completes.
+#[derive(Clone, Debug)]
+pub struct TransactionManager {
+    muid: MUID,
+    timeout: Duration,
+    max_retries: u8,
+    max_requests: HashMap<MUID, u8>,
+    next_id: u32,
+    next_request_id: u8,
+    pending: Vec<Pending>,
+    queued: VecDeque<Pending>,
+    transmit: VecDeque<Vec<u8>>,
+    events: VecDeque<TransactionEvent>,
+}
+
+impl TransactionManager {
+    /// Create a manager for the device with a MUID, waiting [TRANSACTION_TIMEOUT] for replies
+    /// without retrying.
+    pub fn new(muid: MUID) -> Self {
+        Self {
+            muid,
+            timeout: TRANSACTION_TIMEOUT,
+            max_retries: 0,
+            max_requests: HashMap::new(),
+            next_id: 0,
+            next_request_id: 0,
+            pending: Vec::new(),
+            queued: VecDeque::new(),
+            transmit: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Wait a different time for replies.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send requests that time out again, up to a number of times.
+    pub fn with_max_retries(mut self, retries: u8) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Change the MUID of the local device, eg after a collision.
+    pub fn set_muid(&mut self, muid: MUID) {
+        self.muid = muid;
+    }
+
+    /// Limit the number of simultaneous Property Exchange requests to a device. Learnt from
+    /// received Property Exchange Capabilities replies, 1 until then.
+    pub fn set_max_requests(&mut self, device: MUID, max_requests: u8) {
+        self.max_requests.insert(device, max_requests.max(1));
+    }
+
+    /// The number of requests waiting for a reply, excluding queued requests.
+    pub fn outstanding(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if a request has not completed yet.
+    pub fn is_pending(&self, id: TransactionId) -> bool {
+        self.pending.iter().chain(&self.queued).any(|p| p.id == id)
+    }
+
+    /// Allocate a Property Exchange request ID that no outstanding request uses.
+    pub fn next_request_id(&mut self) -> Option<u8> {
+        for _ in 0..0x80 {
+            let id = self.next_request_id;
+            self.next_request_id = (self.next_request_id + 1) % 0x80;
+            let used = self
+                .pending
+                .iter()
+                .chain(&self.queued)
+                .any(|p| p.request_id == Some(id));
+            if !used {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Send a request, split into several SysEx messages for chunked Property Exchange
+    /// requests. Fails if the first message is not a MIDI-CI message.
+    pub fn request(
+        &mut self,
+        now: Duration,
+        messages: Vec<Vec<u8>>,
+    ) -> Result<TransactionId, Error> {
+        let first = messages.first().ok_or(Error::Truncated)?;
+        let message = CiMessage::decode(first)?;
+        let header = message.header();
+        let request_id = match message {
+            CiMessage::PropertyData(data) => Some(data.request_id),
+            _ => None,
+        };
+        let profile = profile(&message);
+        let destination = header.destination;
+        let id = TransactionId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        let pending = Pending {
+            id,
+            destination,
+            device_id: header.device_id,
+            sub_id: header.sub_id,
+            request_id,
+            profile,
+            messages,
+            deadline: now + self.timeout,
+            retries: 0,
+        };
+        if pending.is_property_request() && !self.has_capacity(destination) {
+            self.queued.push_back(pending);
+        } else {
+            self.send(pending);
+        }
+        Ok(id)
+    }
+
+    /// Stop waiting for a request. Returns true if it was pending.
+    pub fn cancel(&mut self, now: Duration, id: TransactionId) -> bool {
+        let len = self.pending.len() + self.queued.len();
+        self.pending.retain(|p| p.id != id);
+        self.queued.retain(|p| p.id != id);
+        let cancelled = self.pending.len() + self.queued.len() < len;
+        if cancelled {
+            self.send_queued(now);
+        }
+        cancelled
+    }
+
+    /// Advance the clock, retrying or timing out requests whose reply is late.
+    pub fn tick(&mut self, now: Duration) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            if self.pending[index].deadline > now {
+                index += 1;
+                continue;
+            }
+            let mut pending = self.pending.remove(index);
+            if pending.destination == BROADCAST {
+                self.events
+                    .push_back(TransactionEvent::Completed(pending.id));
+            } else if pending.retries < self.max_retries {
+                pending.retries += 1;
+                pending.deadline = now + self.timeout;
+                self.send(pending);
+                // The request was moved to the end.
+                continue;
+            } else {
+                self.events
+                    .push_back(TransactionEvent::TimedOut(pending.id));
+            }
+        }
+        self.send_queued(now);
+    }
+
+    /// Handle a received SysEx message, returning the request it answers, if any.
+    pub fn receive(&mut self, now: Duration, sysex: &[u8]) -> Result<Option<TransactionId>, Error> {
+        let message = CiMessage::decode(sysex)?;
+        let header = message.header();
+        if header.destination != self.muid && header.destination != BROADCAST {
+            return Ok(None);
+        }
+        let (ack, status) = match &message {
+            CiMessage::Acknowledged(ack) => (true, Some(ack.status)),
+            CiMessage::NotAcknowledged(nak) => (false, Some(nak.status)),
+            CiMessage::PropertyCapabilitiesReply(reply) => {
+                self.set_max_requests(reply.source, reply.max_requests);
+                (false, None)
+            }
+            _ => (false, None),
+        };
+        let index = match status {
+            Some(status) => self
+                .pending
+                .iter()
+                .position(|p| p.acknowledged_by(&header, &status)),
+            None => self.pending.iter().position(|p| p.answered_by(&message)),
+        };
+        let Some(index) = index else {
+            return Ok(None);
+        };
+        let id = self.pending[index].id;
+        match (ack, status) {
+            (true, Some(status)) if status.code == StatusCode::Busy => {
+                let wait = Duration::from_millis(100) * status.data as u32;
+                self.pending[index].deadline = now + wait.max(self.timeout);
+            }
+            (false, Some(status))
+                if matches!(status.code, StatusCode::Retry | StatusCode::TimeoutRetry)
+                    && self.pending[index].retries < self.max_retries =>
+            {
+                let delay = match status.data {
+                    0 => RETRY_DELAY,
+                    seconds => Duration::from_secs(seconds as u64),
+                };
+                // Retry once the delay elapsed, as if the request had timed out.
+                let pending = &mut self.pending[index];
+                pending.deadline = now + delay;
+            }
+            (false, Some(status)) => {
+                self.pending.remove(index);
+                self.events
+                    .push_back(TransactionEvent::Rejected(id, status.code));
+            }
+            _ => {
+                let broadcast = self.pending[index].destination == BROADCAST;
+                let last_chunk = match message {
+                    CiMessage::PropertyData(data) => data.chunk >= data.chunks,
+                    _ => true,
+                };
+                if broadcast {
+                    // Replies to broadcasts are collected until the timeout.
+                } else if last_chunk {
+                    self.pending.remove(index);
+                    self.events.push_back(TransactionEvent::Completed(id));
+                } else {
+                    self.pending[index].deadline = now + self.timeout;
+                }
+            }
+        }
+        self.send_queued(now);
+        Ok(Some(id))
+    }
+
+    /// The next SysEx message to send, without `F0`/`F7` delimiters.
+    pub fn poll_transmit(&mut self) -> Option<Vec<u8>> {
+        self.transmit.pop_front()
+    }
+
+    /// The next outcome to handle.
+    pub fn poll_event(&mut self) -> Option<TransactionEvent> {
+        self.events.pop_front()
+    }
+
+    fn has_capacity(&self, destination: MUID) -> bool {
+        let max = self.max_requests.get(&destination).copied().unwrap_or(1);
+        let active = self
+            .pending
+            .iter()
+            .filter(|p| p.is_property_request() && p.destination == destination)
+            .count();
+        active < max as usize
+    }
+
+    fn send(&mut self, pending: Pending) {
+        self.transmit.extend(pending.messages.iter().cloned());
+        self.pending.push(pending);
+    }
+
+    fn send_queued(&mut self, now: Duration) {
+        let mut index = 0;
+        while index < self.queued.len() {
+            if self.has_capacity(self.queued[index].destination) {
+                let mut pending = self.queued.remove(index).unwrap();
+                pending.deadline = now + self.timeout;
+                self.send(pending);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::muid::muid;
+
+    const LOCAL: u32 = 1;
+    const REMOTE: u32 = 2;
+
+    fn get(request_id: u8) -> Vec<u8> {
+        PropertyData {
+            kind: PropertyMessageKind::Get,
+            source: muid(LOCAL),
+            destination: muid(REMOTE),
+            request_id,
+            header: br#"{"resource":"DeviceInfo"}"#,
+            chunks: 1,
+            chunk: 1,
+            body: &[],
+        }
+        .to_sysex()
+        .unwrap()
+    }
+
+    fn reply(request_id: u8, chunk: u16, chunks: u16) -> Vec<u8> {
+        PropertyData {
+            kind: PropertyMessageKind::GetReply,
+            source: muid(REMOTE),
+            destination: muid(LOCAL),
+            request_id,
+            header: if chunk == 1 {
+                br#"{"status":200}"#
+            } else {
+                &[]
+            },
+            chunks,
+            chunk,
+            body: b"{}",
+        }
+        .to_sysex()
+        .unwrap()
+    }
+
+    fn events(manager: &mut TransactionManager) -> Vec<TransactionEvent> {
+        std::iter::from_fn(|| manager.poll_event()).collect()
+    }
+
+    #[test]
+    fn correlates_replies() {
+        let mut manager = TransactionManager::new(muid(LOCAL));
+        let inquiry = ProfileInquiry {
+            device_id: 0x7f,
+            source: muid(LOCAL),
+            destination: muid(REMOTE),
+        };
+        let now = Duration::ZERO;
+        let id = manager
+            .request(now, vec![inquiry.to_sysex().unwrap()])
+            .unwrap();
+        assert_eq!(manager.poll_transmit(), Some(inquiry.to_sysex().unwrap()));
+
+        // A reply from another device is not correlated.
+        let mut reply = ProfileInquiryReply {
+            device_id: 0x7f,
+            source: muid(3),
+            destination: muid(LOCAL),
+            enabled: ProfileList::new(&[]),
+            disabled: ProfileList::new(&[]),
+        };
+        assert_eq!(manager.receive(now, &reply.to_sysex().unwrap()), Ok(None));
+        reply.source = muid(REMOTE);
+        assert_eq!(
+            manager.receive(now, &reply.to_sysex().unwrap()),
+            Ok(Some(id))
+        );
+        assert_eq!(events(&mut manager), [TransactionEvent::Completed(id)]);
+        assert_eq!(manager.outstanding(), 0);
+    }
+
+    #[test]
+    fn correlates_profile_reports() {
+        let mut manager = TransactionManager::new(muid(LOCAL));
+        let now = Duration::ZERO;
+        let profile = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        let on = SetProfileOn {
+            device_id: 0,
+            source: muid(LOCAL),
+            destination: muid(REMOTE),
+            profile,
+            channels: 0,
+        };
+        let off = SetProfileOff {
+            device_id: 0,
+            source: muid(LOCAL),
+            destination: muid(REMOTE),
+            profile,
+        };
+        let on_id = manager.request(now, vec![on.to_sysex().unwrap()]).unwrap();
+        let off_id = manager.request(now, vec![off.to_sysex().unwrap()]).unwrap();
+
+        let enabled = ProfileEnabled {
+            device_id: 0,
+            source: muid(REMOTE),
+            profile,
+            channels: 0,
+        };
+        let disabled = ProfileDisabled {
+            device_id: 0,
+            source: muid(REMOTE),
+            profile,
+            channels: 0,
+        };
+        // Set Profile Off does not answer Set Profile On.
+        assert_eq!(manager.receive(now, &off.to_sysex().unwrap()), Ok(None));
+        assert_eq!(
+            manager.receive(now, &disabled.to_sysex().unwrap()),
+            Ok(Some(off_id))
+        );
+        assert_eq!(
+            manager.receive(now, &enabled.to_sysex().unwrap()),
+            Ok(Some(on_id))
+        );
+        assert_eq!(
+            events(&mut manager),
+            [
+                TransactionEvent::Completed(off_id),
+                TransactionEvent::Completed(on_id)
+            ]
+        );
+    }
+
+    #[test]
+    fn correlates_by_profile_and_request_id() {
+        let mut manager = TransactionManager::new(muid(LOCAL));
+        let now = Duration::ZERO;
+        let set_on = |device_id, profile| SetProfileOn {
+            device_id,
+            source: muid(LOCAL),
+            destination: muid(REMOTE),
+            profile,
+            channels: 0,
+        };
+        let first = ProfileId::standard(0x21, 0x01, 0x00, 0x01);
+        let second = ProfileId::standard(0x22, 0x01, 0x00, 0x01);
+        let first_id = manager
+            .request(now, vec![set_on(0, first).to_sysex().unwrap()])
+            .unwrap();
+        let second_id = manager
+            .request(now, vec![set_on(0, second).to_sysex().unwrap()])
+            .unwrap();
+        let other_channel = manager
+            .request(now, vec![set_on(1, second).to_sysex().unwrap()])
+            .unwrap();
+        let enabled = ProfileEnabled {
+            device_id: 0,
+            source: muid(REMOTE),
+            profile: second,
+            channels: 0,
+        };
+        assert_eq!(
+            manager.receive(now, &enabled.to_sysex().unwrap()),
+            Ok(Some(second_id))
+        );
+        let header = set_on(1, second).header();
+        let nak = NotAcknowledged::profile_not_supported(muid(REMOTE), &header, second);
+        assert_eq!(
+            manager.receive(now, &nak.to_sysex().unwrap()),
+            Ok(Some(other_channel))
+        );
+        assert!(manager.is_pending(first_id));
+
+        // A NAK resolves the Property Exchange request with its request ID.
+        manager.set_max_requests(muid(REMOTE), 2);
+        let gets: Vec<_> = (0..2)
+            .map(|request_id| manager.request(now, vec![get(request_id)]).unwrap())
+            .collect();
+        let (header, _) = crate::ci::sysex::Header::parse(&get(1)).unwrap();
+        let mut nak = NotAcknowledged::malformed(muid(REMOTE), &header);
+        nak.status = nak.status.with_details([1, 0, 0, 0, 0]);
+        assert_eq!(
+            manager.receive(now, &nak.to_sysex().unwrap()),
+            Ok(Some(gets[1]))
+        );
+        assert!(manager.is_pending(gets[0]));
+    }
+
+    #[test]
+    fn limits_simultaneous_property_requests() {
+        let mut manager = TransactionManager::new(muid(LOCAL));
+        let now = Duration::ZERO;
+        let capabilities = PropertyCapabilitiesReply {
+            source: muid(REMOTE),
+            destination: muid(LOCAL),
+            max_requests: 2,
+            major_version: 0,
+            minor_version: 0,
+        };
+        manager
+            .receive(now, &capabilities.to_sysex().unwrap())
+            .unwrap();
+
+        let ids: Vec<_> = (0..3)
+            .map(|_| {
+                let request_id = manager.next_request_id().unwrap();
+                manager.request(now, vec![get(request_id)]).unwrap()
+            })
+            .collect();
+        assert_eq!(manager.outstanding(), 2);
+        assert_eq!(manager.poll_transmit(), Some(get(0)));
+        assert_eq!(manager.poll_transmit(), Some(get(1)));
+        assert_eq!(manager.poll_transmit(), None);
+
+        // A chunked reply completes with its last chunk, freeing a slot for the third request.
+        assert_eq!(manager.receive(now, &reply(1, 1, 2)), Ok(Some(ids[1])));
+        assert!(events(&mut manager).is_empty());
+        assert_eq!(manager.receive(now, &reply(1, 2, 2)), Ok(Some(ids[1])));
+        assert_eq!(events(&mut manager), [TransactionEvent::Completed(ids[1])]);
+        assert_eq!(manager.poll_transmit(), Some(get(2)));
+        assert_eq!(manager.next_request_id(), Some(3));
+    }
+
+    #[test]
+    fn retries_and_times_out() {
+        let mut manager = TransactionManager::new(muid(LOCAL)).with_max_retries(1);
+        let start = Duration::from_secs(10);
+        let id = manager.request(start, vec![get(0)]).unwrap();
+        manager.poll_transmit().unwrap();
+
+        manager.tick(start + TRANSACTION_TIMEOUT);
+        assert_eq!(manager.poll_transmit(), Some(get(0)));
+        assert!(events(&mut manager).is_empty());
+
+        manager.tick(start + TRANSACTION_TIMEOUT * 2);
+        assert_eq!(manager.poll_transmit(), None);
+        assert_eq!(events(&mut manager), [TransactionEvent::TimedOut(id)]);
+        assert!(!manager.is_pending(id));
+    }
+
+    #[test]
+    fn handles_nak() {
+        let mut manager = TransactionManager::new(muid(LOCAL)).with_max_retries(1);
+        let now = Duration::ZERO;
+        let request = get(0);
+        let id = manager.request(now, vec![request.clone()]).unwrap();
+        manager.poll_transmit().unwrap();
+        let (header, _) = crate::ci::sysex::Header::parse(&request).unwrap();
+
+        // The device asks to retry in 2 seconds.
+        let nak = NotAcknowledged::retry(muid(REMOTE), &header, 2);
+        assert_eq!(manager.receive(now, &nak.to_sysex().unwrap()), Ok(Some(id)));
+        manager.tick(Duration::from_secs(2));
+        assert_eq!(manager.poll_transmit(), Some(request));
+
+        let nak = NotAcknowledged::malformed(muid(REMOTE), &header);
+        manager.receive(now, &nak.to_sysex().unwrap()).unwrap();
+        assert_eq!(
+            events(&mut manager),
+            [TransactionEvent::Rejected(id, StatusCode::Malformed)]
+        );
+    }
+}
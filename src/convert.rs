@@ -3,20 +3,22 @@
 #[cfg(feature = "vst3")]
 pub mod vst3;
 
+/// Convert a pitch in semitones and cents to 7.9 fixed point, clamped to the range of notes.
 #[inline(always)]
 pub fn semitones_to_fixed_7_9(semis: u8, cents: f32) -> u16 {
     let pitch = (semis as f32) + cents / 100.0;
-    let int = pitch.floor().max(127.0) as u16;
-    let frac = (512.0 * pitch.fract()) as u16;
-    (int << 9) | frac
+    let int = pitch.floor().clamp(0.0, 127.0);
+    let frac = (512.0 * (pitch - int)).clamp(0.0, 511.0) as u16;
+    ((int as u16) << 9) | frac
 }
 
+/// Convert a pitch in semitones and cents to 7.25 fixed point, clamped to the range of notes.
 #[inline(always)]
 pub fn semitones_to_fixed_7_25(semis: u8, cents: f32) -> u32 {
-    let pitch = (semis as f32) + cents / 100.0;
-    let int = pitch.floor().max(127.0) as u32;
-    let frac = (33554432.0 * pitch.fract()) as u32;
-    (int << 25) | frac
+    let pitch = (semis as f64) + cents as f64 / 100.0;
+    let int = pitch.floor().clamp(0.0, 127.0);
+    let frac = (33554432.0 * (pitch - int)).clamp(0.0, 33554431.0) as u32;
+    ((int as u32) << 25) | frac
 }
 
 #[inline(always)]
@@ -7,3 +7,7 @@ pub mod message;
 pub mod muid;
 pub mod packet;
 pub mod rpn;
+pub mod tempo;
+#[cfg(not(feature = "no-std"))]
+pub mod tuning;
+pub mod universal;
@@ -163,7 +163,7 @@ impl Data {
     pub fn as_bytes(&self) -> &[u8] {
         let words: &[u32] = self.as_ref();
         let data = words.as_ptr().cast();
-        let len = words.len() * mem::size_of::<u32>();
+        let len = mem::size_of_val(words);
         unsafe { slice::from_raw_parts(data, len) }
     }
 
@@ -15,11 +15,11 @@ pub struct LegacyChannelVoice(pub(crate) Packet32);
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 #[repr(u8)]
 pub enum LegacyChannelVoiceStatus {
-    /// This message is a note on message.
-    NoteOn = 0x8,
-
     /// This message is a note off message.
-    NoteOff = 0x9,
+    NoteOff = 0x8,
+
+    /// This message is a note on message.
+    NoteOn = 0x9,
 
     /// This message is a polyphonic key pressure message.
     PolyPressure = 0xa,
@@ -97,9 +97,9 @@ impl LegacyChannelVoice {
     /// Builder function for adding a channel.
     pub fn with_channel(mut self, channel: u8) -> Self {
         debug_assert!(channel < 16, "Channels must be in the range [0, 15].");
-        // 0x30xk_dddd
-        let channel = (channel as u32) << 24;
-        self.0[0] = (self.0[0] & 0xfff0_0000) | channel;
+        // 0x2gsc_dddd
+        let channel = (channel as u32) << 16;
+        self.0[0] = (self.0[0] & 0xfff0_ffff) | channel;
         self
     }
 
@@ -112,7 +112,7 @@ impl LegacyChannelVoice {
             | (velocity as u32)]))
     }
 
-    /// Create a new note off message.
+    /// Create a new note on message.
     pub fn note_on(note: u8, velocity: u8) -> Self {
         debug_assert!(note < 128, "Note numbers must be in the range [0, 127].");
         debug_assert!(velocity < 128, "Velocity must be in the range [0, 127].");
@@ -192,8 +192,8 @@ impl Message for LegacyChannelVoice {
 
     fn status(&self) -> Self::Status {
         match self.0.status() >> 4 {
-            0x8 => Self::Status::NoteOn,
-            0x9 => Self::Status::NoteOff,
+            0x8 => Self::Status::NoteOff,
+            0x9 => Self::Status::NoteOn,
             0xa => Self::Status::PolyPressure,
             0xb => Self::Status::ControlChange,
             0xc => Self::Status::ProgramChange,
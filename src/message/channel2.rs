@@ -125,9 +125,9 @@ impl ChannelVoice {
     /// Builder function for adding a channel.
     pub fn with_channel(mut self, channel: u8) -> Self {
         debug_assert!(channel < 16, "Channels must be in the range [0, 15].");
-        // 0x30xk_dddd
-        let channel = (channel as u32) << 24;
-        self.0[0] = (self.0[0] & 0xfff0_0000) | channel;
+        // 0x4gsc_dddd
+        let channel = (channel as u32) << 16;
+        self.0[0] = (self.0[0] & 0xfff0_ffff) | channel;
         self
     }
 
@@ -143,12 +143,12 @@ impl ChannelVoice {
         let velocity = (velocity as u32) << 16;
         let note_number = (note as u32) << 8;
         Self(Packet([
-            0x4090_0000 | note_number | attr_type,
+            0x4080_0000 | note_number | attr_type,
             velocity | attr_data,
         ]))
     }
 
-    /// Create a new note off message.
+    /// Create a new note on message.
     pub fn note_on(note: u8, velocity: u16, attribute: Option<Attribute>) -> Self {
         debug_assert!(note < 128, "Note numbers must be in the range [0, 127].");
         let (attr_type, attr_data) = match attribute {
@@ -160,7 +160,7 @@ impl ChannelVoice {
         let velocity = (velocity as u32) << 16;
         let note_number = (note as u32) << 8;
         Self(Packet([
-            0x40a0_0000 | note_number | attr_type,
+            0x4090_0000 | note_number | attr_type,
             velocity | attr_data,
         ]))
     }
@@ -205,6 +205,15 @@ impl ChannelVoice {
         Self(Packet([0x20e0_0000, value]))
     }
 
+    /// Create a registered per-note controller message, eg Pitch 7.25 (index 3).
+    pub fn registered_per_note_controller(note: u8, index: u8, value: u32) -> Self {
+        debug_assert!(note < 128, "Note numbers must be in the range [0, 127].");
+        Self(Packet([
+            0x4000_0000 | (note as u32) << 8 | index as u32,
+            value,
+        ]))
+    }
+
     /// Create a per-note pitch bend message.
     pub fn per_note_pitch_bend(note_number: u8, value: u32) -> Self {
         let value = u32::from_be_bytes([0, 0, (value >> 7) as u8, ((value << 7) >> 7) as u8]);
@@ -260,3 +269,29 @@ impl Message for ChannelVoice {
         (word1[2], word1[3], word2)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::channel1::LegacyChannelVoice;
+
+    #[test]
+    fn note_on_and_off() {
+        let off = ChannelVoice::note_off(60, 0x8000, None);
+        assert_eq!(off[..], [0x4080_3c00, 0x8000_0000]);
+        assert_eq!(off.status(), ChannelVoiceStatus::NoteOff);
+        let on = ChannelVoice::note_on(60, 0x8000, None);
+        assert_eq!(on[..], [0x4090_3c00, 0x8000_0000]);
+        assert_eq!(on.status(), ChannelVoiceStatus::NoteOn);
+    }
+
+    #[test]
+    fn translates_midi1_notes() {
+        let off = ChannelVoice::from(LegacyChannelVoice::note_off(60, 0x40).with_channel(2));
+        assert_eq!(off.status(), ChannelVoiceStatus::NoteOff);
+        assert_eq!(off[..], [0x4082_3c00, 0x4000_0000]);
+        let on = ChannelVoice::from(LegacyChannelVoice::note_on(60, 0x40).with_channel(2));
+        assert_eq!(on.status(), ChannelVoiceStatus::NoteOn);
+        assert_eq!(on[..], [0x4092_3c00, 0x4000_0000]);
+    }
+}
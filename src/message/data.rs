@@ -4,7 +4,7 @@ use core::convert::TryInto;
 use core::ops::Deref;
 
 use crate::message::Message;
-use crate::packet::{MessageType, Packet128, Packet64};
+use crate::packet::{MessageType, Packet, Packet128, Packet64};
 
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct Data64(pub(crate) Packet64);
@@ -26,6 +26,62 @@ impl Data64 {
     pub(crate) fn from_packet_unchecked(ump: Packet64) -> Self {
         Self(ump)
     }
+
+    /// Split a SysEx message, without its `F0`/`F7` delimiters, into SysEx7 packets of up to
+    /// 6 bytes each.
+    pub fn sysex7(group: u8, sysex: &[u8]) -> Sysex7Packets<'_> {
+        debug_assert!(group < 16, "Wrong integer size: group is u4");
+        Sysex7Packets {
+            group,
+            remaining: sysex,
+            started: false,
+        }
+    }
+
+    /// The SysEx bytes carried by a SysEx7 packet.
+    pub fn sysex7_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        let len = ((self.0[0] >> 16) & 0xf).min(6) as usize;
+        let [_, _, a, b] = self.0[0].to_be_bytes();
+        let [c, d, e, f] = self.0[1].to_be_bytes();
+        IntoIterator::into_iter([a, b, c, d, e, f]).take(len)
+    }
+}
+
+/// An iterator over the SysEx7 packets of a SysEx message, see [Data64::sysex7].
+#[derive(Clone, Debug)]
+pub struct Sysex7Packets<'a> {
+    group: u8,
+    remaining: &'a [u8],
+    started: bool,
+}
+
+impl Iterator for Sysex7Packets<'_> {
+    type Item = Data64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started && self.remaining.is_empty() {
+            return None;
+        }
+        let len = self.remaining.len().min(6);
+        let (bytes, remaining) = self.remaining.split_at(len);
+        let status = match (self.started, remaining.is_empty()) {
+            (false, true) => DataStatus::SinglePacket,
+            (false, false) => DataStatus::Start,
+            (true, false) => DataStatus::Continue,
+            (true, true) => DataStatus::End,
+        };
+        self.started = true;
+        self.remaining = remaining;
+        let mut payload = [0; 6];
+        payload[..len].copy_from_slice(bytes);
+        let word0 = (MessageType::Data64 as u32) << 28
+            | (self.group as u32) << 24
+            | (status as u32) << 20
+            | (len as u32) << 16
+            | u16::from_be_bytes([payload[0], payload[1]]) as u32;
+        let word1 = u32::from_be_bytes([payload[2], payload[3], payload[4], payload[5]]);
+        Some(Data64::from_packet_unchecked(Packet([word0, word1])))
+    }
 }
 
 impl Deref for Data64 {
@@ -157,3 +213,24 @@ impl From<DataFormat> for u8 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sysex7_packets() {
+        let sysex = [0x7f, 0x7f, 0x04, 0x01, 0x00, 0x40, 0x11];
+        let packets: Vec<_> = Data64::sysex7(2, &sysex).collect();
+        assert_eq!(packets[0][..], [0x3216_7f7f, 0x0401_0040]);
+        assert_eq!(packets[1][..], [0x3231_1100, 0]);
+        assert_eq!(packets[0].status(), DataStatus::Start);
+        let bytes: Vec<_> = packets.iter().flat_map(|p| p.sysex7_bytes()).collect();
+        assert_eq!(bytes, sysex);
+
+        let packets: Vec<_> = Data64::sysex7(0, &sysex[..6]).collect();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].status(), DataStatus::SinglePacket);
+        assert_eq!(Data64::sysex7(0, &[]).count(), 1);
+    }
+}
@@ -3,7 +3,13 @@ use core::convert::TryInto;
 use core::ops::Deref;
 
 use crate::message::{data::DataFormat, Message};
-use crate::packet::{MessageType, Packet128};
+use crate::packet::{MessageType, Packet, Packet128};
+
+pub mod chord;
+pub mod key;
+#[cfg(not(feature = "no-std"))]
+pub mod lyrics;
+pub mod text;
 
 /// Flex data messages: real time messages with limited variability of size.
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
@@ -81,16 +87,97 @@ impl Flex {
         self.data().into()
     }
 
-    pub fn text_message(&self) -> &'_ [u8] {
-        unsafe {
-            let data = self.data().as_ptr().cast();
-            core::slice::from_raw_parts(data, 12)
+    /// The 12 text bytes carried by this packet, in transmission order. Unused bytes are zero.
+    pub fn text_message(&self) -> [u8; 12] {
+        let mut bytes = [0; 12];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(&self.0[1..4]) {
+            chunk.copy_from_slice(&word.to_be_bytes());
         }
+        bytes
     }
 
     pub(crate) fn from_packet_unchecked(ump: Packet128) -> Self {
         Self(ump)
     }
+
+    /// Create a new Flex Data message from its header fields and the three data words.
+    pub fn new(
+        group: u8,
+        address: FlexAddress,
+        format: DataFormat,
+        status: FlexStatus,
+        data: [u32; 3],
+    ) -> Self {
+        debug_assert!(group < 16, "Groups must be in the range [0, 15].");
+        let word0 = 0xD000_0000
+            | (group as u32) << 24
+            | (u8::from(format) as u32) << 22
+            | (u8::from(address) as u32) << 16
+            | u16::from(status) as u32;
+        Self(Packet([word0, data[0], data[1], data[2]]))
+    }
+
+    /// Create a Set Tempo message.
+    pub fn set_tempo(
+        group: u8,
+        address: FlexAddress,
+        format: DataFormat,
+        tempo: FlexTempo,
+    ) -> Self {
+        let status = FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTempo);
+        Self::new(group, address, format, status, [tempo.into(), 0, 0])
+    }
+
+    /// Create a Set Time Signature message.
+    pub fn set_time_signature(
+        group: u8,
+        address: FlexAddress,
+        format: DataFormat,
+        time_signature: FlexTimeSignature,
+    ) -> Self {
+        let status = FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTimeSignature);
+        Self::new(
+            group,
+            address,
+            format,
+            status,
+            [time_signature.into(), 0, 0],
+        )
+    }
+
+    /// Create a Set Metronome message.
+    pub fn set_metronome(
+        group: u8,
+        address: FlexAddress,
+        format: DataFormat,
+        metronome: FlexMetronome,
+    ) -> Self {
+        let status = FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetMetronome);
+        Self::new(group, address, format, status, metronome.into())
+    }
+
+    /// Create a Set Key Signature message.
+    pub fn set_key_signature(
+        group: u8,
+        address: FlexAddress,
+        format: DataFormat,
+        key_signature: FlexKeySignature,
+    ) -> Self {
+        let status = FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetKeySignature);
+        let word = (u8::from(key_signature) as u32) << 24;
+        Self::new(group, address, format, status, [word, 0, 0])
+    }
+
+    /// Create a Set Chord Name message.
+    pub fn set_chord_name(
+        group: u8,
+        address: FlexAddress,
+        format: DataFormat,
+        chord_name: FlexChordName,
+    ) -> Self {
+        let status = FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetChordName);
+        Self::new(group, address, format, status, chord_name.into())
+    }
 }
 
 /// Determines the address destination of each UMP.
@@ -333,12 +420,21 @@ impl From<FlexTempo> for u32 {
 }
 
 impl FlexTempo {
+    /// Number of 10 nanosecond units in a minute.
+    const UNITS_PER_MINUTE: f64 = 6_000_000_000.0;
+
     pub fn bpm(&self) -> f32 {
-        60.0 * 1_000_000.0 / self.0 as f32
+        (Self::UNITS_PER_MINUTE / self.0 as f64) as f32
     }
 
     pub fn from_bpm(bpm: f32) -> Self {
-        Self((60.0 * 1_000_000.0 / bpm) as u32)
+        // Rounded by hand, as `f64::round` needs std.
+        Self((Self::UNITS_PER_MINUTE / bpm as f64 + 0.5) as u32)
+    }
+
+    /// The duration of a quarter note in seconds.
+    pub fn seconds_per_quarter(&self) -> f64 {
+        self.0 as f64 * 1e-8
     }
 }
 
@@ -369,7 +465,7 @@ impl From<u32> for FlexTimeSignature {
 
 impl From<FlexTimeSignature> for u32 {
     fn from(value: FlexTimeSignature) -> Self {
-        u32::from_le_bytes([value.numerator, value.denominator, value.number_of_32n, 0])
+        u32::from_be_bytes([value.numerator, value.denominator, value.number_of_32n, 0])
     }
 }
 
@@ -405,13 +501,13 @@ impl From<[u32; 2]> for FlexMetronome {
 impl From<FlexMetronome> for [u32; 2] {
     fn from(value: FlexMetronome) -> Self {
         [
-            u32::from_le_bytes([
+            u32::from_be_bytes([
                 value.clocks_per_primary_click,
                 value.bar_accents[0],
                 value.bar_accents[1],
                 value.bar_accents[2],
             ]),
-            u32::from_le_bytes([
+            u32::from_be_bytes([
                 value.subdivision_clicks[0],
                 value.subdivision_clicks[1],
                 0,
@@ -461,9 +557,11 @@ impl FlexKeySignature {
     }
 }
 
-/// unimplemented
+/// Declares a chord name, for display in lead sheets or to drive accompaniment.
+/// Renders and parses as text like `C#m7b5/E`, see [chord].
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct FlexChordName {
+    /// The chord tonic, [NoteName::Unknown] if there is no chord.
     pub tonic_note: NoteName,
 
     /// Positive values declare the number of sharps applied to the tonic note.
@@ -472,15 +570,21 @@ pub struct FlexChordName {
 
     pub chord_type: ChordType,
 
+    /// Up to four degrees added, removed, raised or lowered from the chord type.
     pub chord_alterations: [Alteration; 4],
 
+    /// The bass note of a slash chord, [NoteName::Unknown] if there is none.
     pub bass_note: NoteName,
 
     /// Positive values declare the number of sharps applied to the tonic note.
     /// Negative values declare the number of flats applied to the tonic note.
     /// if `-8`, Bass is the same as the chord tonic note.
     pub bass_alteration: i8,
+
+    /// The chord type built on the bass note, for polychords.
     pub bass_chord_type: ChordType,
+
+    /// Up to two alterations of the bass chord type.
     pub bass_chord_alterations: [Alteration; 2],
 }
 
@@ -510,6 +614,28 @@ impl From<[u32; 3]> for FlexChordName {
     }
 }
 
+impl From<FlexChordName> for [u32; 3] {
+    fn from(value: FlexChordName) -> Self {
+        let alterations = value.chord_alterations.map(u8::from);
+        let bass_alterations = value.bass_chord_alterations.map(u8::from);
+        [
+            u32::from_be_bytes([
+                u4_from_i8(value.tonic_alteration) << 4 | u8::from(value.tonic_note),
+                value.chord_type.into(),
+                alterations[0],
+                alterations[1],
+            ]),
+            u32::from_be_bytes([alterations[2], alterations[3], 0, 0]),
+            u32::from_be_bytes([
+                u4_from_i8(value.bass_alteration) << 4 | u8::from(value.bass_note),
+                value.bass_chord_type.into(),
+                bass_alterations[0],
+                bass_alterations[1],
+            ]),
+        ]
+    }
+}
+
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub enum NoteName {
     Unknown,
@@ -540,6 +666,22 @@ impl From<u8> for NoteName {
     }
 }
 
+impl From<NoteName> for u8 {
+    fn from(value: NoteName) -> Self {
+        match value {
+            NoteName::Unknown => 0x0,
+            NoteName::A => 0x1,
+            NoteName::B => 0x2,
+            NoteName::C => 0x3,
+            NoteName::D => 0x4,
+            NoteName::E => 0x5,
+            NoteName::F => 0x6,
+            NoteName::G => 0x7,
+            NoteName::Reserved(value) => value,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub enum ChordType {
     None,
@@ -609,14 +751,55 @@ impl From<u8> for ChordType {
     }
 }
 
+impl From<ChordType> for u8 {
+    fn from(value: ChordType) -> Self {
+        match value {
+            ChordType::None => 0x00,
+            ChordType::Major => 0x01,
+            ChordType::Major6 => 0x02,
+            ChordType::Major7 => 0x03,
+            ChordType::Major9 => 0x04,
+            ChordType::Major11 => 0x05,
+            ChordType::Major13 => 0x06,
+            ChordType::Minor => 0x07,
+            ChordType::Minor6 => 0x08,
+            ChordType::Minor7 => 0x09,
+            ChordType::Minor9 => 0x0A,
+            ChordType::Minor11 => 0x0B,
+            ChordType::Minor13 => 0x0C,
+            ChordType::Dominant => 0x0D,
+            ChordType::Dominant9 => 0x0E,
+            ChordType::Dominant11 => 0x0F,
+            ChordType::Dominant13 => 0x10,
+            ChordType::Augmented => 0x11,
+            ChordType::Augmented7 => 0x12,
+            ChordType::Diminished => 0x13,
+            ChordType::Diminished7 => 0x14,
+            ChordType::HalfDiminished => 0x15,
+            ChordType::MajorMinor => 0x16,
+            ChordType::Pedal => 0x17,
+            ChordType::Power => 0x18,
+            ChordType::Suspended2 => 0x19,
+            ChordType::Suspended4 => 0x1A,
+            ChordType::Suspended4_7 => 0x1B,
+            ChordType::Reserved(value) => value,
+        }
+    }
+}
+
+/// A change to the degrees of a chord type, packed as a type nibble and a degree nibble.
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub enum Alteration {
+    /// No alteration, always encoded as `0x00`.
     None,
     AddDegree(u8),
     SubtractDegree(u8),
     RaiseDegree(u8),
     LowerDegree(u8),
-    Reserved { alteration_type: u8, degree: u8 },
+    Reserved {
+        alteration_type: u8,
+        degree: u8,
+    },
 }
 
 impl From<u8> for Alteration {
@@ -624,7 +807,7 @@ impl From<u8> for Alteration {
         let alteration_type = value >> 4;
         let degree = value & 0xF;
         match alteration_type {
-            0 => Self::None,
+            0 if degree == 0 => Self::None,
             1 => Self::AddDegree(degree),
             2 => Self::SubtractDegree(degree),
             3 => Self::RaiseDegree(degree),
@@ -637,6 +820,27 @@ impl From<u8> for Alteration {
     }
 }
 
+impl From<Alteration> for u8 {
+    fn from(value: Alteration) -> Self {
+        match value {
+            Alteration::None => 0x00,
+            Alteration::AddDegree(degree) => 0x10 | degree,
+            Alteration::SubtractDegree(degree) => 0x20 | degree,
+            Alteration::RaiseDegree(degree) => 0x30 | degree,
+            Alteration::LowerDegree(degree) => 0x40 | degree,
+            Alteration::Reserved {
+                alteration_type,
+                degree,
+            } => alteration_type << 4 | degree,
+        }
+    }
+}
+
+fn u4_from_i8(n: i8) -> u8 {
+    debug_assert!((-8..8).contains(&n), "Integer out of bounds: i4");
+    n as u8 & 0x0F
+}
+
 fn i8_from_u4(n: u8) -> i8 {
     if n < 8 {
         n as i8
@@ -831,11 +1035,11 @@ mod tests {
 
     #[test]
     fn tempo_bpm() {
-        assert_eq!(120.0, FlexTempo(500_000).bpm());
-        assert_eq!(FlexTempo(500_000), FlexTempo::from_bpm(120.0));
+        assert_eq!(120.0, FlexTempo(50_000_000).bpm());
+        assert_eq!(FlexTempo(50_000_000), FlexTempo::from_bpm(120.0));
+        assert_eq!(0.5, FlexTempo(50_000_000).seconds_per_quarter());
     }
 
-    #[cfg(target_endian = "little")]
     #[test]
     fn time_signature_from_u32() {
         assert_eq!(
@@ -852,7 +1056,7 @@ mod tests {
                 denominator: 0xB1,
                 number_of_32n: 0xC2,
             },
-            u32::from_le(0x00C2_B1A0_u32).into()
+            0xA0B1_C200_u32.into()
         );
     }
 
@@ -868,7 +1072,7 @@ mod tests {
             .into()
         );
         assert_eq!(
-            u32::from_le(0x00C2_B1A0),
+            0xA0B1_C200_u32,
             FlexTimeSignature {
                 numerator: 0xA0,
                 denominator: 0xB1,
@@ -886,7 +1090,7 @@ mod tests {
                 bar_accents: [0xB2, 0xC3, 0xD4],
                 subdivision_clicks: [0xE5, 0xF6],
             },
-            [u32::from_le(0xD4C3_B2A1), u32::from_le(0x0000_F6E5)].into()
+            [0xA1B2_C3D4_u32, 0xE5F6_0000].into()
         );
     }
 
@@ -898,10 +1102,7 @@ mod tests {
             subdivision_clicks: [0xE5, 0xF6],
         }
         .into();
-        assert_eq!(
-            [u32::from_le(0xD4C3_B2A1_u32), u32::from_le(0x0000_F6E5)],
-            a
-        );
+        assert_eq!([0xA1B2_C3D4_u32, 0xE5F6_0000], a);
 
         let b: [u32; 3] = FlexMetronome {
             clocks_per_primary_click: 0xA1,
@@ -910,7 +1111,7 @@ mod tests {
         }
         .into();
 
-        assert_eq!([u32::from_le(0xD4C3_B2A1), u32::from_le(0x0000_F6E5), 0], b);
+        assert_eq!([0xA1B2_C3D4_u32, 0xE5F6_0000, 0], b);
     }
 
     #[test]
@@ -964,11 +1165,84 @@ mod tests {
             bass_chord_type: 0x1A.into(),
             bass_chord_alterations: [0x2B.into(), 0x3C.into()],
         };
-        let source = [
-            u32::from_le(0xD4C3_B2A1_u32),
-            u32::from_le(0x_0000_F6E5),
-            u32::from_le(0x3C2B_1AF0),
-        ];
+        let source = [0xA1B2_C3D4_u32, 0xE5F6_0000, 0xF01A_2B3C];
         assert_eq!(chord, source.into());
     }
+
+    #[test]
+    fn chord_name_into_u32_3() {
+        let source = [0xA1B2_C3D4_u32, 0xE5F6_0000, 0xF01A_2B3C];
+        let chord: FlexChordName = source.into();
+        let words: [u32; 3] = chord.into();
+        assert_eq!(source, words);
+    }
+
+    #[test]
+    fn set_tempo_message() {
+        let message = Flex::set_tempo(
+            3,
+            FlexAddress::Group(0),
+            DataFormat::SinglePacket,
+            FlexTempo(50_000_000),
+        );
+        assert_eq!(&*message, &[0xD310_0000, 50_000_000, 0, 0]);
+        assert_eq!(message.group(), 3);
+        assert_eq!(message.address(), FlexAddress::Group(0));
+        assert_eq!(message.format(), DataFormat::SinglePacket);
+        assert_eq!(
+            message.status(),
+            FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTempo)
+        );
+        assert_eq!(message.tempo(), 50_000_000);
+    }
+
+    #[test]
+    fn setup_and_performance_messages() {
+        let address = FlexAddress::Channel(5);
+        let format = DataFormat::SinglePacket;
+
+        let time_signature = FlexTimeSignature {
+            numerator: 6,
+            denominator: 3,
+            number_of_32n: 8,
+        };
+        let message = Flex::set_time_signature(1, address, format, time_signature);
+        assert_eq!(message[0], 0xD105_0001);
+        assert_eq!(message.address(), address);
+        assert_eq!(message.time_signature(), time_signature);
+
+        let metronome = FlexMetronome {
+            clocks_per_primary_click: 24,
+            bar_accents: [3, 2, 0],
+            subdivision_clicks: [2, 0],
+        };
+        let message = Flex::set_metronome(1, address, format, metronome);
+        assert_eq!(message[0], 0xD105_0002);
+        assert_eq!(message.metronome(), metronome);
+
+        let key_signature = FlexKeySignature::from_note(5, -3);
+        let message = Flex::set_key_signature(1, address, format, key_signature);
+        assert_eq!(message[0], 0xD105_0005);
+        assert_eq!(message[1], 0xD500_0000);
+        assert_eq!(message.key_signature(), key_signature);
+
+        let chord_name = FlexChordName {
+            tonic_note: NoteName::C,
+            tonic_alteration: 1,
+            chord_type: ChordType::Minor7,
+            chord_alterations: [
+                Alteration::LowerDegree(5),
+                Alteration::None,
+                Alteration::None,
+                Alteration::None,
+            ],
+            bass_note: NoteName::E,
+            bass_alteration: 0,
+            bass_chord_type: ChordType::None,
+            bass_chord_alterations: [Alteration::None, Alteration::None],
+        };
+        let message = Flex::set_chord_name(1, address, format, chord_name);
+        assert_eq!(message[0], 0xD105_0006);
+        assert_eq!(message.chord_name(), chord_name);
+    }
 }
@@ -0,0 +1,395 @@
+//! Textual chord names, as written on lead sheets.
+//!
+//! A chord name is a tonic with its accidentals, a chord type suffix, up to four alterations and
+//! an optional slash bass, eg `C#m7b5/E`, `Bbmaj7#11` or `G7sus4/F`. `N.C.` declares no chord.
+use core::fmt;
+use core::str::FromStr;
+
+use super::{Alteration, ChordType, FlexChordName, NoteName};
+
+/// Chord type suffixes. The first suffix of each type is the one used when rendering, the
+/// rest are accepted aliases.
+const SUFFIXES: &[(&str, ChordType)] = &[
+    ("", ChordType::Major),
+    ("6", ChordType::Major6),
+    ("maj7", ChordType::Major7),
+    ("maj9", ChordType::Major9),
+    ("maj11", ChordType::Major11),
+    ("maj13", ChordType::Major13),
+    ("m", ChordType::Minor),
+    ("m6", ChordType::Minor6),
+    ("m7", ChordType::Minor7),
+    ("m9", ChordType::Minor9),
+    ("m11", ChordType::Minor11),
+    ("m13", ChordType::Minor13),
+    ("7", ChordType::Dominant),
+    ("9", ChordType::Dominant9),
+    ("11", ChordType::Dominant11),
+    ("13", ChordType::Dominant13),
+    ("+", ChordType::Augmented),
+    ("+7", ChordType::Augmented7),
+    ("dim", ChordType::Diminished),
+    ("dim7", ChordType::Diminished7),
+    ("ø", ChordType::HalfDiminished),
+    ("m(maj7)", ChordType::MajorMinor),
+    ("ped", ChordType::Pedal),
+    ("5", ChordType::Power),
+    ("sus2", ChordType::Suspended2),
+    ("sus4", ChordType::Suspended4),
+    ("7sus4", ChordType::Suspended4_7),
+    ("M7", ChordType::Major7),
+    ("min", ChordType::Minor),
+    ("min7", ChordType::Minor7),
+    ("aug", ChordType::Augmented),
+    ("aug7", ChordType::Augmented7),
+    ("°", ChordType::Diminished),
+    ("°7", ChordType::Diminished7),
+    ("ø7", ChordType::HalfDiminished),
+    ("mMaj7", ChordType::MajorMinor),
+];
+
+/// Errors that can occur when parsing a chord name.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
+pub enum ParseChordNameError {
+    /// The tonic or bass is not a note letter from A to G.
+    InvalidNote,
+
+    /// More than seven sharps or flats were applied to a note.
+    TooManyAccidentals,
+
+    /// The text after the chord type is not an alteration.
+    InvalidAlteration,
+
+    /// More alterations than the message can hold.
+    TooManyAlterations,
+}
+
+impl fmt::Display for ParseChordNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::InvalidNote => "invalid note name",
+            Self::TooManyAccidentals => "too many accidentals",
+            Self::InvalidAlteration => "invalid chord alteration",
+            Self::TooManyAlterations => "too many chord alterations",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl fmt::Display for NoteName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letter = match self {
+            NoteName::A => "A",
+            NoteName::B => "B",
+            NoteName::C => "C",
+            NoteName::D => "D",
+            NoteName::E => "E",
+            NoteName::F => "F",
+            NoteName::G => "G",
+            NoteName::Unknown | NoteName::Reserved(_) => "?",
+        };
+        f.write_str(letter)
+    }
+}
+
+impl fmt::Display for ChordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match SUFFIXES.iter().find(|(_, chord_type)| chord_type == self) {
+            Some((suffix, _)) => f.write_str(suffix),
+            None => f.write_str("?"),
+        }
+    }
+}
+
+impl fmt::Display for Alteration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alteration::AddDegree(degree) => write!(f, "add{degree}"),
+            Alteration::SubtractDegree(degree) => write!(f, "no{degree}"),
+            Alteration::RaiseDegree(degree) => write!(f, "#{degree}"),
+            Alteration::LowerDegree(degree) => write!(f, "b{degree}"),
+            Alteration::None | Alteration::Reserved { .. } => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for FlexChordName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.chord_type == ChordType::None {
+            return f.write_str("N.C.");
+        }
+        write_note(f, self.tonic_note, self.tonic_alteration)?;
+        write_chord(f, self.chord_type, &self.chord_alterations)?;
+        if self.bass_note != NoteName::Unknown && self.bass_alteration != -8 {
+            f.write_str("/")?;
+            write_note(f, self.bass_note, self.bass_alteration)?;
+            if self.bass_chord_type != ChordType::None {
+                write_chord(f, self.bass_chord_type, &self.bass_chord_alterations)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_note(f: &mut fmt::Formatter<'_>, note: NoteName, alteration: i8) -> fmt::Result {
+    write!(f, "{note}")?;
+    let accidental = if alteration < 0 { "b" } else { "#" };
+    for _ in 0..alteration.unsigned_abs() {
+        f.write_str(accidental)?;
+    }
+    Ok(())
+}
+
+fn write_chord(
+    f: &mut fmt::Formatter<'_>,
+    chord_type: ChordType,
+    alterations: &[Alteration],
+) -> fmt::Result {
+    write!(f, "{chord_type}")?;
+
+    // Without a suffix, a raised or lowered degree would read as an accidental of the note.
+    let parenthesize = chord_type == ChordType::Major
+        && matches!(
+            alterations.first(),
+            Some(Alteration::RaiseDegree(_) | Alteration::LowerDegree(_))
+        );
+    if parenthesize {
+        f.write_str("(")?;
+    }
+    for alteration in alterations {
+        write!(f, "{alteration}")?;
+    }
+    if parenthesize {
+        f.write_str(")")?;
+    }
+    Ok(())
+}
+
+impl FromStr for FlexChordName {
+    type Err = ParseChordNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "N.C." || s == "NC" {
+            return Ok(Self {
+                tonic_note: NoteName::Unknown,
+                tonic_alteration: 0,
+                chord_type: ChordType::None,
+                chord_alterations: [Alteration::None; 4],
+                bass_note: NoteName::Unknown,
+                bass_alteration: 0,
+                bass_chord_type: ChordType::None,
+                bass_chord_alterations: [Alteration::None; 2],
+            });
+        }
+
+        let (chord, bass) = match s.split_once('/') {
+            Some((chord, bass)) => (chord, Some(bass)),
+            None => (s, None),
+        };
+
+        let (tonic_note, tonic_alteration, rest) = parse_note(chord)?;
+        let (chord_type, rest) = parse_chord_type(rest);
+        let mut chord_alterations = [Alteration::None; 4];
+        parse_alterations(rest, &mut chord_alterations)?;
+
+        let mut bass_note = NoteName::Unknown;
+        let mut bass_alteration = 0;
+        let mut bass_chord_type = ChordType::None;
+        let mut bass_chord_alterations = [Alteration::None; 2];
+        if let Some(bass) = bass {
+            let (note, alteration, rest) = parse_note(bass)?;
+            bass_note = note;
+            bass_alteration = alteration;
+            if !rest.is_empty() {
+                let (chord_type, rest) = parse_chord_type(rest);
+                bass_chord_type = chord_type;
+                parse_alterations(rest, &mut bass_chord_alterations)?;
+            }
+        }
+
+        Ok(Self {
+            tonic_note,
+            tonic_alteration,
+            chord_type,
+            chord_alterations,
+            bass_note,
+            bass_alteration,
+            bass_chord_type,
+            bass_chord_alterations,
+        })
+    }
+}
+
+fn parse_note(s: &str) -> Result<(NoteName, i8, &str), ParseChordNameError> {
+    let mut chars = s.chars();
+    let note = match chars.next() {
+        Some('A') => NoteName::A,
+        Some('B') => NoteName::B,
+        Some('C') => NoteName::C,
+        Some('D') => NoteName::D,
+        Some('E') => NoteName::E,
+        Some('F') => NoteName::F,
+        Some('G') => NoteName::G,
+        _ => return Err(ParseChordNameError::InvalidNote),
+    };
+    let mut rest = chars.as_str();
+    let mut alteration = 0_i8;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '#' | '♯' => alteration += 1,
+            'b' | '♭' => alteration -= 1,
+            _ => break,
+        }
+        if !(-7..=7).contains(&alteration) {
+            return Err(ParseChordNameError::TooManyAccidentals);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    Ok((note, alteration, rest))
+}
+
+fn parse_chord_type(s: &str) -> (ChordType, &str) {
+    let (suffix, chord_type) = SUFFIXES
+        .iter()
+        .filter(|(suffix, _)| s.starts_with(suffix))
+        .max_by_key(|(suffix, _)| suffix.len())
+        .copied()
+        .unwrap_or(("", ChordType::Major));
+    (chord_type, &s[suffix.len()..])
+}
+
+fn parse_alterations(
+    mut s: &str,
+    alterations: &mut [Alteration],
+) -> Result<(), ParseChordNameError> {
+    let mut count = 0;
+    loop {
+        s = s.trim_start_matches(['(', ')', ',', ' ']);
+        if s.is_empty() {
+            return Ok(());
+        }
+        let (kind, rest): (fn(u8) -> Alteration, &str) = if let Some(rest) = s.strip_prefix("add") {
+            (Alteration::AddDegree, rest)
+        } else if let Some(rest) = s.strip_prefix("no").or_else(|| s.strip_prefix("omit")) {
+            (Alteration::SubtractDegree, rest)
+        } else if let Some(rest) = s.strip_prefix('#').or_else(|| s.strip_prefix('♯')) {
+            (Alteration::RaiseDegree, rest)
+        } else if let Some(rest) = s.strip_prefix('b').or_else(|| s.strip_prefix('♭')) {
+            (Alteration::LowerDegree, rest)
+        } else {
+            return Err(ParseChordNameError::InvalidAlteration);
+        };
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let degree = rest[..digits]
+            .parse::<u8>()
+            .ok()
+            .filter(|degree| (1..16).contains(degree))
+            .ok_or(ParseChordNameError::InvalidAlteration)?;
+        let slot = alterations
+            .get_mut(count)
+            .ok_or(ParseChordNameError::TooManyAlterations)?;
+        *slot = kind(degree);
+        count += 1;
+        s = &rest[digits..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chord_type_round_trip() {
+        for value in 0..=u8::MAX {
+            assert_eq!(value, u8::from(ChordType::from(value)));
+        }
+    }
+
+    #[test]
+    fn alteration_round_trip() {
+        for value in 0..=u8::MAX {
+            assert_eq!(value, u8::from(Alteration::from(value)));
+        }
+    }
+
+    #[test]
+    fn note_name_round_trip() {
+        for value in 0..16 {
+            assert_eq!(value, u8::from(NoteName::from(value)));
+        }
+    }
+
+    #[test]
+    fn parse_half_diminished_slash_chord() {
+        let chord: FlexChordName = "C#m7b5/E".parse().unwrap();
+        assert_eq!(chord.tonic_note, NoteName::C);
+        assert_eq!(chord.tonic_alteration, 1);
+        assert_eq!(chord.chord_type, ChordType::Minor7);
+        assert_eq!(
+            chord.chord_alterations,
+            [
+                Alteration::LowerDegree(5),
+                Alteration::None,
+                Alteration::None,
+                Alteration::None
+            ]
+        );
+        assert_eq!(chord.bass_note, NoteName::E);
+        assert_eq!(chord.bass_alteration, 0);
+        assert_eq!(chord.to_string(), "C#m7b5/E");
+    }
+
+    #[test]
+    fn text_round_trip() {
+        for text in [
+            "C",
+            "Bbmaj7#11",
+            "F#m",
+            "G7sus4/F",
+            "Ebm(maj7)",
+            "Dø",
+            "A+7",
+            "C(#5)",
+            "Cadd9",
+            "Abdim7",
+            "E7b9#9",
+            "N.C.",
+            "Db/Ab",
+            "C/Bbm",
+        ] {
+            let chord: FlexChordName = text.parse().unwrap();
+            assert_eq!(text, chord.to_string());
+            let words: [u32; 3] = chord.into();
+            assert_eq!(chord, words.into());
+        }
+    }
+
+    #[test]
+    fn parse_aliases() {
+        let chord: FlexChordName = "Cmin7(b5)".parse().unwrap();
+        assert_eq!(chord.to_string(), "Cm7b5");
+        let chord: FlexChordName = "F♯°7".parse().unwrap();
+        assert_eq!(chord.to_string(), "F#dim7");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "H7".parse::<FlexChordName>(),
+            Err(ParseChordNameError::InvalidNote)
+        );
+        assert_eq!(
+            "C7x".parse::<FlexChordName>(),
+            Err(ParseChordNameError::InvalidAlteration)
+        );
+        assert_eq!(
+            "C7b9#9#11b13add2".parse::<FlexChordName>(),
+            Err(ParseChordNameError::TooManyAlterations)
+        );
+        assert_eq!(
+            "C/Dm7b9#9b5".parse::<FlexChordName>(),
+            Err(ParseChordNameError::TooManyAlterations)
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Musical keys, for interpreting Set Key Signature messages.
+//!
+//! A key signature only declares a number of sharps or flats and a tonic letter. Together these
+//! identify a major or minor key, which determines the scale and how MIDI note numbers are spelled.
+use core::fmt;
+use core::str::FromStr;
+
+use super::chord::ParseChordNameError;
+use super::{FlexKeySignature, NoteName};
+
+/// The mode of a key.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Mode {
+    /// The major (ionian) mode.
+    Major,
+
+    /// The natural minor (aeolian) mode.
+    Minor,
+}
+
+/// A musical key, eg E♭ major.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Key {
+    /// The letter of the tonic.
+    pub tonic: NoteName,
+
+    /// Sharps (positive) or flats (negative) applied to the tonic.
+    pub alteration: i8,
+
+    /// Major or minor.
+    pub mode: Mode,
+}
+
+/// A note name with accidentals and an octave, eg C♯4.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct SpelledNote {
+    /// The letter of the note.
+    pub letter: NoteName,
+
+    /// Sharps (positive) or flats (negative) applied to the letter.
+    pub alteration: i8,
+
+    /// The octave, where MIDI note 60 is in octave 4.
+    pub octave: i8,
+}
+
+/// Errors that can occur when parsing a key.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum ParseKeyError {
+    /// The tonic is not a valid note.
+    InvalidTonic(ParseChordNameError),
+
+    /// The mode is not major or minor.
+    InvalidMode,
+}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTonic(err) => write!(f, "invalid tonic: {err}"),
+            Self::InvalidMode => f.write_str("invalid mode"),
+        }
+    }
+}
+
+/// The position of a note letter on the line of fifths, relative to C.
+fn letter_fifths(letter: NoteName) -> Option<i32> {
+    match letter {
+        NoteName::F => Some(-1),
+        NoteName::C => Some(0),
+        NoteName::G => Some(1),
+        NoteName::D => Some(2),
+        NoteName::A => Some(3),
+        NoteName::E => Some(4),
+        NoteName::B => Some(5),
+        NoteName::Unknown | NoteName::Reserved(_) => None,
+    }
+}
+
+/// The spelled note at a position on the line of fifths, relative to C.
+fn from_fifths(fifths: i32) -> (NoteName, i8) {
+    const LETTERS: [NoteName; 7] = [
+        NoteName::F,
+        NoteName::C,
+        NoteName::G,
+        NoteName::D,
+        NoteName::A,
+        NoteName::E,
+        NoteName::B,
+    ];
+    let letter = LETTERS[(fifths + 1).rem_euclid(7) as usize];
+    (letter, (fifths + 1).div_euclid(7) as i8)
+}
+
+/// The pitch class of a note letter, where C is 0.
+fn letter_pitch_class(letter: NoteName) -> Option<i32> {
+    letter_fifths(letter).map(|fifths| (fifths * 7).rem_euclid(12))
+}
+
+impl Key {
+    /// Create a new key.
+    pub fn new(tonic: NoteName, alteration: i8, mode: Mode) -> Self {
+        Self {
+            tonic,
+            alteration,
+            mode,
+        }
+    }
+
+    /// The number of sharps (positive) or flats (negative) in the key signature, which may be
+    /// more than seven for theoretical keys like G♯ major.
+    pub fn fifths(&self) -> Option<i32> {
+        let tonic = letter_fifths(self.tonic)? + 7 * self.alteration as i32;
+        match self.mode {
+            Mode::Major => Some(tonic),
+            Mode::Minor => Some(tonic - 3),
+        }
+    }
+
+    /// Find the key with a number of sharps or flats and a mode.
+    pub fn from_fifths(fifths: i32, mode: Mode) -> Self {
+        let tonic = match mode {
+            Mode::Major => fifths,
+            Mode::Minor => fifths + 3,
+        };
+        let (tonic, alteration) = from_fifths(tonic);
+        Self::new(tonic, alteration, mode)
+    }
+
+    /// The Set Key Signature message value of this key, if it has at most seven sharps or flats.
+    pub fn key_signature(&self) -> Option<FlexKeySignature> {
+        let fifths = self.fifths().filter(|f| (-7..=7).contains(f))?;
+        Some(FlexKeySignature::from_note(self.tonic.into(), fifths as i8))
+    }
+
+    /// The pitch class of the tonic, where C is 0.
+    pub fn tonic_pitch_class(&self) -> Option<u8> {
+        let pitch_class = letter_pitch_class(self.tonic)? + self.alteration as i32;
+        Some(pitch_class.rem_euclid(12) as u8)
+    }
+
+    /// The pitch classes of the scale, starting at the tonic.
+    pub fn scale(&self) -> Option<[u8; 7]> {
+        const MAJOR: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+        const MINOR: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
+        let tonic = self.tonic_pitch_class()?;
+        let steps = match self.mode {
+            Mode::Major => MAJOR,
+            Mode::Minor => MINOR,
+        };
+        Some(steps.map(|step| (tonic + step) % 12))
+    }
+
+    /// The pitch classes of the scale as a set, where bit `n` is set if pitch class `n` is
+    /// in the scale.
+    pub fn pitch_class_set(&self) -> Option<u16> {
+        let scale = self.scale()?;
+        Some(scale.iter().fold(0, |set, pc| set | 1 << pc))
+    }
+
+    /// Spell a MIDI note number in this key. Notes in the scale use its letters, chromatic
+    /// notes use the accidentals closest to the key signature, eg G♯ rather than A♭ in A minor.
+    pub fn spell(&self, note: u8) -> Option<SpelledNote> {
+        // The twelve fifths closest to the middle of the scale.
+        let center = match self.mode {
+            Mode::Major => self.fifths()? + 2,
+            Mode::Minor => self.fifths()? + 3,
+        };
+        let pitch_class = note as i32 % 12;
+        let base = (pitch_class * 7).rem_euclid(12);
+        let fifths = base + 12 * (center - 6 - base).div_euclid(12) + 12;
+        let fifths = if fifths > center + 5 {
+            fifths - 12
+        } else {
+            fifths
+        };
+        let (letter, alteration) = from_fifths(fifths);
+        let natural = letter_pitch_class(letter)? + alteration as i32;
+        Some(SpelledNote {
+            letter,
+            alteration,
+            octave: ((note as i32 - natural).div_euclid(12) - 1) as i8,
+        })
+    }
+
+    /// The data bytes of a Standard MIDI File key signature meta event (`FF 59 02 sf mi`).
+    pub fn to_smf(&self) -> Option<[u8; 2]> {
+        let fifths = self.fifths().filter(|f| (-7..=7).contains(f))?;
+        let mode = match self.mode {
+            Mode::Major => 0,
+            Mode::Minor => 1,
+        };
+        Some([fifths as i8 as u8, mode])
+    }
+
+    /// Parse the data bytes of a Standard MIDI File key signature meta event.
+    pub fn from_smf(data: [u8; 2]) -> Option<Self> {
+        let fifths = data[0] as i8 as i32;
+        let mode = match data[1] {
+            0 => Mode::Major,
+            1 => Mode::Minor,
+            _ => return None,
+        };
+        (-7..=7)
+            .contains(&fifths)
+            .then(|| Self::from_fifths(fifths, mode))
+    }
+}
+
+impl FlexKeySignature {
+    /// The major or minor key of this key signature, if the tonic belongs to one.
+    pub fn key(&self) -> Option<Key> {
+        let fifths = self.sharps_or_flats() as i32;
+        if fifths == -8 {
+            return None;
+        }
+        let tonic = NoteName::from(self.tonic_note());
+        [Mode::Major, Mode::Minor]
+            .iter()
+            .map(|mode| Key::from_fifths(fifths, *mode))
+            .find(|key| key.tonic == tonic)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Major => f.write_str("major"),
+            Mode::Minor => f.write_str("minor"),
+        }
+    }
+}
+
+fn write_accidentals(f: &mut fmt::Formatter<'_>, alteration: i8) -> fmt::Result {
+    let accidental = if alteration < 0 { "b" } else { "#" };
+    for _ in 0..alteration.unsigned_abs() {
+        f.write_str(accidental)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tonic)?;
+        write_accidentals(f, self.alteration)?;
+        write!(f, " {}", self.mode)
+    }
+}
+
+impl fmt::Display for SpelledNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.letter)?;
+        write_accidentals(f, self.alteration)?;
+        write!(f, "{}", self.octave)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseKeyError;
+
+    /// Parses keys like `Eb major`, `E♭ major`, `F# minor`, `F#m` or `C`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| !matches!(c, '#' | '♯' | 'b' | '♭'))
+            .map_or(s.len(), |(i, _)| i);
+        let (tonic, mode) = s.split_at(split);
+        let tonic: super::FlexChordName = tonic.parse().map_err(ParseKeyError::InvalidTonic)?;
+        let mode = match mode.trim() {
+            "" | "M" => Mode::Major,
+            "m" => Mode::Minor,
+            mode if mode.eq_ignore_ascii_case("major") || mode.eq_ignore_ascii_case("maj") => {
+                Mode::Major
+            }
+            mode if mode.eq_ignore_ascii_case("minor") || mode.eq_ignore_ascii_case("min") => {
+                Mode::Minor
+            }
+            _ => return Err(ParseKeyError::InvalidMode),
+        };
+        Ok(Self::new(tonic.tonic_note, tonic.tonic_alteration, mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_signature_round_trip() {
+        for fifths in -7..=7 {
+            for mode in [Mode::Major, Mode::Minor] {
+                let key = Key::from_fifths(fifths, mode);
+                let key_signature = key.key_signature().unwrap();
+                assert_eq!(key_signature.sharps_or_flats() as i32, fifths);
+                assert_eq!(key_signature.key(), Some(key));
+                assert_eq!(Key::from_smf(key.to_smf().unwrap()), Some(key));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let key: Key = "E♭ major".parse().unwrap();
+        assert_eq!(key, Key::new(NoteName::E, -1, Mode::Major));
+        assert_eq!(key.fifths(), Some(-3));
+        assert_eq!(key.to_string(), "Eb major");
+
+        let key: Key = "F#m".parse().unwrap();
+        assert_eq!(key, Key::new(NoteName::F, 1, Mode::Minor));
+        assert_eq!(key.to_smf(), Some([3, 1]));
+        assert_eq!(key.to_string(), "F# minor");
+
+        assert_eq!("Bb".parse(), Ok(Key::new(NoteName::B, -1, Mode::Major)));
+        assert_eq!("C dorian".parse::<Key>(), Err(ParseKeyError::InvalidMode));
+    }
+
+    #[test]
+    fn scales() {
+        let key = Key::new(NoteName::E, -1, Mode::Major);
+        assert_eq!(key.scale(), Some([3, 5, 7, 8, 10, 0, 2]));
+        let key = Key::new(NoteName::A, 0, Mode::Minor);
+        assert_eq!(key.pitch_class_set(), Some(0b1010_1011_0101));
+    }
+
+    #[test]
+    fn spelling() {
+        let spell = |key: &str, note| key.parse::<Key>().unwrap().spell(note).unwrap().to_string();
+        assert_eq!(spell("C major", 60), "C4");
+        assert_eq!(spell("C major", 61), "C#4");
+        assert_eq!(spell("C major", 70), "Bb4");
+        assert_eq!(spell("A minor", 68), "G#4");
+        assert_eq!(spell("Eb major", 63), "Eb4");
+        assert_eq!(spell("Eb major", 68), "Ab4");
+        assert_eq!(spell("F# major", 65), "E#4");
+        assert_eq!(spell("Gb major", 59), "Cb4");
+        assert_eq!(spell("C# major", 60), "B#3");
+        assert_eq!(spell("Db major", 66), "Gb4");
+    }
+
+    #[test]
+    fn tonic_must_match_key_signature() {
+        // Two sharps with a tonic of E is neither D major nor B minor.
+        assert_eq!(
+            FlexKeySignature::from_note(NoteName::E.into(), 2).key(),
+            None
+        );
+        assert_eq!(
+            FlexKeySignature::from_note(NoteName::B.into(), 2).key(),
+            Some(Key::new(NoteName::B, 0, Mode::Minor))
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! A timeline of lyrics built from Performance Text Event messages.
+//!
+//! Lyrics are stored per group and address as a list of timed syllables. Ruby lyrics are
+//! paired with the lyric syllable sent at the same time, and the BCP 47 language of the
+//! lyrics and ruby lyrics is tracked for each address.
+use std::collections::HashMap;
+
+use super::text::{Text, TextAssembler};
+use super::{Flex, FlexAddress, FlexPerformanceTextEvent, FlexStatus};
+
+/// A syllable of lyrics and its ruby annotation, if any.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Syllable {
+    /// The time of the syllable, in the units of the caller (eg ticks).
+    pub time: u64,
+
+    /// The lyric text. Empty if only ruby was received for this time.
+    pub text: String,
+
+    /// The ruby annotation of the text.
+    pub ruby: Option<String>,
+}
+
+/// The lyrics sent to a single group and address.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct LyricTrack {
+    /// BCP 47 language identifier of the lyrics, eg `ja`.
+    pub language: Option<String>,
+
+    /// BCP 47 language identifier of the ruby lyrics, eg `ja-Hira`.
+    pub ruby_language: Option<String>,
+
+    syllables: Vec<Syllable>,
+}
+
+impl LyricTrack {
+    /// The syllables of the track, ordered by time.
+    pub fn syllables(&self) -> &[Syllable] {
+        &self.syllables
+    }
+
+    /// The syllable at a time, if there is one.
+    pub fn syllable_at(&self, time: u64) -> Option<&Syllable> {
+        let index = self.syllables.partition_point(|s| s.time < time);
+        self.syllables.get(index).filter(|s| s.time == time)
+    }
+
+    /// Add a lyric syllable. If ruby was already received at this time, the text is added to
+    /// that syllable.
+    pub fn push_lyric(&mut self, time: u64, text: impl Into<String>) {
+        let text = text.into();
+        match self.find_mut(time, |s| s.text.is_empty()) {
+            Some(syllable) => syllable.text = text,
+            None => self.insert(Syllable {
+                time,
+                text,
+                ruby: None,
+            }),
+        }
+    }
+
+    /// Annotate the lyric syllable at the same time with ruby. If there is no such syllable,
+    /// one is created with empty text to be filled in by [LyricTrack::push_lyric].
+    pub fn push_ruby(&mut self, time: u64, ruby: impl Into<String>) {
+        let ruby = Some(ruby.into());
+        match self.find_mut(time, |s| s.ruby.is_none()) {
+            Some(syllable) => syllable.ruby = ruby,
+            None => self.insert(Syllable {
+                time,
+                text: String::new(),
+                ruby,
+            }),
+        }
+    }
+
+    fn find_mut(&mut self, time: u64, f: impl Fn(&Syllable) -> bool) -> Option<&mut Syllable> {
+        let start = self.syllables.partition_point(|s| s.time < time);
+        self.syllables[start..]
+            .iter_mut()
+            .take_while(|s| s.time == time)
+            .find(|s| f(s))
+    }
+
+    fn insert(&mut self, syllable: Syllable) {
+        let index = self.syllables.partition_point(|s| s.time <= syllable.time);
+        self.syllables.insert(index, syllable);
+    }
+}
+
+/// Lyric tracks for every group and address, built from or serialized to timed Flex messages.
+#[derive(Clone, Debug, Default)]
+pub struct LyricTimeline {
+    tracks: HashMap<(u8, FlexAddress), LyricTrack>,
+    assembler: TextAssembler,
+}
+
+impl LyricTimeline {
+    /// Create an empty timeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a timeline from Flex messages and the time each was received.
+    pub fn from_messages(messages: impl IntoIterator<Item = (u64, Flex)>) -> Self {
+        let mut timeline = Self::new();
+        for (time, message) in messages {
+            timeline.push(time, &message);
+        }
+        timeline
+    }
+
+    /// Add a Flex message received at a time. Text spanning several packets is added at the
+    /// time of its final packet. Messages other than lyrics are ignored.
+    pub fn push(&mut self, time: u64, message: &Flex) {
+        let Some(Text {
+            group,
+            address,
+            status,
+            text,
+        }) = self.assembler.push(message)
+        else {
+            return;
+        };
+        let FlexStatus::PerformanceTextEvent(event) = status else {
+            return;
+        };
+        let track = self.track_mut(group, address);
+        match event {
+            FlexPerformanceTextEvent::Lyrics => track.push_lyric(time, text),
+            FlexPerformanceTextEvent::LyricsLanguage => track.language = Some(text),
+            FlexPerformanceTextEvent::RubyLyrics => track.push_ruby(time, text),
+            FlexPerformanceTextEvent::RubyLyricsLanguage => track.ruby_language = Some(text),
+            FlexPerformanceTextEvent::Unknown => (),
+        }
+    }
+
+    /// The track of a group and address, if any lyrics were sent to it.
+    pub fn track(&self, group: u8, address: FlexAddress) -> Option<&LyricTrack> {
+        self.tracks.get(&(group, address))
+    }
+
+    /// The track of a group and address, created if it does not exist.
+    pub fn track_mut(&mut self, group: u8, address: FlexAddress) -> &mut LyricTrack {
+        self.tracks.entry((group, address)).or_default()
+    }
+
+    /// Iterate over the tracks of the timeline, in no particular order.
+    pub fn tracks(&self) -> impl Iterator<Item = (u8, FlexAddress, &LyricTrack)> + '_ {
+        self.tracks
+            .iter()
+            .map(|((group, address), track)| (*group, *address, track))
+    }
+
+    /// Serialize the timeline into timed Flex messages, ordered by time. The languages of a
+    /// track are sent at the time of its first syllable, and each ruby annotation directly
+    /// follows its lyric.
+    pub fn to_messages(&self) -> Vec<(u64, Flex)> {
+        let mut keys: Vec<_> = self.tracks.keys().copied().collect();
+        keys.sort_by_key(|(group, address)| (*group, u8::from(*address)));
+
+        let mut messages = Vec::new();
+        for (group, address) in keys {
+            let track = &self.tracks[&(group, address)];
+            let mut push = |time: u64, event: FlexPerformanceTextEvent, text: &str| {
+                let status = FlexStatus::PerformanceTextEvent(event);
+                messages.extend(Flex::text(group, address, status, text).map(|m| (time, m)));
+            };
+            let start = track.syllables.first().map_or(0, |s| s.time);
+            if let Some(language) = &track.language {
+                push(start, FlexPerformanceTextEvent::LyricsLanguage, language);
+            }
+            if let Some(language) = &track.ruby_language {
+                push(
+                    start,
+                    FlexPerformanceTextEvent::RubyLyricsLanguage,
+                    language,
+                );
+            }
+            for syllable in &track.syllables {
+                if !syllable.text.is_empty() {
+                    push(
+                        syllable.time,
+                        FlexPerformanceTextEvent::Lyrics,
+                        &syllable.text,
+                    );
+                }
+                if let Some(ruby) = &syllable.ruby {
+                    push(syllable.time, FlexPerformanceTextEvent::RubyLyrics, ruby);
+                }
+            }
+        }
+        messages.sort_by_key(|(time, _)| *time);
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_ruby_with_lyrics() {
+        let mut track = LyricTrack::default();
+        track.push_lyric(0, "東");
+        track.push_ruby(0, "ひがし");
+        track.push_ruby(480, "きょう");
+        track.push_lyric(480, "京");
+        track.push_lyric(960, "へ");
+        assert_eq!(
+            track.syllables(),
+            [
+                Syllable {
+                    time: 0,
+                    text: "東".into(),
+                    ruby: Some("ひがし".into())
+                },
+                Syllable {
+                    time: 480,
+                    text: "京".into(),
+                    ruby: Some("きょう".into())
+                },
+                Syllable {
+                    time: 960,
+                    text: "へ".into(),
+                    ruby: None
+                },
+            ]
+        );
+        assert_eq!(track.syllable_at(480).unwrap().text, "京");
+        assert_eq!(track.syllable_at(481), None);
+    }
+
+    #[test]
+    fn round_trip_messages() {
+        let mut timeline = LyricTimeline::new();
+        let track = timeline.track_mut(0, FlexAddress::Channel(2));
+        track.language = Some("ja".into());
+        track.ruby_language = Some("ja-Hira".into());
+        track.push_lyric(0, "東京");
+        track.push_ruby(0, "とうきょう");
+        track.push_lyric(240, "特許許可局長今日急遽休暇許可拒否");
+        timeline
+            .track_mut(1, FlexAddress::Group(0))
+            .push_lyric(120, "Hello");
+
+        let messages = timeline.to_messages();
+        assert!(messages.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        let decoded = LyricTimeline::from_messages(messages);
+        assert_eq!(
+            decoded.track(0, FlexAddress::Channel(2)),
+            timeline.track(0, FlexAddress::Channel(2))
+        );
+        assert_eq!(
+            decoded.track(1, FlexAddress::Group(0)),
+            timeline.track(1, FlexAddress::Group(0))
+        );
+        assert_eq!(decoded.tracks().count(), 2);
+    }
+}
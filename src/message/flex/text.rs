@@ -0,0 +1,318 @@
+//! Text spanning multiple Flex Data packets, such as metadata and lyrics.
+//!
+//! Each packet carries up to 12 bytes of UTF-8 text. Longer text is split across a Start packet,
+//! any number of Continue packets and an End packet, never cutting a codepoint in two.
+use super::{Flex, FlexAddress, FlexSetupAndPerformance, FlexStatus};
+use crate::message::data::DataFormat;
+#[cfg(not(feature = "no-std"))]
+use crate::message::Message;
+
+/// Maximum number of text bytes in a single packet.
+pub const TEXT_BYTES_PER_PACKET: usize = 12;
+
+/// An iterator over the packets of a text message, see [Flex::text].
+#[derive(Clone, Debug)]
+pub struct TextPackets<'a> {
+    group: u8,
+    address: FlexAddress,
+    status: FlexStatus,
+    remaining: &'a str,
+    first: bool,
+    done: bool,
+}
+
+impl Flex {
+    /// Split text into the packets of a text message. Empty text is sent as a single empty packet.
+    pub fn text(
+        group: u8,
+        address: FlexAddress,
+        status: FlexStatus,
+        text: &str,
+    ) -> TextPackets<'_> {
+        debug_assert!(
+            !matches!(status, FlexStatus::Reserved(_)),
+            "Text must have a text message status."
+        );
+        TextPackets {
+            group,
+            address,
+            status,
+            remaining: text,
+            first: true,
+            done: false,
+        }
+    }
+
+    /// The text bytes of this packet, without the zero padding.
+    pub fn text_bytes(&self) -> ([u8; TEXT_BYTES_PER_PACKET], usize) {
+        let bytes = self.text_message();
+        let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        (bytes, len)
+    }
+}
+
+impl<'a> Iterator for TextPackets<'a> {
+    type Item = Flex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut len = self.remaining.len().min(TEXT_BYTES_PER_PACKET);
+        while !self.remaining.is_char_boundary(len) {
+            len -= 1;
+        }
+        let (chunk, rest) = self.remaining.split_at(len);
+        self.remaining = rest;
+        self.done = rest.is_empty();
+
+        let format = match (self.first, self.done) {
+            (true, true) => DataFormat::SinglePacket,
+            (true, false) => DataFormat::Start,
+            (false, false) => DataFormat::Continue,
+            (false, true) => DataFormat::End,
+        };
+        self.first = false;
+
+        let mut bytes = [0; TEXT_BYTES_PER_PACKET];
+        bytes[..len].copy_from_slice(chunk.as_bytes());
+        let mut data = [0; 3];
+        for (word, chunk) in data.iter_mut().zip(bytes.chunks_exact(4)) {
+            *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        Some(Flex::new(
+            self.group,
+            self.address,
+            format,
+            self.status,
+            data,
+        ))
+    }
+}
+
+/// Returns true if the status of a Flex Data message carries text.
+pub fn is_text(status: FlexStatus) -> bool {
+    matches!(
+        status,
+        FlexStatus::MetadataText(_)
+            | FlexStatus::PerformanceTextEvent(_)
+            | FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::TextMessageCommonFormat(_))
+    )
+}
+
+/// A complete text message.
+#[cfg(not(feature = "no-std"))]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Text {
+    /// The group the text was sent on.
+    pub group: u8,
+
+    /// The channel or group the text is addressed to.
+    pub address: FlexAddress,
+
+    /// What the text describes, eg [super::FlexMetadataText::CopyrightNotice].
+    pub status: FlexStatus,
+
+    /// The text itself. Invalid UTF-8 is replaced with `U+FFFD`.
+    pub text: String,
+}
+
+/// The default length in bytes of the longest text a [TextAssembler] collects.
+pub const MAX_TEXT_LEN: usize = 4096;
+
+/// Collects the packets of text messages into complete strings.
+///
+/// Text messages in flight are tracked per group, address and status, so messages for
+/// different channels or of different kinds may be interleaved. A message growing past the
+/// maximum length is dropped.
+#[cfg(not(feature = "no-std"))]
+#[derive(Clone, Debug)]
+pub struct TextAssembler {
+    pending: std::collections::HashMap<(u8, FlexAddress, FlexStatus), Vec<u8>>,
+    max_len: usize,
+}
+
+#[cfg(not(feature = "no-std"))]
+impl Default for TextAssembler {
+    fn default() -> Self {
+        Self {
+            pending: std::collections::HashMap::new(),
+            max_len: MAX_TEXT_LEN,
+        }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl TextAssembler {
+    /// Create a new assembler, collecting text of up to [MAX_TEXT_LEN] bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define the length in bytes of the longest text collected.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Add a packet, returning the text if the packet completes a message. Packets that do
+    /// not carry text, and Continue or End packets without a Start, are ignored.
+    pub fn push(&mut self, message: &Flex) -> Option<Text> {
+        let status = message.status();
+        if !is_text(status) {
+            return None;
+        }
+        let key = (message.group(), message.address(), status);
+        let (bytes, len) = message.text_bytes();
+        let bytes = &bytes[..len];
+        let complete = match message.format() {
+            DataFormat::SinglePacket => {
+                self.pending.remove(&key);
+                bytes.to_vec()
+            }
+            DataFormat::Start => {
+                self.pending.insert(key, bytes.to_vec());
+                return None;
+            }
+            DataFormat::Continue => {
+                let text = self.pending.get_mut(&key)?;
+                if text.len() + bytes.len() > self.max_len {
+                    self.pending.remove(&key);
+                } else {
+                    text.extend_from_slice(bytes);
+                }
+                return None;
+            }
+            DataFormat::End => {
+                let mut text = self.pending.remove(&key)?;
+                if text.len() + bytes.len() > self.max_len {
+                    return None;
+                }
+                text.extend_from_slice(bytes);
+                text
+            }
+            DataFormat::Reserved => return None,
+        };
+        let text = match String::from_utf8(complete) {
+            Ok(text) => text,
+            Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+        };
+        Some(Text {
+            group: key.0,
+            address: key.1,
+            status: key.2,
+            text,
+        })
+    }
+
+    /// Drop any partially received text.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::flex::{FlexMetadataText, FlexPerformanceTextEvent};
+
+    const TITLE: FlexStatus = FlexStatus::MetadataText(FlexMetadataText::CompositionName);
+    const LYRICS: FlexStatus = FlexStatus::PerformanceTextEvent(FlexPerformanceTextEvent::Lyrics);
+
+    #[test]
+    fn single_packet() {
+        let packets: Vec<_> = Flex::text(0, FlexAddress::Group(0), TITLE, "Hello").collect();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].format(), DataFormat::SinglePacket);
+        assert_eq!(packets[0][0], 0xD010_0102);
+        assert_eq!(&packets[0][1..], &[0x4865_6C6C, 0x6F00_0000, 0]);
+    }
+
+    #[test]
+    fn never_splits_codepoints() {
+        let text = "aありがとうございました";
+        let packets: Vec<_> = Flex::text(0, FlexAddress::Channel(1), LYRICS, text).collect();
+        let formats: Vec<_> = packets.iter().map(Flex::format).collect();
+        assert_eq!(
+            formats,
+            [DataFormat::Start, DataFormat::Continue, DataFormat::End]
+        );
+        let mut lengths = Vec::new();
+        for packet in &packets {
+            let (bytes, len) = packet.text_bytes();
+            assert!(core::str::from_utf8(&bytes[..len]).is_ok());
+            lengths.push(len);
+        }
+        assert_eq!(lengths, [10, 12, 12]);
+    }
+
+    #[test]
+    fn assembles_interleaved_messages() {
+        let title = "Take the 'A' Train, by Billy Strayhorn";
+        let lyric = "Héllo wörld, ça va?";
+        let mut assembler = TextAssembler::new();
+        let mut title_packets = Flex::text(2, FlexAddress::Group(0), TITLE, title);
+        let mut lyric_packets = Flex::text(2, FlexAddress::Channel(3), LYRICS, lyric);
+        let mut complete = Vec::new();
+        loop {
+            let a = title_packets.next();
+            let b = lyric_packets.next();
+            if a.is_none() && b.is_none() {
+                break;
+            }
+            complete.extend(a.and_then(|p| assembler.push(&p)));
+            complete.extend(b.and_then(|p| assembler.push(&p)));
+        }
+        assert_eq!(complete.len(), 2);
+        assert_eq!(complete[0].text, lyric);
+        assert_eq!(complete[0].address, FlexAddress::Channel(3));
+        assert_eq!(complete[1].text, title);
+        assert_eq!(complete[1].status, TITLE);
+        assert_eq!(complete[1].group, 2);
+    }
+
+    #[test]
+    fn round_trip_lengths() {
+        let mut assembler = TextAssembler::new();
+        let source = "aé漢😀".repeat(8);
+        for end in 0..source.len() {
+            if !source.is_char_boundary(end) {
+                continue;
+            }
+            let text = &source[..end];
+            let received: Vec<_> = Flex::text(0, FlexAddress::Group(0), TITLE, text)
+                .filter_map(|p| assembler.push(&p))
+                .collect();
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0].text, text);
+        }
+    }
+
+    #[test]
+    fn drops_long_text() {
+        let mut assembler = TextAssembler::new().with_max_len(24);
+        let fits = "a".repeat(24);
+        let received: Vec<_> = Flex::text(0, FlexAddress::Group(0), TITLE, &fits)
+            .filter_map(|p| assembler.push(&p))
+            .collect();
+        assert_eq!(received[0].text, fits);
+
+        for len in [25, 37] {
+            let text = "a".repeat(len);
+            let received: Vec<_> = Flex::text(0, FlexAddress::Group(0), TITLE, &text)
+                .filter_map(|p| assembler.push(&p))
+                .collect();
+            assert!(received.is_empty());
+            assert!(assembler.pending.is_empty());
+        }
+    }
+
+    #[test]
+    fn ignores_orphaned_packets() {
+        let mut assembler = TextAssembler::new();
+        let packets: Vec<_> =
+            Flex::text(0, FlexAddress::Group(0), TITLE, "a long enough title").collect();
+        assert_eq!(assembler.push(&packets[1]), None);
+    }
+}
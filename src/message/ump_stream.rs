@@ -2,8 +2,10 @@
 use core::convert::TryInto;
 use core::ops::Deref;
 
+use crate::ci::MidiVersion;
 use crate::message::{data::DataFormat, Message};
 use crate::packet::{MessageType, Packet, Packet128};
+use crate::universal::DeviceIdentity;
 
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct UmpStream(pub(crate) Packet128);
@@ -33,7 +35,7 @@ impl Message for UmpStream {
     }
 
     fn status(&self) -> Self::Status {
-        ((self.0[0] >> 16) as u16).into()
+        ((self.0[0] >> 16) as u16 & 0x3ff).into()
     }
 
     fn data(&self) -> Self::Data {
@@ -48,10 +50,12 @@ impl UmpStream {
 
     pub fn format(&self) -> DataFormat {
         let bytes = self.0[0].to_be_bytes();
-        (bytes[0] & 0xF).into()
+        ((bytes[0] >> 2) & 0x3).into()
     }
 }
 
+/// The 10 bit status field of a UMP Stream message.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub enum Status {
     EndpointDiscovery,
     EndpointInfoNotification,
@@ -65,69 +69,144 @@ pub enum Status {
     FunctionBlockNameNotification,
     StartOfClip,
     EndOfClip,
-    Reserved,
+
+    /// A status not defined by the specification, holding the raw 10 bit value.
+    ///
+    /// Only the low 10 bits are used, and a `Reserved` holding the value of a defined status
+    /// converts to that status: build statuses from their value with `Status::from` to keep
+    /// conversions lossless.
+    Reserved(u16),
 }
 
 impl From<u16> for Status {
     fn from(value: u16) -> Self {
-        match value {
-            0x0 => Self::EndpointDiscovery,
-            0x1 => Self::EndpointInfoNotification,
-            0x2 => Self::DeviceIdentityNotification,
-            0x3 => Self::EndpointNameNotification,
-            0x4 => Self::ProductInstanceIdNotification,
-            0x5 => Self::StreamConfigurationRequest,
-            0x6 => Self::StreamConfigurationNotification,
+        match value & 0x3ff {
+            0x00 => Self::EndpointDiscovery,
+            0x01 => Self::EndpointInfoNotification,
+            0x02 => Self::DeviceIdentityNotification,
+            0x03 => Self::EndpointNameNotification,
+            0x04 => Self::ProductInstanceIdNotification,
+            0x05 => Self::StreamConfigurationRequest,
+            0x06 => Self::StreamConfigurationNotification,
             0x10 => Self::FunctionBlockDiscovery,
             0x11 => Self::FunctionBlockInfoNotification,
             0x12 => Self::FunctionBlockNameNotification,
             0x20 => Self::StartOfClip,
             0x21 => Self::EndOfClip,
-            _ => Self::Reserved,
+            value => Self::Reserved(value),
         }
     }
 }
 
-impl From<Status> for u8 {
-    fn from(value: Status) -> u8 {
+impl From<Status> for u16 {
+    fn from(value: Status) -> u16 {
         match value {
-            Status::EndpointDiscovery => 0x0,
-            Status::EndpointInfoNotification => 0x1,
-            Status::DeviceIdentityNotification => 0x2,
-            Status::EndpointNameNotification => 0x3,
-            Status::ProductInstanceIdNotification => 0x4,
-            Status::StreamConfigurationRequest => 0x5,
-            Status::StreamConfigurationNotification => 0x6,
+            Status::EndpointDiscovery => 0x00,
+            Status::EndpointInfoNotification => 0x01,
+            Status::DeviceIdentityNotification => 0x02,
+            Status::EndpointNameNotification => 0x03,
+            Status::ProductInstanceIdNotification => 0x04,
+            Status::StreamConfigurationRequest => 0x05,
+            Status::StreamConfigurationNotification => 0x06,
             Status::FunctionBlockDiscovery => 0x10,
             Status::FunctionBlockInfoNotification => 0x11,
             Status::FunctionBlockNameNotification => 0x12,
             Status::StartOfClip => 0x20,
             Status::EndOfClip => 0x21,
-            Status::Reserved => 0x22,
+            Status::Reserved(value) => value & 0x3ff,
         }
     }
 }
 
+/// Builds the first word of a UMP Stream message, leaving the status specific data zeroed.
+fn header(format: DataFormat, status: Status) -> u32 {
+    0xF000_0000 | (u8::from(format) as u32) << 26 | (u16::from(status) as u32 & 0x3ff) << 16
+}
+
 impl UmpStream {
     pub fn endpoint_discovery(data: EndpointDiscovery) -> Self {
-        let word0 = u32::from_be_bytes([
-            u8::from(Status::EndpointDiscovery) << 4,
-            0,
-            data.ump_major_version,
-            data.ump_minor_version,
-        ]);
-        let word1 = u32::from_be_bytes([0, 0, 0, data.filter_bitmap]);
-        let word2 = 0;
-        let word3 = 0;
+        let word0 = header(DataFormat::SinglePacket, Status::EndpointDiscovery)
+            | (data.ump_major_version as u32) << 8
+            | data.ump_minor_version as u32;
+        let word1 = data.filter_bitmap as u32;
+        Self::from_packet_unchecked(Packet([word0, word1, 0, 0]))
+    }
+
+    pub fn endpoint_info_notification(data: EndpointInfoNotification) -> Self {
+        let word0 = header(DataFormat::SinglePacket, Status::EndpointInfoNotification)
+            | (data.ump_major_version as u32) << 8
+            | data.ump_minor_version as u32;
+        let word1 = (data.static_function_blocks as u32) << 31
+            | (data.function_block_count as u32 & 0x7f) << 24
+            | (data.m2_support as u32) << 9
+            | (data.m1_support as u32) << 8
+            | (data.jr_receive_support as u32) << 1
+            | data.jr_transmit_support as u32;
+        Self::from_packet_unchecked(Packet([word0, word1, 0, 0]))
+    }
+
+    pub fn device_identity_notification(data: DeviceIdentityNotification) -> Self {
+        let word0 = header(DataFormat::SinglePacket, Status::DeviceIdentityNotification);
+        let [a, b, c] = data.manufacturer;
+        let word1 = u32::from_be_bytes([0, a, b, c]);
+        let word2 =
+            u32::from_be_bytes([data.family[0], data.family[1], data.model[0], data.model[1]]);
+        let word3 = u32::from_be_bytes(data.version);
         Self::from_packet_unchecked(Packet([word0, word1, word2, word3]))
     }
 
+    pub fn stream_configuration_request(data: StreamConfigurationRequest) -> Self {
+        let word0 = header(DataFormat::SinglePacket, Status::StreamConfigurationRequest)
+            | (data.protocol as u32) << 8
+            | (data.jr_receive as u32) << 1
+            | data.jr_transmit as u32;
+        Self::from_packet_unchecked(Packet([word0, 0, 0, 0]))
+    }
+
+    pub fn stream_configuration_notification(data: StreamConfigurationNotification) -> Self {
+        let word0 = header(
+            DataFormat::SinglePacket,
+            Status::StreamConfigurationNotification,
+        ) | (data.protocol as u32) << 8
+            | (data.jr_receive as u32) << 1
+            | data.jr_transmit as u32;
+        Self::from_packet_unchecked(Packet([word0, 0, 0, 0]))
+    }
+
     pub fn get_endpoint_discovery(&self) -> EndpointDiscovery {
-        todo!()
+        let bytes = self.0[0].to_be_bytes();
+        EndpointDiscovery {
+            ump_major_version: bytes[2],
+            ump_minor_version: bytes[3],
+            filter_bitmap: self.0[1].to_be_bytes()[3],
+        }
     }
 
     pub fn get_endpoint_info_notification(&self) -> EndpointInfoNotification {
-        todo!()
+        let bytes = self.0[0].to_be_bytes();
+        let word1 = self.0[1];
+        EndpointInfoNotification {
+            ump_major_version: bytes[2],
+            ump_minor_version: bytes[3],
+            static_function_blocks: word1 & 0x8000_0000 != 0,
+            function_block_count: ((word1 >> 24) & 0x7f) as u8,
+            m2_support: word1 & 0x0200 != 0,
+            m1_support: word1 & 0x0100 != 0,
+            jr_receive_support: word1 & 0x02 != 0,
+            jr_transmit_support: word1 & 0x01 != 0,
+        }
+    }
+
+    pub fn get_device_identity_notification(&self) -> DeviceIdentityNotification {
+        let bytes = |word: u32| word.to_be_bytes().map(|byte| byte & 0x7f);
+        let [_, a, b, c] = bytes(self.0[1]);
+        let [family0, family1, model0, model1] = bytes(self.0[2]);
+        DeviceIdentityNotification {
+            manufacturer: [a, b, c],
+            family: [family0, family1],
+            model: [model0, model1],
+            version: bytes(self.0[3]),
+        }
     }
 
     pub fn get_endpoint_name_notification(&self) -> EndpointNameIdentification {
@@ -139,11 +218,21 @@ impl UmpStream {
     }
 
     pub fn get_stream_configuration_request(&self) -> StreamConfigurationRequest {
-        todo!()
+        let bytes = self.0[0].to_be_bytes();
+        StreamConfigurationRequest {
+            protocol: bytes[2],
+            jr_receive: bytes[3] & 0x02 != 0,
+            jr_transmit: bytes[3] & 0x01 != 0,
+        }
     }
 
     pub fn get_stream_configuration_notification(&self) -> StreamConfigurationNotification {
-        todo!()
+        let bytes = self.0[0].to_be_bytes();
+        StreamConfigurationNotification {
+            protocol: bytes[2],
+            jr_receive: bytes[3] & 0x02 != 0,
+            jr_transmit: bytes[3] & 0x01 != 0,
+        }
     }
 
     pub fn get_function_block_discovery(&self) -> FunctionBlockDiscovery {
@@ -159,40 +248,121 @@ impl UmpStream {
     }
 }
 
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct EndpointDiscovery {
     pub ump_major_version: u8,
     pub ump_minor_version: u8,
     pub filter_bitmap: u8,
 }
 
+/// Describes the capabilities of an endpoint.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct EndpointInfoNotification {
     pub ump_major_version: u8,
     pub ump_minor_version: u8,
+
+    /// The function blocks of the endpoint never change.
+    pub static_function_blocks: bool,
+
+    /// Number of function blocks, at most 32.
     pub function_block_count: u8,
-    pub m1_support: u8,
-    pub m2_support: u8,
-    pub jitter_reduction_support: u8,
+
+    /// The endpoint supports the MIDI 1.0 protocol.
+    pub m1_support: bool,
+
+    /// The endpoint supports the MIDI 2.0 protocol.
+    pub m2_support: bool,
+
+    /// The endpoint can receive jitter reduction timestamps.
+    pub jr_receive_support: bool,
+
+    /// The endpoint can transmit jitter reduction timestamps.
+    pub jr_transmit_support: bool,
 }
 
+/// Reports the manufacturer, family, model and version of the device of an endpoint, as in
+/// an Identity Reply.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct DeviceIdentityNotification {
-    pub sysex_id: u8,
-    pub family_id: u8,
-    pub model_id: u8,
-    pub version_id: u8,
+    /// The manufacturer SysEx ID in its 3 byte form.
+    pub manufacturer: [u8; 3],
+    pub family: [u8; 2],
+    pub model: [u8; 2],
+    pub version: [u8; 4],
+}
+
+impl From<DeviceIdentity> for DeviceIdentityNotification {
+    fn from(value: DeviceIdentity) -> Self {
+        Self {
+            manufacturer: value.manufacturer,
+            family: value.family,
+            model: value.model,
+            version: value.version,
+        }
+    }
+}
+
+impl From<DeviceIdentityNotification> for DeviceIdentity {
+    fn from(value: DeviceIdentityNotification) -> Self {
+        Self {
+            manufacturer: value.manufacturer,
+            family: value.family,
+            model: value.model,
+            version: value.version,
+        }
+    }
 }
 
 pub struct EndpointNameIdentification(pub [u8; 14]);
 
 pub struct ProductInstanceIdNotification(pub [u8; 14]);
 
+/// Sent to an endpoint to request a protocol and jitter reduction setting.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct StreamConfigurationRequest {
+    /// `0x01` for MIDI 1.0, `0x02` for MIDI 2.0.
     pub protocol: u8,
-    pub jitter_reduction: u8,
+
+    /// Request that the endpoint receives jitter reduction timestamps.
+    pub jr_receive: bool,
+
+    /// Request that the endpoint transmits jitter reduction timestamps.
+    pub jr_transmit: bool,
+}
+
+impl StreamConfigurationRequest {
+    /// The requested protocol, if it is known.
+    pub fn midi_version(&self) -> Option<MidiVersion> {
+        midi_version(self.protocol)
+    }
 }
 
+/// Reports the current protocol and jitter reduction setting of an endpoint.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
 pub struct StreamConfigurationNotification {
+    /// `0x01` for MIDI 1.0, `0x02` for MIDI 2.0.
     pub protocol: u8,
-    pub jitter_reduction: u8,
+
+    /// The endpoint receives jitter reduction timestamps.
+    pub jr_receive: bool,
+
+    /// The endpoint transmits jitter reduction timestamps.
+    pub jr_transmit: bool,
+}
+
+impl StreamConfigurationNotification {
+    /// The current protocol, if it is known.
+    pub fn midi_version(&self) -> Option<MidiVersion> {
+        midi_version(self.protocol)
+    }
+}
+
+fn midi_version(protocol: u8) -> Option<MidiVersion> {
+    match protocol {
+        0x01 => Some(MidiVersion::Midi1),
+        0x02 => Some(MidiVersion::Midi2),
+        _ => None,
+    }
 }
 
 pub struct FunctionBlockDiscovery {
@@ -209,3 +379,269 @@ pub struct FunctionBlockNameNotification {
     pub function_block_count: u8,
     pub name_bytes: [u8; 12],
 }
+
+/// The protocol and jitter reduction settings agreed on for a UMP stream.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
+pub struct StreamConfiguration {
+    /// The protocol used for channel voice messages.
+    pub protocol: MidiVersion,
+
+    /// Jitter reduction timestamps are received by the endpoint.
+    pub jr_receive: bool,
+
+    /// Jitter reduction timestamps are transmitted by the endpoint.
+    pub jr_transmit: bool,
+}
+
+impl StreamConfiguration {
+    /// The message type that channel voice messages must be sent as.
+    pub fn channel_voice_type(&self) -> MessageType {
+        match self.protocol {
+            MidiVersion::Midi1 => MessageType::LegacyChannelVoice,
+            MidiVersion::Midi2 => MessageType::ChannelVoice,
+        }
+    }
+}
+
+impl From<StreamConfiguration> for StreamConfigurationRequest {
+    fn from(value: StreamConfiguration) -> Self {
+        Self {
+            protocol: value.protocol as u8,
+            jr_receive: value.jr_receive,
+            jr_transmit: value.jr_transmit,
+        }
+    }
+}
+
+impl From<StreamConfiguration> for StreamConfigurationNotification {
+    fn from(value: StreamConfiguration) -> Self {
+        Self {
+            protocol: value.protocol as u8,
+            jr_receive: value.jr_receive,
+            jr_transmit: value.jr_transmit,
+        }
+    }
+}
+
+/// Negotiates the protocol and jitter reduction settings of a UMP stream.
+///
+/// The responder side answers every Stream Configuration Request with a Stream Configuration
+/// Notification, granting only what the local endpoint supports. The initiator side sends a
+/// request built with [StreamConfigurationNegotiation::request] and adopts the configuration
+/// reported by the notification it receives.
+#[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
+pub struct StreamConfigurationNegotiation {
+    local: EndpointInfoNotification,
+    current: StreamConfiguration,
+}
+
+impl StreamConfigurationNegotiation {
+    /// Create a new negotiation from the capabilities of the local endpoint. The stream starts
+    /// out with the MIDI 2.0 protocol if it is supported, MIDI 1.0 otherwise, and no jitter reduction.
+    pub fn new(local: EndpointInfoNotification) -> Self {
+        debug_assert!(
+            local.m1_support || local.m2_support,
+            "Endpoints must support at least one protocol."
+        );
+        let protocol = if local.m2_support {
+            MidiVersion::Midi2
+        } else {
+            MidiVersion::Midi1
+        };
+        Self {
+            local,
+            current: StreamConfiguration {
+                protocol,
+                jr_receive: false,
+                jr_transmit: false,
+            },
+        }
+    }
+
+    /// The capabilities of the local endpoint.
+    pub fn local(&self) -> &EndpointInfoNotification {
+        &self.local
+    }
+
+    /// The currently agreed configuration.
+    pub fn configuration(&self) -> StreamConfiguration {
+        self.current
+    }
+
+    /// Returns true if the local endpoint supports a protocol.
+    pub fn supports(&self, protocol: MidiVersion) -> bool {
+        match protocol {
+            MidiVersion::Midi1 => self.local.m1_support,
+            MidiVersion::Midi2 => self.local.m2_support,
+        }
+    }
+
+    /// Create a Stream Configuration Request asking the remote endpoint for a configuration.
+    pub fn request(&self, desired: StreamConfiguration) -> UmpStream {
+        UmpStream::stream_configuration_request(desired.into())
+    }
+
+    /// Create a Stream Configuration Notification reporting the current configuration.
+    pub fn notification(&self) -> UmpStream {
+        UmpStream::stream_configuration_notification(self.current.into())
+    }
+
+    /// Resolve a request against the local capabilities. Unsupported or unknown protocols
+    /// leave the current protocol unchanged, and jitter reduction is only enabled when supported.
+    pub fn resolve(&self, request: StreamConfigurationRequest) -> StreamConfiguration {
+        let protocol = request
+            .midi_version()
+            .filter(|protocol| self.supports(*protocol))
+            .unwrap_or(self.current.protocol);
+        StreamConfiguration {
+            protocol,
+            jr_receive: request.jr_receive && self.local.jr_receive_support,
+            jr_transmit: request.jr_transmit && self.local.jr_transmit_support,
+        }
+    }
+
+    /// Process an incoming UMP Stream message, returning the message to send in reply, if any.
+    pub fn process(&mut self, message: &UmpStream) -> Option<UmpStream> {
+        match message.status() {
+            Status::StreamConfigurationRequest => {
+                self.current = self.resolve(message.get_stream_configuration_request());
+                Some(self.notification())
+            }
+            Status::StreamConfigurationNotification => {
+                let notification = message.get_stream_configuration_notification();
+                if let Some(protocol) = notification.midi_version() {
+                    self.current = StreamConfiguration {
+                        protocol,
+                        jr_receive: notification.jr_receive,
+                        jr_transmit: notification.jr_transmit,
+                    };
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(m1_support: bool, m2_support: bool, jr: bool) -> EndpointInfoNotification {
+        EndpointInfoNotification {
+            ump_major_version: 1,
+            ump_minor_version: 1,
+            static_function_blocks: true,
+            function_block_count: 1,
+            m1_support,
+            m2_support,
+            jr_receive_support: jr,
+            jr_transmit_support: jr,
+        }
+    }
+
+    #[test]
+    fn status_round_trip() {
+        for value in 0..0x400_u16 {
+            let status = Status::from(value);
+            assert_eq!(value, u16::from(status));
+            assert_eq!(status, Status::from(u16::from(status)));
+            assert_eq!(status, Status::from(u16::from(Status::Reserved(value))));
+            let message = UmpStream::from_packet_unchecked(Packet([
+                header(DataFormat::End, status),
+                0,
+                0,
+                0,
+            ]));
+            assert_eq!(status, message.status());
+            assert_eq!(DataFormat::End, message.format());
+        }
+    }
+
+    #[test]
+    fn status_reserved() {
+        assert_eq!(Status::Reserved(0x07), 0x07.into());
+        assert_eq!(Status::Reserved(0x22), 0x22.into());
+        assert_eq!(Status::Reserved(0x3ff), 0x3ff.into());
+        assert_eq!(Status::StartOfClip, 0x20.into());
+        assert_eq!(u16::from(Status::Reserved(0x422)), 0x22);
+        assert_eq!(Status::from(0x420), Status::StartOfClip);
+    }
+
+    #[test]
+    fn endpoint_info_round_trip() {
+        let info = endpoint(true, true, true);
+        let message = UmpStream::endpoint_info_notification(info);
+        assert_eq!(message.0[0], 0xF001_0101);
+        assert_eq!(message.0[1], 0x8100_0303);
+        assert_eq!(message.format(), DataFormat::SinglePacket);
+        assert_eq!(info, message.get_endpoint_info_notification());
+    }
+
+    #[test]
+    fn stream_configuration_round_trip() {
+        let request = StreamConfigurationRequest {
+            protocol: 0x02,
+            jr_receive: true,
+            jr_transmit: false,
+        };
+        let message = UmpStream::stream_configuration_request(request);
+        assert_eq!(message.0[0], 0xF005_0202);
+        assert_eq!(request, message.get_stream_configuration_request());
+    }
+
+    #[test]
+    fn negotiation_grants_supported_configuration() {
+        let mut responder = StreamConfigurationNegotiation::new(endpoint(true, true, false));
+        let initiator = StreamConfigurationNegotiation::new(endpoint(true, true, true));
+        let request = initiator.request(StreamConfiguration {
+            protocol: MidiVersion::Midi1,
+            jr_receive: true,
+            jr_transmit: true,
+        });
+
+        let reply = responder.process(&request).unwrap();
+        let expected = StreamConfiguration {
+            protocol: MidiVersion::Midi1,
+            jr_receive: false,
+            jr_transmit: false,
+        };
+        assert_eq!(responder.configuration(), expected);
+        assert_eq!(
+            responder.configuration().channel_voice_type(),
+            MessageType::LegacyChannelVoice
+        );
+        assert_eq!(
+            reply.get_stream_configuration_notification(),
+            expected.into()
+        );
+    }
+
+    #[test]
+    fn negotiation_keeps_protocol_when_unsupported() {
+        let mut responder = StreamConfigurationNegotiation::new(endpoint(false, true, false));
+        let request = UmpStream::stream_configuration_request(StreamConfigurationRequest {
+            protocol: 0x01,
+            jr_receive: false,
+            jr_transmit: false,
+        });
+        responder.process(&request);
+        assert_eq!(responder.configuration().protocol, MidiVersion::Midi2);
+    }
+
+    #[test]
+    fn initiator_adopts_notification() {
+        let mut responder = StreamConfigurationNegotiation::new(endpoint(true, false, true));
+        let mut initiator = StreamConfigurationNegotiation::new(endpoint(true, true, true));
+        let reply = responder
+            .process(&initiator.request(StreamConfiguration {
+                protocol: MidiVersion::Midi2,
+                jr_receive: true,
+                jr_transmit: true,
+            }))
+            .unwrap();
+        assert_eq!(initiator.process(&reply), None);
+        assert_eq!(initiator.configuration(), responder.configuration());
+        assert_eq!(initiator.configuration().protocol, MidiVersion::Midi1);
+    }
+}
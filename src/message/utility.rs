@@ -11,22 +11,50 @@ pub struct Utility(pub(crate) Packet32);
 
 /// Represents the status byte of a [Utility] message.
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq)]
-#[repr(u8)]
 pub enum UtilityStatus {
     /// The NoOp message. Data bits must be zeroed.
-    NoOp = 0,
-
-    /// A Jitter reduction timestamp message.
-    JrTimestamp = 1,
+    NoOp,
 
     /// A Jitter reduction clock message.
-    JrClock = 2,
+    JrClock,
+
+    /// A Jitter reduction timestamp message.
+    JrTimestamp,
 
     /// Declares the unit of mesaure used by [UtilityStatus::DeltaClockstamp] messages.
-    DataClockstampTicksPerQuarternote = 3,
+    DataClockstampTicksPerQuarternote,
 
     /// Declares the time of all following messages which occur before the next delta clockstamp message.
-    DeltaClockstamp = 4,
+    DeltaClockstamp,
+
+    /// A status not defined by the specification, holding the raw 4 bit value.
+    Reserved(u8),
+}
+
+impl From<u8> for UtilityStatus {
+    fn from(value: u8) -> Self {
+        match value & 0xf {
+            0 => Self::NoOp,
+            1 => Self::JrClock,
+            2 => Self::JrTimestamp,
+            3 => Self::DataClockstampTicksPerQuarternote,
+            4 => Self::DeltaClockstamp,
+            value => Self::Reserved(value),
+        }
+    }
+}
+
+impl From<UtilityStatus> for u8 {
+    fn from(value: UtilityStatus) -> u8 {
+        match value {
+            UtilityStatus::NoOp => 0,
+            UtilityStatus::JrClock => 1,
+            UtilityStatus::JrTimestamp => 2,
+            UtilityStatus::DataClockstampTicksPerQuarternote => 3,
+            UtilityStatus::DeltaClockstamp => 4,
+            UtilityStatus::Reserved(value) => value & 0xf,
+        }
+    }
 }
 
 impl Utility {
@@ -41,17 +69,23 @@ impl Utility {
 
     /// Create a new jitter reduction clock message.
     pub fn jr_clock(clock: u16) -> Self {
-        Self::from_packet_unchecked(Packet([0x0020_0000 | (clock as u32)]))
+        Self::from_packet_unchecked(Packet([0x0010_0000 | (clock as u32)]))
     }
 
-    /// Create a new delta clockstamp message.
-    pub fn delta_clockstamp(clock: u16) -> Self {
-        Self::from_packet_unchecked(Packet([0x0020_0000 | (clock as u32)]))
+    /// Create a new delta clockstamp message, declaring the number of ticks since the last event.
+    pub fn delta_clockstamp(ticks: u32) -> Self {
+        debug_assert!(ticks < (1 << 20), "Delta clockstamps are 20 bits.");
+        Self::from_packet_unchecked(Packet([0x0040_0000 | (ticks & 0x000f_ffff)]))
     }
 
     /// Create a new delta clockstamp message in ticks per quarternote.
-    pub fn delta_clockstamp_ticks_per_quarternote(clock: u16) -> Self {
-        Self::from_packet_unchecked(Packet([0x0020_0000 | (clock as u32)]))
+    pub fn delta_clockstamp_ticks_per_quarternote(ticks_per_quarter: u16) -> Self {
+        Self::from_packet_unchecked(Packet([0x0030_0000 | (ticks_per_quarter as u32)]))
+    }
+
+    /// The number of ticks since the last event. Note: the status must be [UtilityStatus::DeltaClockstamp].
+    pub fn delta_clockstamp_ticks(&self) -> u32 {
+        self.0[0] & 0x000f_ffff
     }
 }
 
@@ -77,15 +111,7 @@ impl Message for Utility {
     }
 
     fn status(&self) -> Self::Status {
-        let status = self.0.status();
-        match status {
-            0 => Self::Status::NoOp,
-            1 => Self::Status::JrTimestamp,
-            2 => Self::Status::JrClock,
-            3 => Self::Status::DataClockstampTicksPerQuarternote,
-            4 => Self::Status::DeltaClockstamp,
-            _ => unreachable!("Invalid status byte for utility message."),
-        }
+        (self.0.status() >> 4).into()
     }
 
     fn data(&self) -> u16 {
@@ -98,3 +124,22 @@ impl From<Utility> for Packet32 {
         value.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Data;
+
+    #[test]
+    fn reserved_statuses() {
+        for value in 0..16 {
+            assert_eq!(u8::from(UtilityStatus::from(value)), value);
+        }
+        let Data::Utility(utility) = Data::from_words(core::iter::once(0x0050_0000)).unwrap()
+        else {
+            panic!("not a utility message");
+        };
+        assert_eq!(utility.status(), UtilityStatus::Reserved(5));
+        assert_eq!(Utility::jr_clock(0).status(), UtilityStatus::JrClock);
+    }
+}
@@ -1,40 +1,169 @@
-//! Helpers for generating and managing MUIDs, unique 32 bit identifiers for a device. Only
-//! available if the `no-std` feature is not specified. Otherwise one should fallback to
-//! the recommendations of the specification.
+//! Helpers for generating and managing MUIDs, 28 bit identifiers for a MIDI-CI device.
+//!
+//! A MUID should be picked at random from [MUID::random] whenever a device is connected, and
+//! changed if another device turns out to use it. [new_muid] generates one from the standard
+//! library's randomness, and is only available if the `no-std` feature is not specified.
+use core::convert::TryFrom;
+use core::fmt;
 
 /// Represents braodcast messages, those intended to reach all devices
 pub const BROADCAST: MUID = MUID(0x0fff_ffff);
 
+/// The first of the MUIDs reserved for future use, up to [BROADCAST].
+pub const RESERVED: u32 = 0x0fff_ff00;
+
 /// Manufacturer Unique Identifiers (MUIDs).
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct MUID(u32);
 
+/// A source of random numbers to generate MUIDs, like `rand::RngCore`.
+///
+/// Implemented for closures returning `u32`, so that any generator or entropy source a
+/// device has can be used, eg `MUID::random(&mut || rng.next_u32())`.
+pub trait RandomSource {
+    /// Return the next random number.
+    fn next_u32(&mut self) -> u32;
+}
+
+impl<F: FnMut() -> u32> RandomSource for F {
+    fn next_u32(&mut self) -> u32 {
+        self()
+    }
+}
+
+/// The error converting a value that is not a valid MUID, see [MUID::try_from].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct InvalidMuid(pub u32);
+
+impl fmt::Display for InvalidMuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid MUID: {:#x}", self.0)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl std::error::Error for InvalidMuid {}
+
 impl MUID {
-    /// Convert the MUID to bytes.
+    /// Pick a MUID at random, excluding broadcast and reserved values.
+    pub fn random<R: RandomSource + ?Sized>(rng: &mut R) -> Self {
+        loop {
+            let id = rng.next_u32() & BROADCAST.0;
+            if id < RESERVED {
+                return MUID(id);
+            }
+        }
+    }
+
+    /// The MUID as a 28 bit number.
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if this is the [BROADCAST] MUID.
+    pub fn is_broadcast(&self) -> bool {
+        *self == BROADCAST
+    }
+
+    /// Convert the MUID to its SysEx form, see [MUID::to_wire].
     pub fn to_bytes(&self) -> [u8; 4] {
-        self.0.to_ne_bytes()
+        self.to_wire()
+    }
+
+    /// Decode a MUID from its SysEx form: four 7-bit bytes, least significant first. The high
+    /// bit of each byte is ignored.
+    pub fn from_wire(bytes: [u8; 4]) -> Self {
+        let id = bytes
+            .iter()
+            .rev()
+            .fold(0, |id, byte| (id << 7) | (*byte & 0x7f) as u32);
+        MUID(id)
+    }
+
+    /// Encode a MUID in its SysEx form: four 7-bit bytes, least significant first.
+    pub fn to_wire(self) -> [u8; 4] {
+        let mut bytes = [0; 4];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = ((self.0 >> (7 * i)) & 0x7f) as u8;
+        }
+        bytes
     }
 }
 
-/// Generates a MUID by hashing the number of milliseconds since UNIX_EPOCH, truncated
-/// to 32 bits. This method takes _at least_ one millisecond to complete by calling
-/// thread::sleep, which is intended to provide unique IDs in the case that many
-/// are generated in a loop.
+impl TryFrom<u32> for MUID {
+    type Error = InvalidMuid;
+
+    /// Accepts 28 bit values that are not reserved, and [BROADCAST].
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        if value < RESERVED || value == BROADCAST.0 {
+            Ok(MUID(value))
+        } else {
+            Err(InvalidMuid(value))
+        }
+    }
+}
+
+impl From<MUID> for u32 {
+    fn from(muid: MUID) -> u32 {
+        muid.0
+    }
+}
+
+/// A [RandomSource] seeded by the standard library's per-process random keys, for devices
+/// without a random number generator of their own. Only available if the `no-std` feature is
+/// not specified.
+#[cfg(not(feature = "no-std"))]
+pub fn std_random() -> impl RandomSource {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let state = RandomState::new();
+    let mut counter = 0_u64;
+    move || {
+        let mut hasher = state.build_hasher();
+        hasher.write_u64(counter);
+        counter += 1;
+        hasher.finish() as u32
+    }
+}
+
+/// Generates a random MUID from [std_random].
 #[cfg(not(feature = "no-std"))]
 pub fn new_muid() -> MUID {
-    use std::thread;
-    use std::time;
-    thread::sleep(time::Duration::from_millis(1));
-    let mut id = BROADCAST.0;
-    while id >= 0x0fff_fff0 {
-        let now = (time::SystemTime::now()
-            .duration_since(time::SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-            & 0xffff_ffff) as u32;
-        id = ((now >> 16) ^ now).wrapping_mul(0x119d_e1f3);
-        id = ((id >> 16) ^ id).wrapping_mul(0x119d_e1f3);
-        id = id ^ (id >> 16);
-    }
-    MUID(id)
+    MUID::random(&mut std_random())
+}
+
+/// A MUID from a number masked to 28 bits, for tests.
+#[cfg(test)]
+pub(crate) fn muid(id: u32) -> MUID {
+    MUID(id & BROADCAST.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_skips_reserved() {
+        let mut values = [0xffff_ffff, 0x0fff_ff00, 0x1234_5678].iter().copied();
+        let muid = MUID::random(&mut || values.next().unwrap());
+        assert_eq!(muid.value(), 0x0234_5678);
+        assert_ne!(new_muid(), new_muid());
+    }
+
+    #[test]
+    fn try_from_u32() {
+        assert_eq!(MUID::try_from(0x0234_5678).map(u32::from), Ok(0x0234_5678));
+        assert_eq!(MUID::try_from(0x0fff_ffff), Ok(BROADCAST));
+        assert_eq!(MUID::try_from(0x0fff_ff10), Err(InvalidMuid(0x0fff_ff10)));
+        assert_eq!(MUID::try_from(0x1000_0000), Err(InvalidMuid(0x1000_0000)));
+    }
+
+    #[test]
+    fn wire_form() {
+        let muid = MUID::try_from(0x0234_5678).unwrap();
+        assert_eq!(muid.to_wire(), [0x78, 0x2c, 0x51, 0x11]);
+        assert_eq!(muid.to_bytes(), muid.to_wire());
+        assert_eq!(MUID::from_wire(muid.to_wire()), muid);
+    }
 }
@@ -0,0 +1,405 @@
+//! Tempo maps, for converting between musical time (ticks, bars and beats) and real time
+//! (seconds and samples) using Set Tempo and Set Time Signature Flex Data messages.
+use core::fmt;
+
+use crate::message::flex::FlexTimeSignature;
+
+#[cfg(not(feature = "no-std"))]
+pub mod metronome;
+
+/// A position in musical time. All fields count from zero, but are displayed counting
+/// bars and beats from one, eg `1|1|000` for the start of a song.
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct BarsBeatsTicks {
+    /// The bar number.
+    pub bar: u64,
+
+    /// The beat within the bar, in units of the time signature denominator.
+    pub beat: u64,
+
+    /// The tick within the beat.
+    pub tick: u64,
+}
+
+impl fmt::Display for BarsBeatsTicks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}|{}|{:03}", self.bar + 1, self.beat + 1, self.tick)
+    }
+}
+
+/// The number of ticks in a beat of a time signature, whose denominator is a power of two, eg 0
+/// for whole notes and 2 for quarter notes. Beats are at least one tick long.
+pub fn ticks_per_beat(ticks_per_quarter: u16, time_signature: FlexTimeSignature) -> u64 {
+    let ticks_per_whole = 4 * ticks_per_quarter as u64;
+    (ticks_per_whole >> time_signature.denominator.min(63)).max(1)
+}
+
+/// The number of ticks in a bar of a time signature.
+pub fn ticks_per_bar(ticks_per_quarter: u16, time_signature: FlexTimeSignature) -> u64 {
+    ticks_per_beat(ticks_per_quarter, time_signature) * time_signature.numerator.max(1) as u64
+}
+
+#[cfg(not(feature = "no-std"))]
+pub use self::map::TempoMap;
+
+#[cfg(not(feature = "no-std"))]
+mod map {
+    use super::*;
+    use crate::message::flex::{FlexSetupAndPerformance, FlexTempo};
+    use crate::message::utility::UtilityStatus;
+    use crate::message::{flex::FlexStatus, Data, Message};
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct TempoChange {
+        tick: u64,
+        seconds: f64,
+        tempo: FlexTempo,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct TimeSignatureChange {
+        tick: u64,
+        bar: u64,
+        time_signature: FlexTimeSignature,
+    }
+
+    /// A map of tempo and time signature changes over time.
+    ///
+    /// The map starts at 120 BPM in 4/4. Changes are kept sorted by tick, along with the time
+    /// and bar they occur at, so conversions are a binary search followed by a linear step.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TempoMap {
+        ticks_per_quarter: u16,
+        tempos: Vec<TempoChange>,
+        time_signatures: Vec<TimeSignatureChange>,
+        position: u64,
+    }
+
+    impl Default for TempoMap {
+        fn default() -> Self {
+            Self::new(480)
+        }
+    }
+
+    impl TempoMap {
+        /// Create a new tempo map with a resolution in ticks per quarter note.
+        pub fn new(ticks_per_quarter: u16) -> Self {
+            debug_assert!(ticks_per_quarter > 0, "Ticks per quarter must be positive.");
+            Self {
+                ticks_per_quarter,
+                tempos: vec![TempoChange {
+                    tick: 0,
+                    seconds: 0.0,
+                    tempo: FlexTempo::from_bpm(120.0),
+                }],
+                time_signatures: vec![TimeSignatureChange {
+                    tick: 0,
+                    bar: 0,
+                    time_signature: FlexTimeSignature {
+                        numerator: 4,
+                        denominator: 2,
+                        number_of_32n: 8,
+                    },
+                }],
+                position: 0,
+            }
+        }
+
+        /// The resolution of the map in ticks per quarter note.
+        pub fn ticks_per_quarter(&self) -> u16 {
+            self.ticks_per_quarter
+        }
+
+        /// Change the resolution of the map. Existing changes keep their tick positions.
+        pub fn set_ticks_per_quarter(&mut self, ticks_per_quarter: u16) {
+            debug_assert!(ticks_per_quarter > 0, "Ticks per quarter must be positive.");
+            self.ticks_per_quarter = ticks_per_quarter;
+            self.update_tempos(0);
+            self.update_time_signatures(0);
+        }
+
+        /// The position in ticks that the next message passed to [TempoMap::push] applies to.
+        pub fn position(&self) -> u64 {
+            self.position
+        }
+
+        /// Move the position used by [TempoMap::push].
+        pub fn set_position(&mut self, tick: u64) {
+            self.position = tick;
+        }
+
+        /// Add a message to the map. Delta Clockstamps advance the position, Delta Clockstamp
+        /// Ticks Per Quarter Note messages set the resolution, and Set Tempo and Set Time Signature
+        /// messages add a change at the current position. Other messages, and tempos of 0, are
+        /// ignored.
+        pub fn push(&mut self, message: &Data) {
+            match message {
+                Data::Utility(utility) => match utility.status() {
+                    UtilityStatus::DeltaClockstamp => {
+                        self.position += utility.delta_clockstamp_ticks() as u64;
+                    }
+                    UtilityStatus::DataClockstampTicksPerQuarternote if utility.data() > 0 => {
+                        self.set_ticks_per_quarter(utility.data());
+                    }
+                    _ => (),
+                },
+                Data::Flex(flex) => match flex.status() {
+                    FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTempo)
+                        if flex.tempo() > 0 =>
+                    {
+                        self.insert_tempo(self.position, FlexTempo(flex.tempo()));
+                    }
+                    FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTimeSignature) => {
+                        self.insert_time_signature(self.position, flex.time_signature());
+                    }
+                    _ => (),
+                },
+                _ => (),
+            }
+        }
+
+        /// Set the tempo from a tick onwards, replacing any tempo change at the same tick.
+        pub fn insert_tempo(&mut self, tick: u64, tempo: FlexTempo) {
+            debug_assert!(tempo.0 > 0, "Tempo must be positive.");
+            let index = self.tempos.partition_point(|c| c.tick < tick);
+            let change = TempoChange {
+                tick,
+                seconds: 0.0,
+                tempo,
+            };
+            match self.tempos.get_mut(index) {
+                Some(existing) if existing.tick == tick => *existing = change,
+                _ => self.tempos.insert(index, change),
+            }
+            self.update_tempos(index);
+        }
+
+        /// Set the time signature from a tick onwards, replacing any change at the same tick.
+        /// A change in the middle of a bar starts a new bar.
+        pub fn insert_time_signature(&mut self, tick: u64, time_signature: FlexTimeSignature) {
+            let index = self.time_signatures.partition_point(|c| c.tick < tick);
+            let change = TimeSignatureChange {
+                tick,
+                bar: 0,
+                time_signature,
+            };
+            match self.time_signatures.get_mut(index) {
+                Some(existing) if existing.tick == tick => *existing = change,
+                _ => self.time_signatures.insert(index, change),
+            }
+            self.update_time_signatures(index);
+        }
+
+        /// The tempo at a tick.
+        pub fn tempo_at(&self, tick: u64) -> FlexTempo {
+            self.tempo_change_at(tick).tempo
+        }
+
+        /// The time signature at a tick.
+        pub fn time_signature_at(&self, tick: u64) -> FlexTimeSignature {
+            self.time_signature_change_at(tick).time_signature
+        }
+
+        /// Convert a position in ticks to seconds.
+        pub fn ticks_to_seconds(&self, tick: u64) -> f64 {
+            let change = self.tempo_change_at(tick);
+            change.seconds + (tick - change.tick) as f64 * self.seconds_per_tick(change.tempo)
+        }
+
+        /// Convert a time in seconds to a (fractional) position in ticks.
+        pub fn seconds_to_ticks(&self, seconds: f64) -> f64 {
+            let seconds = seconds.max(0.0);
+            let index = self.tempos.partition_point(|c| c.seconds <= seconds);
+            let change = &self.tempos[index.saturating_sub(1)];
+            change.tick as f64 + (seconds - change.seconds) / self.seconds_per_tick(change.tempo)
+        }
+
+        /// Convert a position in ticks to the nearest sample at a sample rate.
+        pub fn ticks_to_samples(&self, tick: u64, sample_rate: f64) -> u64 {
+            (self.ticks_to_seconds(tick) * sample_rate).round() as u64
+        }
+
+        /// Convert a sample at a sample rate to a (fractional) position in ticks.
+        pub fn samples_to_ticks(&self, sample: u64, sample_rate: f64) -> f64 {
+            self.seconds_to_ticks(sample as f64 / sample_rate)
+        }
+
+        /// Convert a position in ticks to bars, beats and ticks.
+        pub fn ticks_to_bbt(&self, tick: u64) -> BarsBeatsTicks {
+            let change = self.time_signature_change_at(tick);
+            let per_beat = ticks_per_beat(self.ticks_per_quarter, change.time_signature);
+            let per_bar = ticks_per_bar(self.ticks_per_quarter, change.time_signature);
+            let elapsed = tick - change.tick;
+            let in_bar = elapsed % per_bar;
+            BarsBeatsTicks {
+                bar: change.bar + elapsed / per_bar,
+                beat: in_bar / per_beat,
+                tick: in_bar % per_beat,
+            }
+        }
+
+        /// Convert bars, beats and ticks to a position in ticks.
+        pub fn bbt_to_ticks(&self, bbt: BarsBeatsTicks) -> u64 {
+            let index = self.time_signatures.partition_point(|c| c.bar <= bbt.bar);
+            let change = &self.time_signatures[index.saturating_sub(1)];
+            let per_beat = ticks_per_beat(self.ticks_per_quarter, change.time_signature);
+            let per_bar = ticks_per_bar(self.ticks_per_quarter, change.time_signature);
+            change.tick + (bbt.bar - change.bar) * per_bar + bbt.beat * per_beat + bbt.tick
+        }
+
+        fn seconds_per_tick(&self, tempo: FlexTempo) -> f64 {
+            tempo.seconds_per_quarter() / self.ticks_per_quarter as f64
+        }
+
+        fn tempo_change_at(&self, tick: u64) -> &TempoChange {
+            let index = self.tempos.partition_point(|c| c.tick <= tick);
+            &self.tempos[index.saturating_sub(1)]
+        }
+
+        fn time_signature_change_at(&self, tick: u64) -> &TimeSignatureChange {
+            let index = self.time_signatures.partition_point(|c| c.tick <= tick);
+            &self.time_signatures[index.saturating_sub(1)]
+        }
+
+        /// Recompute the time of each tempo change from an index onwards.
+        fn update_tempos(&mut self, from: usize) {
+            for i in from.max(1)..self.tempos.len() {
+                let previous = self.tempos[i - 1];
+                let elapsed = (self.tempos[i].tick - previous.tick) as f64;
+                self.tempos[i].seconds =
+                    previous.seconds + elapsed * self.seconds_per_tick(previous.tempo);
+            }
+        }
+
+        /// Recompute the bar of each time signature change from an index onwards.
+        fn update_time_signatures(&mut self, from: usize) {
+            for i in from.max(1)..self.time_signatures.len() {
+                let previous = self.time_signatures[i - 1];
+                let per_bar = ticks_per_bar(self.ticks_per_quarter, previous.time_signature);
+                let elapsed = self.time_signatures[i].tick - previous.tick;
+                self.time_signatures[i].bar = previous.bar + elapsed.div_ceil(per_bar);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "no-std")))]
+mod tests {
+    use super::*;
+    use crate::message::{
+        data::DataFormat,
+        flex::{Flex, FlexAddress, FlexTempo},
+        utility::Utility,
+        Data,
+    };
+
+    fn time_signature(numerator: u8, denominator: u8) -> FlexTimeSignature {
+        FlexTimeSignature {
+            numerator,
+            denominator,
+            number_of_32n: 8,
+        }
+    }
+
+    #[test]
+    fn seconds_across_tempo_changes() {
+        let mut map = TempoMap::new(480);
+        map.insert_tempo(960, FlexTempo::from_bpm(60.0));
+        assert_eq!(map.ticks_to_seconds(480), 0.5);
+        assert_eq!(map.ticks_to_seconds(960), 1.0);
+        assert_eq!(map.ticks_to_seconds(1440), 2.0);
+        assert_eq!(map.seconds_to_ticks(0.25), 240.0);
+        assert_eq!(map.seconds_to_ticks(2.0), 1440.0);
+        assert_eq!(map.ticks_to_samples(1440, 48_000.0), 96_000);
+        assert_eq!(map.samples_to_ticks(72_000, 48_000.0), 1200.0);
+
+        // Inserting an earlier change moves the later ones.
+        map.insert_tempo(480, FlexTempo::from_bpm(240.0));
+        assert_eq!(map.ticks_to_seconds(960), 0.75);
+        assert_eq!(map.ticks_to_seconds(1440), 1.75);
+        assert_eq!(map.seconds_to_ticks(1.75), 1440.0);
+    }
+
+    #[test]
+    fn bars_beats_ticks() {
+        let mut map = TempoMap::new(480);
+        // Two bars of 4/4, then 6/8 starting halfway through the third bar.
+        map.insert_time_signature(2 * 1920 + 960, time_signature(6, 3));
+        let change = 2 * 1920 + 960;
+
+        assert_eq!(
+            map.ticks_to_bbt(1920 + 480 + 7),
+            BarsBeatsTicks {
+                bar: 1,
+                beat: 1,
+                tick: 7
+            }
+        );
+        assert_eq!(map.ticks_to_bbt(change).bar, 3);
+        let bbt = map.ticks_to_bbt(change + 1440 + 240 * 4 + 5);
+        assert_eq!(
+            bbt,
+            BarsBeatsTicks {
+                bar: 4,
+                beat: 4,
+                tick: 5
+            }
+        );
+        assert_eq!(bbt.to_string(), "5|5|005");
+        for tick in (0..20_000).step_by(37) {
+            assert_eq!(map.bbt_to_ticks(map.ticks_to_bbt(tick)), tick);
+        }
+
+        // A denominator of 0 is a whole note.
+        assert_eq!(ticks_per_beat(480, time_signature(3, 0)), 1920);
+        assert_eq!(ticks_per_bar(480, time_signature(3, 0)), 5760);
+        assert_eq!(ticks_per_beat(1, time_signature(1, 5)), 1);
+    }
+
+    #[test]
+    fn ingests_messages() {
+        let mut map = TempoMap::default();
+        let messages = [
+            Data::Utility(Utility::delta_clockstamp_ticks_per_quarternote(96)),
+            Data::Utility(Utility::delta_clockstamp(192)),
+            Data::Flex(Flex::set_tempo(
+                0,
+                FlexAddress::Group(0),
+                DataFormat::SinglePacket,
+                FlexTempo::from_bpm(60.0),
+            )),
+            Data::Flex(Flex::set_time_signature(
+                0,
+                FlexAddress::Group(0),
+                DataFormat::SinglePacket,
+                time_signature(3, 2),
+            )),
+        ];
+        for message in &messages {
+            map.push(message);
+        }
+        assert_eq!(map.ticks_per_quarter(), 96);
+        assert_eq!(map.position(), 192);
+        assert_eq!(map.tempo_at(191), FlexTempo::from_bpm(120.0));
+        assert_eq!(map.tempo_at(192), FlexTempo::from_bpm(60.0));
+        assert_eq!(map.ticks_to_seconds(288), 2.0);
+        assert_eq!(map.time_signature_at(192), time_signature(3, 2));
+        assert_eq!(map.ticks_to_bbt(192 + 288).bar, 2);
+    }
+
+    #[test]
+    fn ignores_reserved_messages() {
+        let mut map = TempoMap::default();
+        let expected = map.clone();
+        for status in 5..16 {
+            map.push(&Data::from_words(core::iter::once(status << 20)).unwrap());
+        }
+        map.push(&Data::Flex(Flex::set_tempo(
+            0,
+            FlexAddress::Group(0),
+            DataFormat::SinglePacket,
+            FlexTempo(0),
+        )));
+        assert_eq!(map, expected);
+    }
+}
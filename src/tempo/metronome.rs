@@ -0,0 +1,375 @@
+//! A sample accurate click track driven by Set Metronome, Set Tempo and Set Time Signature
+//! Flex Data messages.
+use crate::message::flex::{
+    Flex, FlexMetronome, FlexSetupAndPerformance, FlexStatus, FlexTempo, FlexTimeSignature,
+};
+use crate::message::Message;
+
+/// MIDI clocks in a quarter note.
+const CLOCKS_PER_QUARTER: f64 = 24.0;
+
+/// Tolerance when comparing positions in MIDI clocks.
+const EPSILON: f64 = 1e-9;
+
+/// The sound of a metronome click.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum ClickKind {
+    /// A primary click starting a group of [FlexMetronome::bar_accents].
+    Accent,
+
+    /// A primary click.
+    Primary,
+
+    /// A click from the first subdivision field.
+    FirstSubdivision,
+
+    /// A click from the second subdivision field.
+    SecondSubdivision,
+}
+
+/// A click to be played within a block of audio.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Click {
+    /// The offset of the click from the start of the block, in frames.
+    pub frame: usize,
+
+    /// The sound of the click.
+    pub kind: ClickKind,
+}
+
+/// Schedules metronome clicks for blocks of audio.
+///
+/// Bars restart whenever the time signature changes, and tempo changes take effect from the
+/// current position without skipping or repeating clicks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Metronome {
+    sample_rate: f64,
+    metronome: FlexMetronome,
+    tempo: FlexTempo,
+    time_signature: FlexTimeSignature,
+
+    /// The absolute sample of the start of the next block.
+    sample: u64,
+
+    /// The absolute (fractional) sample of the start of the current bar.
+    bar_start: f64,
+
+    /// The position in MIDI clocks of the last click within the current bar.
+    cursor: f64,
+}
+
+impl Metronome {
+    /// Create a new metronome clicking every quarter note at 120 BPM in 4/4, without accents.
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            sample_rate,
+            metronome: FlexMetronome {
+                clocks_per_primary_click: 24,
+                bar_accents: [0; 3],
+                subdivision_clicks: [0; 2],
+            },
+            tempo: FlexTempo::from_bpm(120.0),
+            time_signature: FlexTimeSignature {
+                numerator: 4,
+                denominator: 2,
+                number_of_32n: 8,
+            },
+            sample: 0,
+            bar_start: 0.0,
+            cursor: -1.0,
+        }
+    }
+
+    /// The current metronome settings.
+    pub fn metronome(&self) -> FlexMetronome {
+        self.metronome
+    }
+
+    /// Change the metronome settings from the current position.
+    pub fn set_metronome(&mut self, metronome: FlexMetronome) {
+        self.metronome = metronome;
+        // Clicks at the current position have not been played yet.
+        self.cursor = self.clock_now() - 2.0 * EPSILON;
+    }
+
+    /// Change the tempo from the current position. A tempo of 0 is ignored.
+    pub fn set_tempo(&mut self, tempo: FlexTempo) {
+        if tempo.0 == 0 {
+            return;
+        }
+        let clock = self.clock_now();
+        self.tempo = tempo;
+        self.bar_start = self.sample as f64 - clock * self.samples_per_clock();
+    }
+
+    /// Change the time signature, starting a new bar at the current position.
+    pub fn set_time_signature(&mut self, time_signature: FlexTimeSignature) {
+        self.time_signature = time_signature;
+        self.locate(self.sample);
+    }
+
+    /// Change the sample rate from the current position.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        let clock = self.clock_now();
+        self.sample_rate = sample_rate;
+        self.bar_start = self.sample as f64 - clock * self.samples_per_clock();
+    }
+
+    /// Move to an absolute sample, starting a new bar there.
+    pub fn locate(&mut self, sample: u64) {
+        self.sample = sample;
+        self.bar_start = sample as f64;
+        self.cursor = -1.0;
+    }
+
+    /// Apply a Set Metronome, Set Tempo or Set Time Signature message from the current position.
+    /// Other messages are ignored.
+    pub fn push(&mut self, message: &Flex) {
+        match message.status() {
+            FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetMetronome) => {
+                self.set_metronome(message.metronome())
+            }
+            FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTempo) => {
+                self.set_tempo(FlexTempo(message.tempo()))
+            }
+            FlexStatus::SetupAndPerformance(FlexSetupAndPerformance::SetTimeSignature) => {
+                self.set_time_signature(message.time_signature())
+            }
+            _ => (),
+        }
+    }
+
+    /// Compute the clicks within the next block of `frames` frames, in order, and advance
+    /// to the end of the block.
+    pub fn process(&mut self, frames: usize, mut click: impl FnMut(Click)) {
+        let block_start = self.sample;
+        let block_end = block_start + frames as u64;
+        let samples_per_clock = self.samples_per_clock();
+        let bar_clocks = self.bar_clocks();
+        if self.metronome.clocks_per_primary_click > 0 && samples_per_clock > 0.0 {
+            loop {
+                let Some((clock, kind)) = self.next_click(bar_clocks) else {
+                    let bar_end = self.bar_start + bar_clocks * samples_per_clock;
+                    if bar_end.ceil() >= block_end as f64 {
+                        break;
+                    }
+                    self.bar_start = bar_end;
+                    self.cursor = -1.0;
+                    continue;
+                };
+                let sample = (self.bar_start + clock * samples_per_clock).ceil().max(0.0) as u64;
+                if sample >= block_end {
+                    break;
+                }
+                self.cursor = clock;
+                if sample >= block_start {
+                    click(Click {
+                        frame: (sample - block_start) as usize,
+                        kind,
+                    });
+                }
+            }
+        }
+        self.sample = block_end;
+    }
+
+    fn samples_per_clock(&self) -> f64 {
+        self.tempo.seconds_per_quarter() * self.sample_rate / CLOCKS_PER_QUARTER
+    }
+
+    /// The length of a bar in MIDI clocks. The denominator is a power of two, eg 0 for whole notes.
+    fn bar_clocks(&self) -> f64 {
+        let whole = 4.0 * CLOCKS_PER_QUARTER;
+        let beat = whole / 2_f64.powi(self.time_signature.denominator as i32);
+        beat * self.time_signature.numerator.max(1) as f64
+    }
+
+    /// The position in MIDI clocks of the start of the next block within the current bar.
+    fn clock_now(&self) -> f64 {
+        (self.sample as f64 - self.bar_start) / self.samples_per_clock()
+    }
+
+    /// Find the first click after the cursor within the current bar.
+    fn next_click(&self, bar_clocks: f64) -> Option<(f64, ClickKind)> {
+        let primary_clocks = self.metronome.clocks_per_primary_click as f64;
+        let after = |step: f64| ((self.cursor + EPSILON) / step).floor() + 1.0;
+
+        let index = after(primary_clocks).max(0.0);
+        let mut next = (index * primary_clocks, self.primary_kind(index as u64));
+
+        let fields = [ClickKind::FirstSubdivision, ClickKind::SecondSubdivision];
+        for (clicks, kind) in self.metronome.subdivision_clicks.iter().zip(fields) {
+            if *clicks < 2 {
+                continue;
+            }
+            let step = primary_clocks / *clicks as f64;
+            let mut index = after(step).max(0.0);
+            if index as u64 % *clicks as u64 == 0 {
+                index += 1.0;
+            }
+            let clock = index * step;
+            if clock < next.0 - EPSILON {
+                next = (clock, kind);
+            }
+        }
+
+        Some(next).filter(|(clock, _)| *clock < bar_clocks - EPSILON)
+    }
+
+    /// Whether a primary click within the bar starts a group of bar accents.
+    fn primary_kind(&self, index: u64) -> ClickKind {
+        let groups = self.metronome.bar_accents;
+        let groups = groups.iter().filter(|g| **g > 0).map(|g| *g as u64);
+        if groups.clone().next().is_none() {
+            return ClickKind::Primary;
+        }
+        let mut start = 0;
+        for group in groups.cycle() {
+            if start == index {
+                return ClickKind::Accent;
+            }
+            if start > index {
+                break;
+            }
+            start += group;
+        }
+        ClickKind::Primary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::data::DataFormat;
+    use crate::message::flex::FlexAddress;
+
+    fn collect(metronome: &mut Metronome, frames: usize) -> Vec<(u64, ClickKind)> {
+        let start = metronome.sample;
+        let mut clicks = Vec::new();
+        metronome.process(frames, |c| clicks.push((start + c.frame as u64, c.kind)));
+        clicks
+    }
+
+    #[test]
+    fn quarter_notes_with_accents() {
+        let mut metronome = Metronome::new(48_000.0);
+        metronome.set_time_signature(FlexTimeSignature {
+            numerator: 5,
+            denominator: 2,
+            number_of_32n: 8,
+        });
+        metronome.set_metronome(FlexMetronome {
+            clocks_per_primary_click: 24,
+            bar_accents: [3, 2, 0],
+            subdivision_clicks: [0, 0],
+        });
+        let clicks = collect(&mut metronome, 48_000 * 5);
+        let expected: Vec<_> = (0..10)
+            .map(|i| {
+                let kind = match i % 5 {
+                    0 | 3 => ClickKind::Accent,
+                    _ => ClickKind::Primary,
+                };
+                (i * 24_000, kind)
+            })
+            .collect();
+        assert_eq!(clicks, expected);
+    }
+
+    #[test]
+    fn whole_note_beats() {
+        let mut metronome = Metronome::new(48_000.0);
+        metronome.set_time_signature(FlexTimeSignature {
+            numerator: 1,
+            denominator: 0,
+            number_of_32n: 8,
+        });
+        metronome.set_metronome(FlexMetronome {
+            clocks_per_primary_click: 96,
+            bar_accents: [1, 0, 0],
+            subdivision_clicks: [0, 0],
+        });
+        let clicks = collect(&mut metronome, 48_000 * 4);
+        assert_eq!(
+            clicks,
+            [(0, ClickKind::Accent), (96_000, ClickKind::Accent)]
+        );
+    }
+
+    #[test]
+    fn subdivisions() {
+        let mut metronome = Metronome::new(48_000.0);
+        metronome.set_metronome(FlexMetronome {
+            clocks_per_primary_click: 24,
+            bar_accents: [4, 0, 0],
+            subdivision_clicks: [2, 3],
+        });
+        let clicks = collect(&mut metronome, 24_000);
+        assert_eq!(
+            clicks,
+            [
+                (0, ClickKind::Accent),
+                (8_000, ClickKind::SecondSubdivision),
+                (12_000, ClickKind::FirstSubdivision),
+                (16_000, ClickKind::SecondSubdivision),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_size_does_not_change_clicks() {
+        let settings = FlexMetronome {
+            clocks_per_primary_click: 18,
+            bar_accents: [2, 0, 0],
+            subdivision_clicks: [3, 0],
+        };
+        let mut reference = Metronome::new(44_100.0);
+        reference.set_tempo(FlexTempo::from_bpm(97.0));
+        reference.set_metronome(settings);
+        let expected = collect(&mut reference, 44_100 * 10);
+
+        let mut metronome = Metronome::new(44_100.0);
+        metronome.set_tempo(FlexTempo::from_bpm(97.0));
+        metronome.set_metronome(settings);
+        let mut clicks = Vec::new();
+        for frames in [1, 64, 333, 512, 1024, 7].iter().cycle() {
+            if metronome.sample >= 44_100 * 10 {
+                break;
+            }
+            let frames = (*frames).min((44_100 * 10 - metronome.sample) as usize);
+            clicks.extend(collect(&mut metronome, frames));
+        }
+        assert_eq!(clicks, expected);
+    }
+
+    #[test]
+    fn tempo_change_keeps_phase() {
+        let mut metronome = Metronome::new(48_000.0);
+        assert_eq!(collect(&mut metronome, 12_000), [(0, ClickKind::Primary)]);
+        metronome.set_tempo(FlexTempo::from_bpm(60.0));
+        // Half way through the first beat, the next click is now half a second away.
+        assert_eq!(
+            collect(&mut metronome, 48_000),
+            [(36_000, ClickKind::Primary)]
+        );
+    }
+
+    #[test]
+    fn ignores_zero_tempo() {
+        let mut metronome = Metronome::new(48_000.0);
+        collect(&mut metronome, 512);
+        metronome.push(&Flex::set_tempo(
+            0,
+            FlexAddress::Group(0),
+            DataFormat::SinglePacket,
+            FlexTempo(0),
+        ));
+        assert_eq!(metronome.tempo, FlexTempo::from_bpm(120.0));
+        collect(&mut metronome, 512);
+        metronome.set_tempo(FlexTempo::from_bpm(60.0));
+        assert_eq!(
+            collect(&mut metronome, 48_000),
+            [(46_976, ClickKind::Primary)]
+        );
+    }
+}
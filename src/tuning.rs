@@ -0,0 +1,353 @@
+//! Tuning tables mapping the 128 MIDI notes to frequencies, for microtonal music.
+//!
+//! A [TuningTable] is loaded from [MIDI Tuning Standard](crate::universal::mts) messages or
+//! [Scala files](scala), and turned into MTS messages or the per-note pitch of MIDI 2.0
+//! messages. Only available if the `no-std` feature is not specified.
+use crate::convert;
+use crate::message::channel2::{Attribute, ChannelVoice};
+use crate::universal::mts::{BulkDump, MtsFrequency, MtsMessage, SingleNoteTuning, NAME_LEN};
+
+pub mod scala;
+
+pub use self::scala::{KeyboardMapping, ScalaError, Scale};
+
+/// The note number of A4.
+pub const A4: u8 = 69;
+
+/// The frequency of A4 in equal temperament, in Hz.
+pub const A4_FREQUENCY: f64 = 440.0;
+
+/// The index of the Pitch 7.25 registered per-note controller.
+const PITCH_7_25: u8 = 3;
+
+/// The pitch of every MIDI note, in semitones where 69.0 is A440.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TuningTable {
+    pitches: [f64; 128],
+}
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        Self::equal_temperament()
+    }
+}
+
+/// Convert a frequency in Hz to a pitch in semitones, where 69.0 is A440.
+pub fn frequency_to_semitones(frequency: f64) -> f64 {
+    A4 as f64 + 12.0 * (frequency / A4_FREQUENCY).log2()
+}
+
+/// Convert a pitch in semitones, where 69.0 is A440, to a frequency in Hz.
+pub fn semitones_to_frequency(semitones: f64) -> f64 {
+    A4_FREQUENCY * ((semitones - A4 as f64) / 12.0).exp2()
+}
+
+impl TuningTable {
+    /// The standard tuning: 12 tone equal temperament with A4 at 440 Hz.
+    pub fn equal_temperament() -> Self {
+        let mut pitches = [0.0; 128];
+        for (note, pitch) in pitches.iter_mut().enumerate() {
+            *pitch = note as f64;
+        }
+        Self { pitches }
+    }
+
+    /// The pitch of a note in semitones.
+    pub fn semitones(&self, note: u8) -> f64 {
+        debug_assert!(note < 0x80, "Wrong integer size: note is u7");
+        self.pitches[(note & 0x7f) as usize]
+    }
+
+    /// Set the pitch of a note in semitones.
+    pub fn set_semitones(&mut self, note: u8, semitones: f64) {
+        debug_assert!(note < 0x80, "Wrong integer size: note is u7");
+        self.pitches[(note & 0x7f) as usize] = semitones;
+    }
+
+    /// The frequency of a note in Hz.
+    pub fn frequency(&self, note: u8) -> f64 {
+        semitones_to_frequency(self.semitones(note))
+    }
+
+    /// Set the frequency of a note in Hz.
+    pub fn set_frequency(&mut self, note: u8, frequency: f64) {
+        self.set_semitones(note, frequency_to_semitones(frequency));
+    }
+
+    /// The offset of a note from equal temperament, in cents.
+    pub fn cents(&self, note: u8) -> f64 {
+        (self.semitones(note) - note as f64) * 100.0
+    }
+
+    /// The tuning of a Scala scale and keyboard mapping, use [KeyboardMapping::default] to
+    /// play the scale from middle C. Notes that are not mapped keep their equal temperament
+    /// pitch.
+    pub fn from_scala(scale: &Scale, mapping: &KeyboardMapping) -> Result<Self, ScalaError> {
+        let reference = mapping
+            .degree(mapping.reference)
+            .ok_or(ScalaError::UnmappedReference)?;
+        let reference_cents = scale.cents(reference);
+        let reference_semitones = frequency_to_semitones(mapping.frequency);
+        let mut table = Self::equal_temperament();
+        for note in mapping.first..=mapping.last.min(127) {
+            if let Some(degree) = mapping.degree(note) {
+                let cents = scale.cents(degree) - reference_cents;
+                table.set_semitones(note, reference_semitones + cents / 100.0);
+            }
+        }
+        Ok(table)
+    }
+
+    /// Apply a MIDI Tuning Standard message to the table. Notes the message leaves unchanged
+    /// keep their pitch, and Scale/Octave Tuning is applied regardless of its channels.
+    pub fn apply(&mut self, message: &MtsMessage) {
+        match message {
+            MtsMessage::BulkDumpRequest(_) => (),
+            MtsMessage::BulkDump(dump) => {
+                for (note, frequency) in dump.frequencies.iter().enumerate() {
+                    if let Some(semitones) = frequency.semitones() {
+                        self.pitches[note] = semitones;
+                    }
+                }
+            }
+            MtsMessage::SingleNoteTuning(tuning) => {
+                for (note, frequency) in &tuning.changes {
+                    if let (Some(pitch), Some(semitones)) =
+                        (self.pitches.get_mut(*note as usize), frequency.semitones())
+                    {
+                        *pitch = semitones;
+                    }
+                }
+            }
+            MtsMessage::ScaleOctaveTuning(tuning) => {
+                let cents = tuning.offsets.cents();
+                for (note, pitch) in self.pitches.iter_mut().enumerate() {
+                    *pitch = note as f64 + cents[note % 12] / 100.0;
+                }
+            }
+        }
+    }
+
+    /// The frequency of a note in MTS form.
+    pub fn mts_frequency(&self, note: u8) -> MtsFrequency {
+        MtsFrequency::from_semitones(self.semitones(note))
+    }
+
+    /// A Bulk Tuning Dump of the table, with a name truncated to 16 ASCII characters.
+    pub fn to_bulk_dump(
+        &self,
+        device_id: u8,
+        bank: Option<u8>,
+        program: u8,
+        name: &str,
+    ) -> BulkDump {
+        let mut bytes = [b' '; NAME_LEN];
+        for (byte, c) in bytes.iter_mut().zip(name.chars()) {
+            *byte = if c.is_ascii() && !c.is_ascii_control() {
+                c as u8
+            } else {
+                b'?'
+            };
+        }
+        let mut frequencies = [MtsFrequency::NO_CHANGE; 128];
+        for (note, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = self.mts_frequency(note as u8);
+        }
+        BulkDump {
+            device_id,
+            bank,
+            program,
+            name: bytes,
+            frequencies,
+        }
+    }
+
+    /// A Real Time Single Note Tuning Change retuning some notes to their pitch in the table.
+    pub fn to_single_note_tuning(
+        &self,
+        device_id: u8,
+        program: u8,
+        notes: impl IntoIterator<Item = u8>,
+    ) -> SingleNoteTuning {
+        SingleNoteTuning {
+            device_id,
+            real_time: true,
+            bank: None,
+            program,
+            changes: notes
+                .into_iter()
+                .map(|note| (note, self.mts_frequency(note)))
+                .collect(),
+        }
+    }
+
+    fn split(&self, note: u8) -> (u8, f32) {
+        let semitones = self.semitones(note).clamp(0.0, 127.999_999_9);
+        let semis = semitones.floor();
+        (semis as u8, ((semitones - semis) * 100.0) as f32)
+    }
+
+    /// The pitch of a note in 7.9 fixed point.
+    pub fn pitch_7_9(&self, note: u8) -> u16 {
+        let (semis, cents) = self.split(note);
+        convert::semitones_to_fixed_7_9(semis, cents)
+    }
+
+    /// The pitch of a note in 7.25 fixed point.
+    pub fn pitch_7_25(&self, note: u8) -> u32 {
+        let (semis, cents) = self.split(note);
+        convert::semitones_to_fixed_7_25(semis, cents)
+    }
+
+    /// The Pitch 7.9 attribute of note on messages playing a note at its pitch in the table.
+    pub fn pitch_attribute(&self, note: u8) -> Attribute {
+        Attribute::Pitch79(self.pitch_7_9(note))
+    }
+
+    /// A note on message playing a note at its pitch in the table, with a Pitch 7.9 attribute.
+    pub fn note_on(&self, note: u8, velocity: u16) -> ChannelVoice {
+        ChannelVoice::note_on(note, velocity, Some(self.pitch_attribute(note)))
+    }
+
+    /// A Pitch 7.25 registered per-note controller message, retuning a note to its pitch in
+    /// the table.
+    pub fn pitch_controller(&self, note: u8) -> ChannelVoice {
+        ChannelVoice::registered_per_note_controller(note, PITCH_7_25, self.pitch_7_25(note))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::channel2::ChannelVoiceStatus;
+    use crate::message::Message;
+    use crate::universal::mts::{ScaleOctaveTuning, ScaleOffsets};
+    use crate::universal::{UniversalMessage, ALL_CALL};
+
+    #[test]
+    fn frequencies() {
+        let mut table = TuningTable::default();
+        assert_eq!(table.frequency(A4), 440.0);
+        assert!((table.frequency(60) - 261.6256).abs() < 1e-4);
+        table.set_frequency(60, 256.0);
+        assert!((table.cents(60) + 37.63).abs() < 0.01);
+    }
+
+    #[test]
+    fn mts_round_trip() {
+        let mut table = TuningTable::default();
+        table.set_semitones(61, 61.25);
+        let dump = table.to_bulk_dump(ALL_CALL, None, 0, "Test tuning");
+        assert_eq!(&dump.name, b"Test tuning     ");
+        let message = MtsMessage::decode(&dump.to_sysex().unwrap()).unwrap();
+        let mut loaded = TuningTable::default();
+        loaded.apply(&message);
+        assert_eq!(loaded.semitones(61), 61.25);
+
+        let change = table.to_single_note_tuning(ALL_CALL, 0, 60..=61);
+        let mut retuned = TuningTable::default();
+        retuned.set_semitones(60, 0.0);
+        retuned.apply(&MtsMessage::SingleNoteTuning(change));
+        assert_eq!(retuned, table);
+
+        let mut cents = [0.0; 12];
+        cents[1] = 25.0;
+        let mut scale = TuningTable::default();
+        scale.apply(&MtsMessage::ScaleOctaveTuning(ScaleOctaveTuning {
+            device_id: ALL_CALL,
+            real_time: true,
+            channels: 0xffff,
+            offsets: ScaleOffsets::one_byte(cents),
+        }));
+        assert_eq!(scale.semitones(61), 61.25);
+        assert_eq!(scale.semitones(13), 13.25);
+        assert_eq!(scale.semitones(60), 60.0);
+    }
+
+    #[test]
+    fn pitch() {
+        let mut table = TuningTable::default();
+        table.set_semitones(60, 60.5);
+        assert_eq!(table.pitch_7_9(60), 60 << 9 | 256);
+        assert_eq!(table.pitch_7_25(60), 60 << 25 | 1 << 24);
+        assert_eq!(table.pitch_7_9(A4), 69 << 9);
+        assert!(matches!(
+            table.pitch_attribute(60),
+            Attribute::Pitch79(0x7900)
+        ));
+        table.set_semitones(127, 140.0);
+        assert_eq!(table.pitch_7_9(127), 0xffff);
+
+        let message = table.pitch_controller(60);
+        assert_eq!(message[..], [0x4000_3c03, 60 << 25 | 1 << 24]);
+
+        let message = table.note_on(60, 0xffff);
+        assert_eq!(message.status(), ChannelVoiceStatus::NoteOn);
+        assert_eq!(message[0], 0x4090_3c03);
+        assert_eq!(message[1], 0xffff << 16 | 60 << 9 | 256);
+    }
+
+    #[test]
+    fn scala() {
+        // Quarter-comma meantone around A440.
+        let scale = Scale::parse(
+            "Meantone
+12
+76.04900
+193.15686
+310.26471
+5/4
+503.42157
+579.47057
+696.57843
+25/16
+889.73529
+1006.84314
+1082.89214
+2/1
+",
+        )
+        .unwrap();
+        let mapping = KeyboardMapping {
+            reference: A4,
+            frequency: 440.0,
+            ..KeyboardMapping::default()
+        };
+        let table = TuningTable::from_scala(&scale, &mapping).unwrap();
+        assert_eq!(table.frequency(A4), 440.0);
+        assert!((table.cents(64) + 3.4216).abs() < 1e-3);
+        assert!((table.frequency(72) / table.frequency(60) - 2.0).abs() < 1e-12);
+
+        // A mapping of the white keys only leaves the black keys in equal temperament.
+        let white_keys = KeyboardMapping::parse(
+            "12\n0\n127\n60\n69\n440.0\n12\n0\nx\n2\nx\n4\n5\nx\n7\nx\n9\nx\n11\n",
+        )
+        .unwrap();
+        let table = TuningTable::from_scala(&scale, &white_keys).unwrap();
+        assert_eq!(table.semitones(61), 61.0);
+        assert!((table.cents(62) - 3.4216).abs() < 1e-3);
+
+        let unmapped = KeyboardMapping {
+            reference: 61,
+            ..white_keys
+        };
+        assert_eq!(
+            TuningTable::from_scala(&scale, &unmapped),
+            Err(ScalaError::UnmappedReference)
+        );
+
+        // Notes past 127 are not MIDI notes and are ignored.
+        let wide = KeyboardMapping {
+            last: 255,
+            ..mapping.clone()
+        };
+        assert_eq!(
+            TuningTable::from_scala(&scale, &wide),
+            TuningTable::from_scala(&scale, &mapping)
+        );
+
+        let message = table.note_on(62, 0x8000);
+        assert_eq!(message[0] & 0xfff0_ffff, 0x4090_3e03);
+        assert_eq!(message[1] >> 16, 0x8000);
+    }
+}
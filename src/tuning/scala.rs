@@ -0,0 +1,319 @@
+//! Import of Scala scale (`.scl`) and keyboard mapping (`.kbm`) files.
+//!
+//! A scale lists the pitches of its degrees above the first, in cents or as ratios, the last
+//! being the period after which the scale repeats, usually an octave. A keyboard mapping
+//! assigns scale degrees to MIDI notes and sets the frequency of a reference note.
+use core::fmt;
+use core::str::FromStr;
+
+use super::semitones_to_frequency;
+
+/// An error parsing a Scala file.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ScalaError {
+    /// A line, numbered from 1, could not be parsed.
+    InvalidLine(usize),
+
+    /// The file ended before all of its fields were read.
+    Truncated,
+
+    /// The reference note of the keyboard mapping is not mapped to a scale degree.
+    UnmappedReference,
+}
+
+impl fmt::Display for ScalaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLine(line) => write!(f, "invalid Scala file at line {line}"),
+            Self::Truncated => f.write_str("truncated Scala file"),
+            Self::UnmappedReference => f.write_str("the reference note is not mapped"),
+        }
+    }
+}
+
+impl std::error::Error for ScalaError {}
+
+/// The lines of a Scala file that are not comments, with their line numbers.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.starts_with('!'))
+}
+
+/// The first word of a line, ignoring the text that may follow it.
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_note(line: Option<(usize, &str)>) -> Result<u8, ScalaError> {
+    let note: u8 = parse_field(line)?;
+    match line {
+        Some((number, _)) if note > 127 => Err(ScalaError::InvalidLine(number)),
+        _ => Ok(note),
+    }
+}
+
+fn parse_field<T: FromStr>(line: Option<(usize, &str)>) -> Result<T, ScalaError> {
+    let (number, line) = line.ok_or(ScalaError::Truncated)?;
+    first_word(line)
+        .parse()
+        .map_err(|_| ScalaError::InvalidLine(number))
+}
+
+/// Parse a pitch in cents if it has a period, otherwise as a ratio or an integer.
+fn parse_pitch(word: &str) -> Option<f64> {
+    if word.contains('.') {
+        return word.parse().ok();
+    }
+    let (numerator, denominator): (u64, u64) = match word.split_once('/') {
+        Some((numerator, denominator)) => (numerator.parse().ok()?, denominator.parse().ok()?),
+        None => (word.parse().ok()?, 1),
+    };
+    if numerator == 0 || denominator == 0 {
+        return None;
+    }
+    Some(1200.0 * (numerator as f64 / denominator as f64).log2())
+}
+
+/// A Scala scale.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    /// A description of the scale.
+    pub description: String,
+
+    /// The pitch of each degree above the first in cents, the last being the period.
+    pub pitches: Vec<f64>,
+}
+
+impl Scale {
+    /// Parse a scale from the text of a `.scl` file.
+    pub fn parse(text: &str) -> Result<Self, ScalaError> {
+        let mut lines = lines(text);
+        let description = lines.next().ok_or(ScalaError::Truncated)?.1.to_owned();
+        let count: usize = parse_field(lines.next())?;
+        let pitches = lines
+            .take(count)
+            .map(|(number, line)| {
+                parse_pitch(first_word(line)).ok_or(ScalaError::InvalidLine(number))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if pitches.len() < count || count == 0 {
+            return Err(ScalaError::Truncated);
+        }
+        Ok(Self {
+            description,
+            pitches,
+        })
+    }
+
+    /// The interval after which the scale repeats, in cents.
+    pub fn period(&self) -> f64 {
+        self.pitches.last().copied().unwrap_or(1200.0)
+    }
+
+    /// The pitch of a scale degree in cents, relative to degree 0.
+    pub fn cents(&self, degree: i32) -> f64 {
+        let len = self.pitches.len().max(1) as i32;
+        let periods = degree.div_euclid(len);
+        let index = degree.rem_euclid(len);
+        let cents = match index {
+            0 => 0.0,
+            index => self.pitches[index as usize - 1],
+        };
+        periods as f64 * self.period() + cents
+    }
+}
+
+impl FromStr for Scale {
+    type Err = ScalaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// A Scala keyboard mapping.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    /// The first note to retune.
+    pub first: u8,
+
+    /// The last note to retune.
+    pub last: u8,
+
+    /// The note playing degree 0 of the scale, or the first entry of the mapping.
+    pub middle: u8,
+
+    /// The note tuned to [KeyboardMapping::frequency].
+    pub reference: u8,
+
+    /// The frequency of the reference note, in Hz.
+    pub frequency: f64,
+
+    /// The scale degree at which the mapping repeats, one period higher.
+    pub octave_degree: u32,
+
+    /// The scale degree of each note of the mapping from the middle note, or `None` for notes
+    /// that are not retuned. Empty for a linear mapping, one degree per note.
+    pub mapping: Vec<Option<u32>>,
+}
+
+impl Default for KeyboardMapping {
+    /// A linear mapping of every note, with degree 0 on middle C tuned as in equal
+    /// temperament.
+    fn default() -> Self {
+        Self {
+            first: 0,
+            last: 127,
+            middle: 60,
+            reference: 60,
+            frequency: semitones_to_frequency(60.0),
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMapping {
+    /// Parse a mapping from the text of a `.kbm` file.
+    pub fn parse(text: &str) -> Result<Self, ScalaError> {
+        let mut lines = lines(text).filter(|(_, line)| !line.is_empty());
+        let size_line = lines.next();
+        let size: usize = parse_field(size_line)?;
+        match size_line {
+            // A mapping never spans more than the 128 MIDI notes.
+            Some((number, _)) if size > 128 => return Err(ScalaError::InvalidLine(number)),
+            _ => (),
+        }
+        let first = parse_note(lines.next())?;
+        let last = parse_note(lines.next())?;
+        let middle = parse_note(lines.next())?;
+        let reference = parse_note(lines.next())?;
+        let frequency_line = lines.next();
+        let frequency: f64 = parse_field(frequency_line)?;
+        match frequency_line {
+            Some((number, _)) if !frequency.is_finite() || frequency <= 0.0 => {
+                return Err(ScalaError::InvalidLine(number))
+            }
+            _ => (),
+        }
+        let octave_degree = parse_field(lines.next())?;
+        // Missing entries at the end of the mapping are not retuned.
+        let mut mapping = vec![None; size];
+        for (entry, (number, line)) in mapping.iter_mut().zip(lines) {
+            *entry = match first_word(line) {
+                "x" | "X" => None,
+                word => Some(word.parse().map_err(|_| ScalaError::InvalidLine(number))?),
+            };
+        }
+        Ok(Self {
+            first,
+            last,
+            middle,
+            reference,
+            frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    /// The scale degree a note plays, if it is mapped. Linear mappings repeat at the scale
+    /// period.
+    pub fn degree(&self, note: u8) -> Option<i32> {
+        let offset = note as i32 - self.middle as i32;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+        let len = self.mapping.len() as i32;
+        let degree = self.mapping[offset.rem_euclid(len) as usize]?;
+        Some(degree as i32 + offset.div_euclid(len) * self.octave_degree as i32)
+    }
+}
+
+impl FromStr for KeyboardMapping {
+    type Err = ScalaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALE: &str = "! meantone.scl
+!
+Quarter-comma meantone, 3 notes
+ 3
+!
+ 193.15686
+ 5/4 major third
+ 2
+";
+
+    #[test]
+    fn scale() {
+        let scale: Scale = SCALE.parse().unwrap();
+        assert_eq!(scale.description, "Quarter-comma meantone, 3 notes");
+        assert_eq!(scale.pitches.len(), 3);
+        assert!((scale.pitches[1] - 386.3137).abs() < 1e-4);
+        assert_eq!(scale.period(), 1200.0);
+        assert_eq!(scale.cents(3), 1200.0);
+        assert_eq!(scale.cents(-2), -1200.0 + 193.15686);
+
+        assert_eq!(
+            Scale::parse("Bad\n2\n100.0\n3/0\n"),
+            Err(ScalaError::InvalidLine(4))
+        );
+        assert_eq!(
+            Scale::parse("Short\n2\n100.0\n"),
+            Err(ScalaError::Truncated)
+        );
+    }
+
+    #[test]
+    fn keyboard_mapping() {
+        let text = "! 7 white keys
+7
+0
+127
+60
+69
+440.0
+7
+0
+x
+1
+x
+2
+3
+";
+        let mapping = KeyboardMapping::parse(text).unwrap();
+        assert_eq!(mapping.mapping.len(), 7);
+        assert_eq!(mapping.mapping[6], None);
+        assert_eq!(mapping.degree(60), Some(0));
+        assert_eq!(mapping.degree(61), None);
+        assert_eq!(mapping.degree(62), Some(1));
+        assert_eq!(mapping.degree(67), Some(7));
+        assert_eq!(mapping.degree(53), Some(-7));
+        assert_eq!(KeyboardMapping::default().degree(59), Some(-1));
+        assert_eq!(
+            KeyboardMapping::parse("1\n0\n127\n60\n69\nA440\n"),
+            Err(ScalaError::InvalidLine(6))
+        );
+        assert_eq!(
+            KeyboardMapping::parse("1\n0\n127\n60\n69\n0.0\n"),
+            Err(ScalaError::InvalidLine(6))
+        );
+        assert_eq!(
+            KeyboardMapping::parse("1\n0\n127\n60\n69\n-440\n"),
+            Err(ScalaError::InvalidLine(6))
+        );
+        assert_eq!(
+            KeyboardMapping::parse("18446744073709551615\n0\n127\n60\n69\n440.0\n"),
+            Err(ScalaError::InvalidLine(1))
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Universal System Exclusive messages that predate MIDI-CI, eg Identity Request.
+//!
+//! Universal SysEx messages start with a Non-Real Time (`7E`) or Real Time (`7F`) ID, the
+//! device ID they address and two sub-IDs:
+//!
+//! ```text
+//! F0 <7E|7F> <device id> <sub-id #1> <sub-id #2> <data...> F7
+//! ```
+//!
+//! Like [MIDI-CI messages](crate::ci), messages are encoded without the `F0` and `F7`
+//! delimiters, and decoding accepts messages with or without them.
+use core::fmt;
+
+#[cfg(not(feature = "no-std"))]
+use crate::message::data::Data64;
+
+pub mod device_control;
+pub mod general_midi;
+pub mod identity;
+#[cfg(not(feature = "no-std"))]
+pub mod mts;
+
+pub use self::device_control::{Control, DeviceControl, GlobalParameterControl};
+pub use self::general_midi::{
+    ChorusParameter, ChorusType, GeneralMidi, GeneralMidiMode, Gm2Chorus, Gm2Reverb,
+    KeyBasedController, ReverbParameter, ReverbType,
+};
+pub use self::identity::{DeviceIdentity, IdentityReply, IdentityRequest, ManufacturerId};
+#[cfg(not(feature = "no-std"))]
+pub use self::mts::{
+    BulkDump, BulkDumpRequest, MtsFrequency, MtsMessage, ScaleOctaveTuning, ScaleOffsets,
+    SingleNoteTuning,
+};
+
+/// Universal Non-Real Time SysEx ID.
+pub const NON_REAL_TIME: u8 = 0x7e;
+
+/// Universal Real Time SysEx ID.
+pub const REAL_TIME: u8 = 0x7f;
+
+/// Device ID addressing every device.
+pub const ALL_CALL: u8 = 0x7f;
+
+/// Errors encoding or decoding Universal SysEx messages.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Error {
+    /// The message is not a Universal SysEx message of the expected type.
+    UnexpectedMessage,
+
+    /// The message ended before all of its fields were read.
+    Truncated,
+
+    /// A data byte had its high bit set.
+    InvalidByte(u8),
+
+    /// A field had a value that is not allowed.
+    InvalidValue,
+
+    /// The checksum of the message did not match its data.
+    InvalidChecksum,
+
+    /// The buffer was too small to hold the encoded message.
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedMessage => f.write_str("unexpected Universal SysEx message"),
+            Self::Truncated => f.write_str("truncated Universal SysEx message"),
+            Self::InvalidByte(byte) => write!(f, "invalid SysEx data byte {byte:#04x}"),
+            Self::InvalidValue => f.write_str("invalid Universal SysEx field value"),
+            Self::InvalidChecksum => f.write_str("invalid Universal SysEx checksum"),
+            Self::BufferTooSmall => f.write_str("buffer too small for Universal SysEx message"),
+        }
+    }
+}
+
+/// A Universal SysEx message.
+pub trait UniversalMessage<'a>: Sized {
+    /// Encode the message into a buffer, returning the number of bytes written.
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error>;
+
+    /// Decode a message of this type.
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error>;
+
+    /// Encode the message into a new buffer.
+    #[cfg(not(feature = "no-std"))]
+    fn to_sysex(&self) -> Result<Vec<u8>, Error> {
+        let mut buffer = vec![0; 32];
+        loop {
+            match self.encode(&mut buffer) {
+                Ok(len) => {
+                    buffer.truncate(len);
+                    return Ok(buffer);
+                }
+                Err(Error::BufferTooSmall) => buffer.resize(buffer.len() * 2, 0),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Encode the message as SysEx7 packets on a group.
+    #[cfg(not(feature = "no-std"))]
+    fn to_packets(&self, group: u8) -> Result<Vec<Data64>, Error> {
+        Ok(Data64::sysex7(group, &self.to_sysex()?).collect())
+    }
+}
+
+/// Split a 14 bit value into two 7-bit bytes, least significant first.
+pub fn u14_to_bytes(value: u16) -> [u8; 2] {
+    debug_assert!(value < 0x4000, "Wrong integer size: value is u14");
+    [(value & 0x7f) as u8, ((value >> 7) & 0x7f) as u8]
+}
+
+/// Join two 7-bit bytes, least significant first, into a 14 bit value.
+pub fn u14_from_bytes(bytes: [u8; 2]) -> u16 {
+    (bytes[0] & 0x7f) as u16 | ((bytes[1] & 0x7f) as u16) << 7
+}
+
+/// Scale a value from 0 to 1 to 14 bits.
+pub fn u14_from_f32(value: f32) -> u16 {
+    // Round by hand, f32::round needs std.
+    (value.clamp(0.0, 1.0) * 16383.0 + 0.5) as u16
+}
+
+/// Scale a 14 bit value to a value from 0 to 1.
+pub fn u14_to_f32(value: u16) -> f32 {
+    value.min(0x3fff) as f32 / 16383.0
+}
+
+/// Strip the optional `F0`/`F7` delimiters, check that every byte is 7-bit and that the
+/// message starts with the expected IDs, returning the device ID and the data that follows.
+fn parse(sysex: &[u8], ids: [u8; 3]) -> Result<(u8, &[u8]), Error> {
+    let sysex = sysex.strip_prefix(&[0xf0]).unwrap_or(sysex);
+    let sysex = sysex.strip_suffix(&[0xf7]).unwrap_or(sysex);
+    if let Some(byte) = sysex.iter().copied().find(|b| *b > 0x7f) {
+        return Err(Error::InvalidByte(byte));
+    }
+    match sysex {
+        [universal, device_id, sub_id1, sub_id2, data @ ..]
+            if [*universal, *sub_id1, *sub_id2] == ids =>
+        {
+            Ok((*device_id, data))
+        }
+        [_, _, _, _, ..] => Err(Error::UnexpectedMessage),
+        _ => Err(Error::Truncated),
+    }
+}
+
+/// Check that every byte is 7-bit and write the bytes of a message into a buffer, returning
+/// the number of bytes written.
+fn write(buffer: &mut [u8], parts: &[&[u8]]) -> Result<usize, Error> {
+    let bytes = parts.iter().flat_map(|part| part.iter().copied());
+    if let Some(byte) = bytes.clone().find(|b| *b > 0x7f) {
+        return Err(Error::InvalidByte(byte));
+    }
+    let len = parts.iter().map(|part| part.len()).sum();
+    if buffer.len() < len {
+        return Err(Error::BufferTooSmall);
+    }
+    let mut offset = 0;
+    for part in parts {
+        buffer[offset..offset + part.len()].copy_from_slice(part);
+        offset += part.len();
+    }
+    Ok(len)
+}
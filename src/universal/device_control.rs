@@ -0,0 +1,279 @@
+//! Device Control, the Universal Real Time messages setting the master volume, balance and
+//! tuning of a whole device.
+use super::{parse, u14_from_bytes, u14_to_bytes, write, Error, UniversalMessage, REAL_TIME};
+
+/// Sub-ID #1 of Device Control messages.
+pub const DEVICE_CONTROL: u8 = 0x04;
+
+/// A Device Control message.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct DeviceControl<'a> {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The control to set.
+    pub control: Control<'a>,
+}
+
+/// The controls of a Device Control message.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Control<'a> {
+    /// The 14 bit master volume, from silence (0) to full volume (0x3fff).
+    MasterVolume(u16),
+
+    /// The 14 bit master balance, from hard left (0) to hard right (0x3fff), centered at
+    /// 0x2000.
+    MasterBalance(u16),
+
+    /// The 14 bit master fine tuning, from -100 (0) to +100 cents (0x3fff), centered at 0x2000.
+    MasterFineTuning(u16),
+
+    /// The master coarse tuning in semitones, from -64 (0) to +63 (0x7f), centered at 0x40.
+    MasterCoarseTuning(u8),
+
+    /// Sets parameters of a device, eg its reverb or chorus.
+    GlobalParameterControl(GlobalParameterControl<'a>),
+}
+
+impl Control<'_> {
+    /// The master volume, from 0 to 1.
+    pub fn volume(volume: f32) -> Self {
+        Self::MasterVolume(super::u14_from_f32(volume))
+    }
+
+    /// The master balance, from -1 (left) to 1 (right).
+    pub fn balance(balance: f32) -> Self {
+        Self::MasterBalance(bipolar_to_u14(balance))
+    }
+
+    /// The master fine tuning, from -100 to +100 cents.
+    pub fn fine_tuning(cents: f32) -> Self {
+        Self::MasterFineTuning(bipolar_to_u14(cents / 100.0))
+    }
+
+    /// The master coarse tuning, from -64 to +63 semitones.
+    pub fn coarse_tuning(semitones: i8) -> Self {
+        Self::MasterCoarseTuning((semitones.clamp(-64, 63) + 0x40) as u8)
+    }
+
+    /// The tuning in cents of master fine and coarse tuning controls.
+    pub fn cents(&self) -> Option<f32> {
+        match *self {
+            Self::MasterFineTuning(value) => Some(u14_to_bipolar(value) * 100.0),
+            Self::MasterCoarseTuning(value) => Some((value as f32 - 64.0) * 100.0),
+            _ => None,
+        }
+    }
+
+    fn sub_id(&self) -> u8 {
+        match self {
+            Self::MasterVolume(_) => 0x01,
+            Self::MasterBalance(_) => 0x02,
+            Self::MasterFineTuning(_) => 0x03,
+            Self::MasterCoarseTuning(_) => 0x04,
+            Self::GlobalParameterControl(_) => 0x05,
+        }
+    }
+}
+
+fn bipolar_to_u14(value: f32) -> u16 {
+    (0x2000 as f32 + value.clamp(-1.0, 1.0) * 0x2000 as f32).clamp(0.0, 16383.0) as u16
+}
+
+fn u14_to_bipolar(value: u16) -> f32 {
+    (value.min(0x3fff) as f32 - 0x2000 as f32) / 0x2000 as f32
+}
+
+/// Global Parameter Control: sets parameters of a slot of a device, eg a reverb unit.
+///
+/// The slot path and parameters are kept as encoded bytes. Each slot of the path is two bytes,
+/// most significant first, and each parameter a number of `parameter_width` bytes followed by
+/// a value of `value_width` bytes, both least significant first.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct GlobalParameterControl<'a> {
+    /// The slots to the parameters, eg `[0x01, 0x01]` for the GM2 reverb.
+    pub slot_path: &'a [u8],
+
+    /// The number of bytes of each parameter number.
+    pub parameter_width: u8,
+
+    /// The number of bytes of each value.
+    pub value_width: u8,
+
+    /// The parameter numbers and values.
+    pub parameters: &'a [u8],
+}
+
+impl<'a> GlobalParameterControl<'a> {
+    /// The slots of the path, most significant byte first.
+    pub fn slots(&self) -> impl Iterator<Item = [u8; 2]> + 'a {
+        self.slot_path
+            .chunks_exact(2)
+            .map(|slot| [slot[0], slot[1]])
+    }
+
+    /// The parameters numbers and values, in their encoded form.
+    pub fn parameters(&self) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + 'a {
+        let width = self.parameter_width as usize;
+        let len = width + self.value_width as usize;
+        self.parameters
+            .chunks_exact(len.max(1))
+            .map(move |parameter| parameter.split_at(width))
+    }
+
+    fn is_valid(&self) -> bool {
+        let len = self.parameter_width as usize + self.value_width as usize;
+        self.slot_path.len() % 2 == 0
+            && self.slot_path.len() < 0x100
+            && (len > 0 || self.parameters.is_empty())
+            && self.parameters.len() % len.max(1) == 0
+    }
+}
+
+impl<'a> UniversalMessage<'a> for DeviceControl<'a> {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let header = [
+            REAL_TIME,
+            self.device_id,
+            DEVICE_CONTROL,
+            self.control.sub_id(),
+        ];
+        match self.control {
+            Control::MasterVolume(value)
+            | Control::MasterBalance(value)
+            | Control::MasterFineTuning(value) => write(buffer, &[&header, &u14_to_bytes(value)]),
+            Control::MasterCoarseTuning(value) => write(buffer, &[&header, &[0, value]]),
+            Control::GlobalParameterControl(gpc) => {
+                if !gpc.is_valid() {
+                    return Err(Error::InvalidValue);
+                }
+                let widths = [
+                    (gpc.slot_path.len() / 2) as u8,
+                    gpc.parameter_width,
+                    gpc.value_width,
+                ];
+                write(buffer, &[&header, &widths, gpc.slot_path, gpc.parameters])
+            }
+        }
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let sysex = sysex.strip_prefix(&[0xf0]).unwrap_or(sysex);
+        let sub_id = *sysex.get(3).ok_or(Error::Truncated)?;
+        let (device_id, data) = parse(sysex, [REAL_TIME, DEVICE_CONTROL, sub_id])?;
+        let control = match (sub_id, data) {
+            (0x01, [lsb, msb, ..]) => Control::MasterVolume(u14_from_bytes([*lsb, *msb])),
+            (0x02, [lsb, msb, ..]) => Control::MasterBalance(u14_from_bytes([*lsb, *msb])),
+            (0x03, [lsb, msb, ..]) => Control::MasterFineTuning(u14_from_bytes([*lsb, *msb])),
+            (0x04, [_, msb, ..]) => Control::MasterCoarseTuning(*msb),
+            (0x05, [slots, parameter_width, value_width, data @ ..]) => {
+                let path_len = *slots as usize * 2;
+                if data.len() < path_len {
+                    return Err(Error::Truncated);
+                }
+                let (slot_path, parameters) = data.split_at(path_len);
+                let gpc = GlobalParameterControl {
+                    slot_path,
+                    parameter_width: *parameter_width,
+                    value_width: *value_width,
+                    parameters,
+                };
+                if !gpc.is_valid() {
+                    return Err(Error::InvalidValue);
+                }
+                Control::GlobalParameterControl(gpc)
+            }
+            (0x01..=0x05, _) => return Err(Error::Truncated),
+            _ => return Err(Error::UnexpectedMessage),
+        };
+        Ok(Self { device_id, control })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::universal::ALL_CALL;
+
+    #[test]
+    fn master_controls() {
+        let volume = DeviceControl {
+            device_id: ALL_CALL,
+            control: Control::MasterVolume(0x2345),
+        };
+        assert_eq!(
+            volume.to_sysex().unwrap(),
+            [0x7f, 0x7f, 0x04, 0x01, 0x45, 0x46]
+        );
+        assert_eq!(
+            DeviceControl::decode(&volume.to_sysex().unwrap()),
+            Ok(volume)
+        );
+        let packets = volume.to_packets(1).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0][..], [0x3106_7f7f, 0x0401_4546]);
+        assert_eq!(Control::volume(0.5), Control::MasterVolume(0x2000));
+        assert_eq!(Control::volume(2.0), Control::MasterVolume(0x3fff));
+
+        let coarse = DeviceControl {
+            device_id: 0x10,
+            control: Control::coarse_tuning(-2),
+        };
+        assert_eq!(
+            coarse.to_sysex().unwrap(),
+            [0x7f, 0x10, 0x04, 0x04, 0x00, 0x3e]
+        );
+        assert_eq!(coarse.control.cents(), Some(-200.0));
+        assert_eq!(
+            DeviceControl::decode(&[0xf0, 0x7f, 0x10, 0x04, 0x04, 0x00, 0x3e, 0xf7]),
+            Ok(coarse)
+        );
+
+        assert_eq!(Control::balance(0.0), Control::MasterBalance(0x2000));
+        assert_eq!(Control::balance(1.0), Control::MasterBalance(0x3fff));
+        assert_eq!(Control::fine_tuning(-100.0), Control::MasterFineTuning(0));
+        assert_eq!(Control::fine_tuning(50.0).cents(), Some(50.0));
+        assert_eq!(Control::volume(1.0), Control::MasterVolume(0x3fff));
+
+        assert_eq!(
+            DeviceControl::decode(&[0x7f, 0x7f, 0x04, 0x01, 0x00]),
+            Err(Error::Truncated)
+        );
+        assert_eq!(
+            DeviceControl::decode(&[0x7f, 0x7f, 0x04, 0x06, 0x00, 0x00]),
+            Err(Error::UnexpectedMessage)
+        );
+    }
+
+    #[test]
+    fn global_parameter_control() {
+        let gpc = DeviceControl {
+            device_id: ALL_CALL,
+            control: Control::GlobalParameterControl(GlobalParameterControl {
+                slot_path: &[0x01, 0x01],
+                parameter_width: 1,
+                value_width: 1,
+                parameters: &[0x00, 0x04, 0x01, 0x40],
+            }),
+        };
+        let sysex = gpc.to_sysex().unwrap();
+        assert_eq!(
+            sysex,
+            [0x7f, 0x7f, 0x04, 0x05, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x04, 0x01, 0x40]
+        );
+        assert_eq!(DeviceControl::decode(&sysex), Ok(gpc));
+        let Control::GlobalParameterControl(decoded) = gpc.control else {
+            unreachable!()
+        };
+        assert_eq!(decoded.slots().collect::<Vec<_>>(), [[0x01, 0x01]]);
+        assert_eq!(
+            decoded.parameters().collect::<Vec<_>>(),
+            [(&[0x00][..], &[0x04][..]), (&[0x01][..], &[0x40][..])]
+        );
+
+        assert_eq!(
+            DeviceControl::decode(&sysex[..sysex.len() - 1]),
+            Err(Error::InvalidValue)
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! General MIDI messages: turning GM1 and GM2 on and off, and setting up GM2 devices.
+use core::convert::TryFrom;
+
+use super::device_control::{Control, DeviceControl, GlobalParameterControl};
+use super::{parse, write, Error, UniversalMessage, NON_REAL_TIME, REAL_TIME};
+
+/// Sub-ID #1 of General MIDI messages.
+pub const GENERAL_MIDI: u8 = 0x09;
+
+/// Sub-ID #1 of Controller Destination Setting messages.
+pub const CONTROLLER_DESTINATION: u8 = 0x0a;
+
+/// Global Parameter Control slot path of the GM2 reverb.
+pub const REVERB_SLOT: [u8; 2] = [0x01, 0x01];
+
+/// Global Parameter Control slot path of the GM2 chorus.
+pub const CHORUS_SLOT: [u8; 2] = [0x01, 0x02];
+
+/// Resets a device to its General MIDI defaults, or leaves General MIDI mode.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum GeneralMidiMode {
+    /// General MIDI 1 System On.
+    Gm1On = 0x01,
+
+    /// General MIDI System Off.
+    Off = 0x02,
+
+    /// General MIDI 2 System On.
+    Gm2On = 0x03,
+}
+
+/// A General MIDI System On or Off message.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct GeneralMidi {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The mode to switch to.
+    pub mode: GeneralMidiMode,
+}
+
+/// The GM2 reverb types.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ReverbType {
+    /// Small room.
+    SmallRoom = 0,
+    /// Medium room.
+    MediumRoom = 1,
+    /// Large room.
+    LargeRoom = 2,
+    /// Medium hall.
+    MediumHall = 3,
+    /// Large hall, the default.
+    LargeHall = 4,
+    /// Plate.
+    Plate = 8,
+}
+
+/// The GM2 chorus types.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ChorusType {
+    /// Chorus 1.
+    Chorus1 = 0,
+    /// Chorus 2.
+    Chorus2 = 1,
+    /// Chorus 3, the default.
+    Chorus3 = 2,
+    /// Chorus 4.
+    Chorus4 = 3,
+    /// Feedback chorus.
+    FeedbackChorus = 4,
+    /// Flanger.
+    Flanger = 5,
+}
+
+/// A parameter of the GM2 reverb.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ReverbParameter {
+    /// The type of reverb, which also resets the reverb time.
+    Type(ReverbType),
+
+    /// The reverb time, 0-127.
+    Time(u8),
+}
+
+/// A parameter of the GM2 chorus.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ChorusParameter {
+    /// The type of chorus, which also resets the other parameters.
+    Type(ChorusType),
+
+    /// The modulation rate, 0-127.
+    ModRate(u8),
+
+    /// The modulation depth, 0-127.
+    ModDepth(u8),
+
+    /// The feedback, 0-127.
+    Feedback(u8),
+
+    /// The amount sent to the reverb, 0-127.
+    SendToReverb(u8),
+}
+
+/// Sets a parameter of the reverb of a GM2 device, with Global Parameter Control.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Gm2Reverb {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The parameter to set.
+    pub parameter: ReverbParameter,
+}
+
+/// Sets a parameter of the chorus of a GM2 device, with Global Parameter Control.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Gm2Chorus {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The parameter to set.
+    pub parameter: ChorusParameter,
+}
+
+/// Key-based Instrument Control: sets controllers of a single key of a drum kit, eg the
+/// volume or pan of a snare.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct KeyBasedController<'a> {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The channel, 0-15.
+    pub channel: u8,
+
+    /// The key, 0-127.
+    pub key: u8,
+
+    /// Pairs of controller numbers and values, eg [KeyBasedController::VOLUME].
+    pub controllers: &'a [u8],
+}
+
+impl KeyBasedController<'_> {
+    /// Sub-ID #2 of the message.
+    pub const SUB_ID: u8 = 0x01;
+
+    /// Key volume, relative to the channel volume. 0x40 is unchanged.
+    pub const VOLUME: u8 = 0x07;
+    /// Key pan, relative to the channel pan. 0x40 is unchanged.
+    pub const PAN: u8 = 0x0a;
+    /// Reverb send level.
+    pub const REVERB_SEND: u8 = 0x5b;
+    /// Chorus send level.
+    pub const CHORUS_SEND: u8 = 0x5d;
+
+    /// The controller numbers and values.
+    pub fn controllers(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.controllers
+            .chunks_exact(2)
+            .map(|controller| (controller[0], controller[1]))
+    }
+}
+
+impl TryFrom<u8> for GeneralMidiMode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Gm1On),
+            0x02 => Ok(Self::Off),
+            0x03 => Ok(Self::Gm2On),
+            _ => Err(Error::UnexpectedMessage),
+        }
+    }
+}
+
+impl TryFrom<u8> for ReverbType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::SmallRoom),
+            1 => Ok(Self::MediumRoom),
+            2 => Ok(Self::LargeRoom),
+            3 => Ok(Self::MediumHall),
+            4 => Ok(Self::LargeHall),
+            8 => Ok(Self::Plate),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+impl TryFrom<u8> for ChorusType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Chorus1),
+            1 => Ok(Self::Chorus2),
+            2 => Ok(Self::Chorus3),
+            3 => Ok(Self::Chorus4),
+            4 => Ok(Self::FeedbackChorus),
+            5 => Ok(Self::Flanger),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+impl ReverbParameter {
+    fn to_bytes(self) -> [u8; 2] {
+        match self {
+            Self::Type(reverb) => [0, reverb as u8],
+            Self::Time(time) => [1, time],
+        }
+    }
+
+    fn from_bytes(bytes: [u8; 2]) -> Result<Self, Error> {
+        match bytes {
+            [0, reverb] => Ok(Self::Type(ReverbType::try_from(reverb)?)),
+            [1, time] => Ok(Self::Time(time)),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+impl ChorusParameter {
+    fn to_bytes(self) -> [u8; 2] {
+        match self {
+            Self::Type(chorus) => [0, chorus as u8],
+            Self::ModRate(value) => [1, value],
+            Self::ModDepth(value) => [2, value],
+            Self::Feedback(value) => [3, value],
+            Self::SendToReverb(value) => [4, value],
+        }
+    }
+
+    fn from_bytes(bytes: [u8; 2]) -> Result<Self, Error> {
+        match bytes {
+            [0, chorus] => Ok(Self::Type(ChorusType::try_from(chorus)?)),
+            [1, value] => Ok(Self::ModRate(value)),
+            [2, value] => Ok(Self::ModDepth(value)),
+            [3, value] => Ok(Self::Feedback(value)),
+            [4, value] => Ok(Self::SendToReverb(value)),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// Encode a single GM2 effect parameter as Global Parameter Control.
+fn encode_effect(
+    buffer: &mut [u8],
+    device_id: u8,
+    slot: [u8; 2],
+    parameter: [u8; 2],
+) -> Result<usize, Error> {
+    DeviceControl {
+        device_id,
+        control: Control::GlobalParameterControl(GlobalParameterControl {
+            slot_path: &slot,
+            parameter_width: 1,
+            value_width: 1,
+            parameters: &parameter,
+        }),
+    }
+    .encode(buffer)
+}
+
+/// Decode a single GM2 effect parameter from Global Parameter Control.
+fn decode_effect(sysex: &[u8], slot: [u8; 2]) -> Result<(u8, [u8; 2]), Error> {
+    let message = DeviceControl::decode(sysex)?;
+    match message.control {
+        Control::GlobalParameterControl(GlobalParameterControl {
+            slot_path,
+            parameter_width: 1,
+            value_width: 1,
+            parameters: &[parameter, value],
+        }) if slot_path == slot => Ok((message.device_id, [parameter, value])),
+        _ => Err(Error::UnexpectedMessage),
+    }
+}
+
+impl<'a> UniversalMessage<'a> for GeneralMidi {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        write(
+            buffer,
+            &[&[NON_REAL_TIME, self.device_id, GENERAL_MIDI, self.mode as u8]],
+        )
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let sysex = sysex.strip_prefix(&[0xf0]).unwrap_or(sysex);
+        let sub_id = *sysex.get(3).ok_or(Error::Truncated)?;
+        let mode = GeneralMidiMode::try_from(sub_id)?;
+        let (device_id, _) = parse(sysex, [NON_REAL_TIME, GENERAL_MIDI, sub_id])?;
+        Ok(Self { device_id, mode })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for Gm2Reverb {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        encode_effect(
+            buffer,
+            self.device_id,
+            REVERB_SLOT,
+            self.parameter.to_bytes(),
+        )
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (device_id, parameter) = decode_effect(sysex, REVERB_SLOT)?;
+        Ok(Self {
+            device_id,
+            parameter: ReverbParameter::from_bytes(parameter)?,
+        })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for Gm2Chorus {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        encode_effect(
+            buffer,
+            self.device_id,
+            CHORUS_SLOT,
+            self.parameter.to_bytes(),
+        )
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (device_id, parameter) = decode_effect(sysex, CHORUS_SLOT)?;
+        Ok(Self {
+            device_id,
+            parameter: ChorusParameter::from_bytes(parameter)?,
+        })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for KeyBasedController<'a> {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if self.channel > 0x0f || self.controllers.len() % 2 != 0 {
+            return Err(Error::InvalidValue);
+        }
+        write(
+            buffer,
+            &[
+                &[
+                    REAL_TIME,
+                    self.device_id,
+                    CONTROLLER_DESTINATION,
+                    Self::SUB_ID,
+                    self.channel,
+                    self.key,
+                ],
+                self.controllers,
+            ],
+        )
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (device_id, data) = parse(sysex, [REAL_TIME, CONTROLLER_DESTINATION, Self::SUB_ID])?;
+        match data {
+            [channel, key, controllers @ ..] => {
+                if *channel > 0x0f || controllers.len() % 2 != 0 {
+                    return Err(Error::InvalidValue);
+                }
+                Ok(Self {
+                    device_id,
+                    channel: *channel,
+                    key: *key,
+                    controllers,
+                })
+            }
+            _ => Err(Error::Truncated),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::universal::ALL_CALL;
+
+    #[test]
+    fn system_on_off() {
+        let cases = [
+            (GeneralMidiMode::Gm1On, 0x01),
+            (GeneralMidiMode::Off, 0x02),
+            (GeneralMidiMode::Gm2On, 0x03),
+        ];
+        for (mode, sub_id) in cases.iter().copied() {
+            let message = GeneralMidi {
+                device_id: ALL_CALL,
+                mode,
+            };
+            assert_eq!(message.to_sysex().unwrap(), [0x7e, 0x7f, 0x09, sub_id]);
+            assert_eq!(
+                GeneralMidi::decode(&message.to_sysex().unwrap()),
+                Ok(message)
+            );
+        }
+        assert_eq!(
+            GeneralMidi::decode(&[0xf0, 0x7e, 0x7f, 0x09, 0x04, 0xf7]),
+            Err(Error::UnexpectedMessage)
+        );
+        let packets = GeneralMidi {
+            device_id: ALL_CALL,
+            mode: GeneralMidiMode::Gm2On,
+        }
+        .to_packets(0)
+        .unwrap();
+        assert_eq!(packets[0][..], [0x3004_7e7f, 0x0903_0000]);
+    }
+
+    #[test]
+    fn effects() {
+        let reverb = Gm2Reverb {
+            device_id: ALL_CALL,
+            parameter: ReverbParameter::Type(ReverbType::Plate),
+        };
+        let sysex = reverb.to_sysex().unwrap();
+        assert_eq!(
+            sysex,
+            [0x7f, 0x7f, 0x04, 0x05, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x08]
+        );
+        assert_eq!(Gm2Reverb::decode(&sysex), Ok(reverb));
+        assert_eq!(Gm2Chorus::decode(&sysex), Err(Error::UnexpectedMessage));
+
+        let chorus = Gm2Chorus {
+            device_id: 0x10,
+            parameter: ChorusParameter::SendToReverb(0x20),
+        };
+        let sysex = chorus.to_sysex().unwrap();
+        assert_eq!(&sysex[7..], [0x01, 0x02, 0x04, 0x20]);
+        assert_eq!(Gm2Chorus::decode(&sysex), Ok(chorus));
+
+        let mut sysex = sysex;
+        sysex[9] = 0x06;
+        assert_eq!(Gm2Chorus::decode(&sysex), Err(Error::InvalidValue));
+    }
+
+    #[test]
+    fn key_based_controller() {
+        let message = KeyBasedController {
+            device_id: ALL_CALL,
+            channel: 9,
+            key: 38,
+            controllers: &[
+                KeyBasedController::VOLUME,
+                0x50,
+                KeyBasedController::PAN,
+                0x30,
+            ],
+        };
+        let sysex = message.to_sysex().unwrap();
+        assert_eq!(
+            sysex,
+            [0x7f, 0x7f, 0x0a, 0x01, 0x09, 38, 0x07, 0x50, 0x0a, 0x30]
+        );
+        let decoded = KeyBasedController::decode(&sysex).unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(
+            decoded.controllers().collect::<Vec<_>>(),
+            [(0x07, 0x50), (0x0a, 0x30)]
+        );
+        assert_eq!(
+            KeyBasedController::decode(&sysex[..9]),
+            Err(Error::InvalidValue)
+        );
+    }
+}
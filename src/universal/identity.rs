@@ -0,0 +1,277 @@
+//! Identity Request and Reply, the Universal Non-Real Time messages used to identify a device
+//! before MIDI-CI.
+//!
+//! The same [DeviceIdentity] is reported by MIDI-CI [Discovery](crate::ci::DeviceDiscovery)
+//! and by the UMP Stream [Device Identity Notification](crate::message::ump_stream::DeviceIdentityNotification).
+use core::convert::TryInto;
+
+use super::{parse, u14_from_bytes, u14_to_bytes, write, Error, UniversalMessage, NON_REAL_TIME};
+
+/// Sub-ID #1 of General Information messages.
+pub const GENERAL_INFORMATION: u8 = 0x06;
+
+/// Manufacturer, family, model and version of a device.
+///
+/// Every field is sent as 7-bit bytes, least significant first. The manufacturer is a SysEx
+/// ID in its 3 byte form, see [DeviceIdentity::manufacturer_id].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// The SysEx ID of the manufacturer.
+    pub manufacturer: [u8; 3],
+
+    /// The device family.
+    pub family: [u8; 2],
+
+    /// The model within the family.
+    pub model: [u8; 2],
+
+    /// The software revision level, in a format defined by the manufacturer.
+    pub version: [u8; 4],
+}
+
+/// A manufacturer SysEx ID, created from its 1 byte form or its 3 byte form starting with `00`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ManufacturerId(pub [u8; 3]);
+
+impl From<[u8; 1]> for ManufacturerId {
+    fn from(id: [u8; 1]) -> Self {
+        Self([id[0], 0, 0])
+    }
+}
+
+impl From<[u8; 3]> for ManufacturerId {
+    fn from(id: [u8; 3]) -> Self {
+        Self(id)
+    }
+}
+
+impl DeviceIdentity {
+    /// Create an identity from a manufacturer SysEx ID, eg `[0x43]` or `[0x00, 0x21, 0x09]`.
+    pub fn new(manufacturer: impl Into<ManufacturerId>) -> Self {
+        Self::default().with_manufacturer_id(manufacturer)
+    }
+
+    /// Set the manufacturer SysEx ID, eg `[0x43]` or `[0x00, 0x21, 0x09]`.
+    pub fn with_manufacturer_id(mut self, id: impl Into<ManufacturerId>) -> Self {
+        self.manufacturer = id.into().0;
+        self
+    }
+
+    /// Set the family, as a 14 bit number.
+    pub fn with_family(mut self, family: u16) -> Self {
+        self.family = u14_to_bytes(family);
+        self
+    }
+
+    /// Set the model, as a 14 bit number.
+    pub fn with_model(mut self, model: u16) -> Self {
+        self.model = u14_to_bytes(model);
+        self
+    }
+
+    /// Set the version.
+    pub fn with_version(mut self, version: [u8; 4]) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The manufacturer SysEx ID in its short form: 1 byte, or 3 bytes starting with `00`
+    /// for extended IDs.
+    pub fn manufacturer_id(&self) -> &[u8] {
+        if self.manufacturer[0] == 0 {
+            &self.manufacturer
+        } else {
+            &self.manufacturer[..1]
+        }
+    }
+
+    /// The family as a 14 bit number.
+    pub fn family_code(&self) -> u16 {
+        u14_from_bytes(self.family)
+    }
+
+    /// The model as a 14 bit number.
+    pub fn model_code(&self) -> u16 {
+        u14_from_bytes(self.model)
+    }
+
+    /// The reply to an Identity Request.
+    pub fn reply_to(&self, request: &IdentityRequest) -> IdentityReply {
+        IdentityReply {
+            device_id: request.device_id,
+            identity: *self,
+        }
+    }
+}
+
+/// Asks a device to send an [IdentityReply].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct IdentityRequest {
+    /// The device asked, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+}
+
+/// Reports the identity of a device.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct IdentityReply {
+    /// The device replying.
+    pub device_id: u8,
+
+    /// The identity of the device.
+    pub identity: DeviceIdentity,
+}
+
+impl IdentityRequest {
+    /// Sub-ID #2 of the message.
+    pub const SUB_ID: u8 = 0x01;
+}
+
+impl IdentityReply {
+    /// Sub-ID #2 of the message.
+    pub const SUB_ID: u8 = 0x02;
+
+    /// The longest encoded reply, with an extended manufacturer ID.
+    pub const MAX_LEN: usize = 15;
+}
+
+impl<'a> UniversalMessage<'a> for IdentityRequest {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        write(
+            buffer,
+            &[&[
+                NON_REAL_TIME,
+                self.device_id,
+                GENERAL_INFORMATION,
+                Self::SUB_ID,
+            ]],
+        )
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (device_id, _) = parse(sysex, [NON_REAL_TIME, GENERAL_INFORMATION, Self::SUB_ID])?;
+        Ok(Self { device_id })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for IdentityReply {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let identity = &self.identity;
+        write(
+            buffer,
+            &[
+                &[
+                    NON_REAL_TIME,
+                    self.device_id,
+                    GENERAL_INFORMATION,
+                    Self::SUB_ID,
+                ],
+                identity.manufacturer_id(),
+                &identity.family,
+                &identity.model,
+                &identity.version,
+            ],
+        )
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (device_id, data) = parse(sysex, [NON_REAL_TIME, GENERAL_INFORMATION, Self::SUB_ID])?;
+        let (manufacturer, data) = match data {
+            [0, a, b, data @ ..] => ([0, *a, *b], data),
+            [id, data @ ..] => ([*id, 0, 0], data),
+            [] => return Err(Error::Truncated),
+        };
+        if data.len() < 8 {
+            return Err(Error::Truncated);
+        }
+        Ok(Self {
+            device_id,
+            identity: DeviceIdentity {
+                manufacturer,
+                family: data[0..2].try_into().unwrap(),
+                model: data[2..4].try_into().unwrap(),
+                version: data[4..8].try_into().unwrap(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ci::DeviceDiscovery;
+    use crate::message::ump_stream::{DeviceIdentityNotification, UmpStream};
+    use crate::muid::MUID;
+
+    #[test]
+    fn identity_request() {
+        let request = IdentityRequest { device_id: 0x7f };
+        assert_eq!(request.to_sysex().unwrap(), [0x7e, 0x7f, 0x06, 0x01]);
+        assert_eq!(
+            IdentityRequest::decode(&[0xf0, 0x7e, 0x10, 0x06, 0x01, 0xf7]),
+            Ok(IdentityRequest { device_id: 0x10 })
+        );
+        assert_eq!(
+            IdentityRequest::decode(&[0x7e, 0x10, 0x06, 0x02]),
+            Err(Error::UnexpectedMessage)
+        );
+        assert_eq!(IdentityRequest::decode(&[0x7e]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn identity_reply() {
+        let identity = DeviceIdentity::new([0x43])
+            .with_family(0x0123)
+            .with_model(0x45)
+            .with_version([1, 2, 3, 4]);
+        let reply = identity.reply_to(&IdentityRequest { device_id: 0x7f });
+        assert_eq!(
+            reply.to_sysex().unwrap(),
+            [0x7e, 0x7f, 0x06, 0x02, 0x43, 0x23, 0x02, 0x45, 0x00, 1, 2, 3, 4]
+        );
+        assert_eq!(IdentityReply::decode(&reply.to_sysex().unwrap()), Ok(reply));
+        assert_eq!(reply.identity.family_code(), 0x0123);
+
+        let extended = IdentityReply {
+            device_id: 0,
+            identity: identity.with_manufacturer_id([0x00, 0x21, 0x09]),
+        };
+        let sysex = extended.to_sysex().unwrap();
+        assert_eq!(sysex.len(), IdentityReply::MAX_LEN);
+        assert_eq!(&sysex[4..7], [0x00, 0x21, 0x09]);
+        assert_eq!(IdentityReply::decode(&sysex), Ok(extended));
+        assert_eq!(
+            IdentityReply::decode(&sysex[..sysex.len() - 1]),
+            Err(Error::Truncated)
+        );
+    }
+
+    #[test]
+    fn invalid_bytes_are_errors() {
+        let reply = DeviceIdentity::new([0x80]).reply_to(&IdentityRequest { device_id: 0 });
+        assert_eq!(reply.to_sysex(), Err(Error::InvalidByte(0x80)));
+        let reply = DeviceIdentity::new([0x43])
+            .with_version([1, 2, 3, 0xff])
+            .reply_to(&IdentityRequest { device_id: 0 });
+        assert_eq!(reply.encode(&mut [0; 32]), Err(Error::InvalidByte(0xff)));
+        assert_eq!(reply.to_packets(0), Err(Error::InvalidByte(0xff)));
+    }
+
+    #[test]
+    fn shared_with_discovery_and_ump_stream() {
+        let identity = DeviceIdentity::new([0x00, 0x21, 0x09])
+            .with_family(1)
+            .with_model(2)
+            .with_version([0x10, 0x20, 0x30, 0x01]);
+        let discovery = DeviceDiscovery::new(MUID::from_wire([1, 0, 0, 0])).with_identity(identity);
+        assert_eq!(discovery.identity(), identity);
+
+        let message = UmpStream::device_identity_notification(identity.into());
+        assert_eq!(
+            message[..],
+            [0xF002_0000, 0x0000_2109, 0x0100_0200, 0x1020_3001]
+        );
+        let notification = message.get_device_identity_notification();
+        assert_eq!(notification, DeviceIdentityNotification::from(identity));
+        assert_eq!(DeviceIdentity::from(notification), identity);
+    }
+}
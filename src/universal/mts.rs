@@ -0,0 +1,587 @@
+//! The MIDI Tuning Standard: messages retuning the notes of a device, see
+//! [TuningTable](crate::tuning::TuningTable).
+//!
+//! Frequencies are sent as a semitone and a 14 bit fraction of a semitone, see
+//! [MtsFrequency]. Tunings are numbered programs, optionally in a bank.
+use core::convert::TryInto;
+
+use super::{parse, u14_from_bytes, u14_to_bytes, write, Error, UniversalMessage};
+use super::{NON_REAL_TIME, REAL_TIME};
+
+/// Sub-ID #1 of MIDI Tuning Standard messages.
+pub const MIDI_TUNING: u8 = 0x08;
+
+/// The length of the name of a tuning.
+pub const NAME_LEN: usize = 16;
+
+/// A frequency: a note number and a 14 bit fraction of a semitone above it.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct MtsFrequency {
+    /// The semitone, 69 is A440 in equal temperament.
+    pub semitone: u8,
+
+    /// The fraction of a semitone, in units of 100/16384 cents.
+    pub fraction: u16,
+}
+
+impl MtsFrequency {
+    /// Leaves the tuning of a note unchanged.
+    pub const NO_CHANGE: Self = Self {
+        semitone: 0x7f,
+        fraction: 0x3fff,
+    };
+
+    /// The frequency of a pitch in semitones, eg 69.5 for a quarter tone above A440,
+    /// clamped to the range of notes.
+    pub fn from_semitones(semitones: f64) -> Self {
+        let max = 127.0 + 16382.0 / 16384.0;
+        let scaled = (semitones.clamp(0.0, max) * 16384.0).round() as u32;
+        Self {
+            semitone: (scaled >> 14) as u8,
+            fraction: (scaled & 0x3fff) as u16,
+        }
+    }
+
+    /// The pitch in semitones, or `None` for [MtsFrequency::NO_CHANGE].
+    pub fn semitones(&self) -> Option<f64> {
+        if *self == Self::NO_CHANGE {
+            None
+        } else {
+            Some(self.semitone as f64 + self.fraction as f64 / 16384.0)
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 3] {
+        let [lsb, msb] = u14_to_bytes(self.fraction);
+        [self.semitone, msb, lsb]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            semitone: bytes[0],
+            fraction: u14_from_bytes([bytes[2], bytes[1]]),
+        }
+    }
+}
+
+/// Asks a device to send a [BulkDump] of one of its tunings.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct BulkDumpRequest {
+    /// The device asked, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The bank of the tuning, if any.
+    pub bank: Option<u8>,
+
+    /// The tuning program.
+    pub program: u8,
+}
+
+/// The frequencies of all 128 notes of a tuning.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct BulkDump {
+    /// The device sending the tuning, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// The bank of the tuning, if any.
+    pub bank: Option<u8>,
+
+    /// The tuning program.
+    pub program: u8,
+
+    /// The name of the tuning, in ASCII.
+    pub name: [u8; NAME_LEN],
+
+    /// The frequency of each note.
+    pub frequencies: [MtsFrequency; 128],
+}
+
+/// Changes the frequencies of some notes of a tuning.
+///
+/// Sent as a Real Time message, notes that are playing are retuned immediately. Non-real time
+/// changes only apply to the next notes, and can only be sent with a bank.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SingleNoteTuning {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// Send the message as a Real Time message.
+    pub real_time: bool,
+
+    /// The bank of the tuning, if any.
+    pub bank: Option<u8>,
+
+    /// The tuning program.
+    pub program: u8,
+
+    /// The notes and their new frequencies, at most 127.
+    pub changes: Vec<(u8, MtsFrequency)>,
+}
+
+/// The offsets in cents from equal temperament of the 12 notes of an octave, from C to B.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ScaleOffsets {
+    /// Offsets from -64 (0) to +63 cents (0x7f), centered at 0x40.
+    OneByte([u8; 12]),
+
+    /// 14 bit offsets from -100 (0) to +100 cents (0x3fff), centered at 0x2000.
+    TwoByte([u16; 12]),
+}
+
+impl ScaleOffsets {
+    /// The offsets of the 1 byte form, rounded to cents.
+    pub fn one_byte(cents: [f64; 12]) -> Self {
+        Self::OneByte(cents.map(|cents| (cents.round().clamp(-64.0, 63.0) + 64.0) as u8))
+    }
+
+    /// The offsets of the 2 byte form.
+    pub fn two_byte(cents: [f64; 12]) -> Self {
+        Self::TwoByte(
+            cents.map(|cents| (8192.0 + cents * 81.92).round().clamp(0.0, 16383.0) as u16),
+        )
+    }
+
+    /// The offsets in cents.
+    pub fn cents(&self) -> [f64; 12] {
+        match self {
+            Self::OneByte(offsets) => offsets.map(|offset| offset as f64 - 64.0),
+            Self::TwoByte(offsets) => offsets.map(|offset| (offset as f64 - 8192.0) / 81.92),
+        }
+    }
+}
+
+/// Retunes every octave of some channels.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ScaleOctaveTuning {
+    /// The device addressed, or [ALL_CALL](super::ALL_CALL).
+    pub device_id: u8,
+
+    /// Send the message as a Real Time message, retuning notes that are playing.
+    pub real_time: bool,
+
+    /// A bitmap of the channels to retune, bit 0 for channel 0.
+    pub channels: u16,
+
+    /// The offsets of each note of the octave.
+    pub offsets: ScaleOffsets,
+}
+
+/// Any MIDI Tuning Standard message.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum MtsMessage {
+    /// Bulk Tuning Dump Request.
+    BulkDumpRequest(BulkDumpRequest),
+
+    /// Bulk Tuning Dump.
+    BulkDump(Box<BulkDump>),
+
+    /// Single Note Tuning Change.
+    SingleNoteTuning(SingleNoteTuning),
+
+    /// Scale/Octave Tuning.
+    ScaleOctaveTuning(ScaleOctaveTuning),
+}
+
+/// The MTS message types, by sub-ID #2.
+const BULK_DUMP_REQUEST: u8 = 0x00;
+const BULK_DUMP: u8 = 0x01;
+const SINGLE_NOTE: u8 = 0x02;
+const BULK_DUMP_REQUEST_BANK: u8 = 0x03;
+const BULK_DUMP_BANK: u8 = 0x04;
+const SINGLE_NOTE_BANK: u8 = 0x07;
+const SCALE_OCTAVE_1: u8 = 0x08;
+const SCALE_OCTAVE_2: u8 = 0x09;
+
+/// Strip the optional delimiters of a message, returning its Universal SysEx ID and sub-ID
+/// #2, which both vary between MTS messages.
+fn ids(sysex: &[u8]) -> Result<(u8, u8), Error> {
+    let sysex = sysex.strip_prefix(&[0xf0]).unwrap_or(sysex);
+    match sysex {
+        [universal, _, MIDI_TUNING, sub_id, ..] => Ok((*universal, *sub_id)),
+        [_, _, _, _, ..] => Err(Error::UnexpectedMessage),
+        _ => Err(Error::Truncated),
+    }
+}
+
+fn universal_id(real_time: bool) -> u8 {
+    if real_time {
+        REAL_TIME
+    } else {
+        NON_REAL_TIME
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |checksum, byte| checksum ^ byte) & 0x7f
+}
+
+fn channel_bytes(channels: u16) -> [u8; 3] {
+    [
+        ((channels >> 14) & 0x03) as u8,
+        ((channels >> 7) & 0x7f) as u8,
+        (channels & 0x7f) as u8,
+    ]
+}
+
+impl<'a> UniversalMessage<'a> for BulkDumpRequest {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let header = [NON_REAL_TIME, self.device_id, MIDI_TUNING];
+        match self.bank {
+            None => write(buffer, &[&header, &[BULK_DUMP_REQUEST, self.program]]),
+            Some(bank) => write(
+                buffer,
+                &[&header, &[BULK_DUMP_REQUEST_BANK, bank, self.program]],
+            ),
+        }
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (_, sub_id) = ids(sysex)?;
+        let (device_id, data) = parse(sysex, [NON_REAL_TIME, MIDI_TUNING, sub_id])?;
+        let (bank, program) = match (sub_id, data) {
+            (BULK_DUMP_REQUEST, [program, ..]) => (None, *program),
+            (BULK_DUMP_REQUEST_BANK, [bank, program, ..]) => (Some(*bank), *program),
+            (BULK_DUMP_REQUEST | BULK_DUMP_REQUEST_BANK, _) => return Err(Error::Truncated),
+            _ => return Err(Error::UnexpectedMessage),
+        };
+        Ok(Self {
+            device_id,
+            bank,
+            program,
+        })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for BulkDump {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let header = [NON_REAL_TIME, self.device_id, MIDI_TUNING];
+        let mut frequencies = [0; 128 * 3];
+        for (bytes, frequency) in frequencies.chunks_exact_mut(3).zip(&self.frequencies) {
+            bytes.copy_from_slice(&frequency.to_bytes());
+        }
+        let bank = [BULK_DUMP_BANK, self.bank.unwrap_or(0), self.program];
+        let program: &[u8] = match self.bank {
+            None => &[BULK_DUMP, self.program],
+            Some(_) => &bank,
+        };
+        let len = write(buffer, &[&header, program, &self.name, &frequencies, &[0]])?;
+        buffer[len - 1] = checksum(&buffer[..len - 1]);
+        Ok(len)
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (_, sub_id) = ids(sysex)?;
+        let (device_id, data) = parse(sysex, [NON_REAL_TIME, MIDI_TUNING, sub_id])?;
+        let (bank, data) = match (sub_id, data) {
+            (BULK_DUMP, data) => (None, data),
+            (BULK_DUMP_BANK, [bank, data @ ..]) => (Some(*bank), data),
+            (BULK_DUMP_BANK, _) => return Err(Error::Truncated),
+            _ => return Err(Error::UnexpectedMessage),
+        };
+        if data.len() < 1 + NAME_LEN + 128 * 3 + 1 {
+            return Err(Error::Truncated);
+        }
+        // The checksum covers every byte from the Universal SysEx ID to the last frequency.
+        let sysex = sysex.strip_prefix(&[0xf0]).unwrap_or(sysex);
+        let sysex = sysex.strip_suffix(&[0xf7]).unwrap_or(sysex);
+        let checksum_offset = sysex.len() - data.len() + 1 + NAME_LEN + 128 * 3;
+        if checksum(&sysex[..checksum_offset]) != sysex[checksum_offset] {
+            return Err(Error::InvalidChecksum);
+        }
+        let mut frequencies = [MtsFrequency::NO_CHANGE; 128];
+        let bytes = &data[1 + NAME_LEN..1 + NAME_LEN + 128 * 3];
+        for (frequency, bytes) in frequencies.iter_mut().zip(bytes.chunks_exact(3)) {
+            *frequency = MtsFrequency::from_bytes(bytes);
+        }
+        Ok(Self {
+            device_id,
+            bank,
+            program: data[0],
+            name: data[1..1 + NAME_LEN].try_into().unwrap(),
+            frequencies,
+        })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for SingleNoteTuning {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if self.changes.len() > 0x7f || (self.bank.is_none() && !self.real_time) {
+            return Err(Error::InvalidValue);
+        }
+        let universal = universal_id(self.real_time);
+        let header = [universal, self.device_id, MIDI_TUNING];
+        let mut changes = Vec::with_capacity(self.changes.len() * 4);
+        for (key, frequency) in &self.changes {
+            changes.push(*key);
+            changes.extend_from_slice(&frequency.to_bytes());
+        }
+        let count = self.changes.len() as u8;
+        match self.bank {
+            None => write(
+                buffer,
+                &[&header, &[SINGLE_NOTE, self.program, count], &changes],
+            ),
+            Some(bank) => write(
+                buffer,
+                &[
+                    &header,
+                    &[SINGLE_NOTE_BANK, bank, self.program, count],
+                    &changes,
+                ],
+            ),
+        }
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (universal, sub_id) = ids(sysex)?;
+        let (device_id, data) = parse(sysex, [universal, MIDI_TUNING, sub_id])?;
+        let (bank, data) = match (universal, sub_id, data) {
+            (REAL_TIME, SINGLE_NOTE, data) => (None, data),
+            (REAL_TIME | NON_REAL_TIME, SINGLE_NOTE_BANK, [bank, data @ ..]) => (Some(*bank), data),
+            (_, SINGLE_NOTE_BANK, _) => return Err(Error::Truncated),
+            _ => return Err(Error::UnexpectedMessage),
+        };
+        let (program, count, data) = match data {
+            [program, count, data @ ..] => (*program, *count as usize, data),
+            _ => return Err(Error::Truncated),
+        };
+        if data.len() < count * 4 {
+            return Err(Error::Truncated);
+        }
+        let changes = data[..count * 4]
+            .chunks_exact(4)
+            .map(|change| (change[0], MtsFrequency::from_bytes(&change[1..])))
+            .collect();
+        Ok(Self {
+            device_id,
+            real_time: universal == REAL_TIME,
+            bank,
+            program,
+            changes,
+        })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for ScaleOctaveTuning {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let universal = universal_id(self.real_time);
+        let header = [universal, self.device_id, MIDI_TUNING];
+        let channels = channel_bytes(self.channels);
+        match self.offsets {
+            ScaleOffsets::OneByte(offsets) => {
+                write(buffer, &[&header, &[SCALE_OCTAVE_1], &channels, &offsets])
+            }
+            ScaleOffsets::TwoByte(offsets) => {
+                let mut bytes = [0; 24];
+                for (bytes, offset) in bytes.chunks_exact_mut(2).zip(offsets.iter()) {
+                    let [lsb, msb] = u14_to_bytes(*offset);
+                    bytes.copy_from_slice(&[msb, lsb]);
+                }
+                write(buffer, &[&header, &[SCALE_OCTAVE_2], &channels, &bytes])
+            }
+        }
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let (universal, sub_id) = ids(sysex)?;
+        if universal != REAL_TIME && universal != NON_REAL_TIME {
+            return Err(Error::UnexpectedMessage);
+        }
+        let (device_id, data) = parse(sysex, [universal, MIDI_TUNING, sub_id])?;
+        let (channels, data) = match data {
+            [ff, gg, hh, data @ ..] => (
+                (*ff as u16 & 0x03) << 14 | (*gg as u16) << 7 | *hh as u16,
+                data,
+            ),
+            _ => return Err(Error::Truncated),
+        };
+        let offsets = match sub_id {
+            SCALE_OCTAVE_1 if data.len() >= 12 => {
+                ScaleOffsets::OneByte(data[..12].try_into().unwrap())
+            }
+            SCALE_OCTAVE_2 if data.len() >= 24 => {
+                let mut offsets = [0; 12];
+                for (offset, bytes) in offsets.iter_mut().zip(data.chunks_exact(2)) {
+                    *offset = u14_from_bytes([bytes[1], bytes[0]]);
+                }
+                ScaleOffsets::TwoByte(offsets)
+            }
+            SCALE_OCTAVE_1 | SCALE_OCTAVE_2 => return Err(Error::Truncated),
+            _ => return Err(Error::UnexpectedMessage),
+        };
+        Ok(Self {
+            device_id,
+            real_time: universal == REAL_TIME,
+            channels,
+            offsets,
+        })
+    }
+}
+
+impl<'a> UniversalMessage<'a> for MtsMessage {
+    fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        match self {
+            Self::BulkDumpRequest(message) => message.encode(buffer),
+            Self::BulkDump(message) => message.encode(buffer),
+            Self::SingleNoteTuning(message) => message.encode(buffer),
+            Self::ScaleOctaveTuning(message) => message.encode(buffer),
+        }
+    }
+
+    fn decode(sysex: &'a [u8]) -> Result<Self, Error> {
+        let message = match ids(sysex)?.1 {
+            BULK_DUMP_REQUEST | BULK_DUMP_REQUEST_BANK => {
+                Self::BulkDumpRequest(BulkDumpRequest::decode(sysex)?)
+            }
+            BULK_DUMP | BULK_DUMP_BANK => Self::BulkDump(Box::new(BulkDump::decode(sysex)?)),
+            SINGLE_NOTE | SINGLE_NOTE_BANK => {
+                Self::SingleNoteTuning(SingleNoteTuning::decode(sysex)?)
+            }
+            SCALE_OCTAVE_1 | SCALE_OCTAVE_2 => {
+                Self::ScaleOctaveTuning(ScaleOctaveTuning::decode(sysex)?)
+            }
+            _ => return Err(Error::UnexpectedMessage),
+        };
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::universal::ALL_CALL;
+
+    #[test]
+    fn frequency() {
+        // Examples from the MIDI Tuning Standard.
+        let a440 = MtsFrequency::from_semitones(69.0);
+        assert_eq!(a440.to_bytes(), [0x45, 0x00, 0x00]);
+        let frequency = MtsFrequency::from_bytes(&[0x3c, 0x00, 0x01]);
+        assert_eq!(frequency.semitones(), Some(60.0 + 1.0 / 16384.0));
+        assert_eq!(
+            MtsFrequency::from_semitones(200.0).to_bytes(),
+            [0x7f, 0x7f, 0x7e]
+        );
+        assert_eq!(MtsFrequency::NO_CHANGE.semitones(), None);
+    }
+
+    #[test]
+    fn bulk_dump() {
+        let request = BulkDumpRequest {
+            device_id: ALL_CALL,
+            bank: Some(1),
+            program: 2,
+        };
+        assert_eq!(
+            request.to_sysex().unwrap(),
+            [0x7e, 0x7f, 0x08, 0x03, 0x01, 0x02]
+        );
+        assert_eq!(
+            BulkDumpRequest::decode(&request.to_sysex().unwrap()),
+            Ok(request)
+        );
+
+        let mut frequencies = [MtsFrequency::NO_CHANGE; 128];
+        for (note, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = MtsFrequency::from_semitones(note as f64 + 0.5);
+        }
+        let dump = BulkDump {
+            device_id: 0,
+            bank: None,
+            program: 5,
+            name: *b"Quarter tones   ",
+            frequencies,
+        };
+        let sysex = dump.to_sysex().unwrap();
+        assert_eq!(sysex.len(), 4 + 1 + NAME_LEN + 384 + 1);
+        assert_eq!(&sysex[..5], [0x7e, 0x00, 0x08, 0x01, 0x05]);
+        assert_eq!(&sysex[21..24], [0x00, 0x40, 0x00]);
+        assert_eq!(BulkDump::decode(&sysex), Ok(dump));
+
+        let mut corrupted = sysex.clone();
+        corrupted[30] ^= 1;
+        assert_eq!(BulkDump::decode(&corrupted), Err(Error::InvalidChecksum));
+
+        let banked = BulkDump {
+            bank: Some(3),
+            ..dump
+        };
+        let sysex = banked.to_sysex().unwrap();
+        assert_eq!(&sysex[3..6], [0x04, 0x03, 0x05]);
+        assert_eq!(
+            MtsMessage::decode(&sysex),
+            Ok(MtsMessage::BulkDump(Box::new(banked)))
+        );
+    }
+
+    #[test]
+    fn single_note_tuning() {
+        let change = SingleNoteTuning {
+            device_id: ALL_CALL,
+            real_time: true,
+            bank: None,
+            program: 0,
+            changes: vec![(69, MtsFrequency::from_semitones(69.5))],
+        };
+        let sysex = change.to_sysex().unwrap();
+        assert_eq!(
+            sysex,
+            [0x7f, 0x7f, 0x08, 0x02, 0x00, 0x01, 69, 0x45, 0x40, 0x00]
+        );
+        assert_eq!(SingleNoteTuning::decode(&sysex), Ok(change.clone()));
+
+        let banked = SingleNoteTuning {
+            real_time: false,
+            bank: Some(2),
+            ..change.clone()
+        };
+        let sysex = banked.to_sysex().unwrap();
+        assert_eq!(&sysex[..7], [0x7e, 0x7f, 0x08, 0x07, 0x02, 0x00, 0x01]);
+        assert_eq!(
+            MtsMessage::decode(&sysex),
+            Ok(MtsMessage::SingleNoteTuning(banked))
+        );
+
+        let invalid = SingleNoteTuning {
+            real_time: false,
+            ..change
+        };
+        assert_eq!(invalid.encode(&mut [0; 32]), Err(Error::InvalidValue));
+        assert_eq!(
+            SingleNoteTuning::decode(&sysex[..sysex.len() - 1]),
+            Err(Error::Truncated)
+        );
+    }
+
+    #[test]
+    fn scale_octave_tuning() {
+        let mut cents = [0.0; 12];
+        cents[4] = -13.7;
+        cents[11] = 50.0;
+        let one_byte = ScaleOctaveTuning {
+            device_id: ALL_CALL,
+            real_time: false,
+            channels: 0b1000_0000_1000_0001,
+            offsets: ScaleOffsets::one_byte(cents),
+        };
+        let sysex = one_byte.to_sysex().unwrap();
+        assert_eq!(&sysex[..7], [0x7e, 0x7f, 0x08, 0x08, 0x02, 0x01, 0x01]);
+        assert_eq!(sysex[7 + 4], 0x40 - 14);
+        assert_eq!(ScaleOctaveTuning::decode(&sysex), Ok(one_byte));
+        assert_eq!(one_byte.offsets.cents()[11], 50.0);
+
+        let two_byte = ScaleOctaveTuning {
+            real_time: true,
+            offsets: ScaleOffsets::two_byte(cents),
+            ..one_byte
+        };
+        let sysex = two_byte.to_sysex().unwrap();
+        assert_eq!(sysex.len(), 7 + 24);
+        assert_eq!(&sysex[7..9], [0x40, 0x00]);
+        assert_eq!(
+            MtsMessage::decode(&sysex),
+            Ok(MtsMessage::ScaleOctaveTuning(two_byte))
+        );
+        assert!((two_byte.offsets.cents()[4] + 13.7).abs() < 0.01);
+    }
+}